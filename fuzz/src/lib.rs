@@ -0,0 +1,60 @@
+//! The code exercised by the fuzz targets, also used by `tests/test_fuzz_regressions.rs`
+//! to replay the regression corpus.
+//!
+//! None of these functions may panic, whatever the input.
+
+use evtx::binxml::deserializer::BinXmlDeserializer;
+use evtx::{EvtxChunkData, EvtxParser, ParserSettings};
+use std::io::Cursor;
+use std::sync::Arc;
+
+fn settings() -> ParserSettings {
+    ParserSettings::new().num_threads(1)
+}
+
+/// Parses `data` as an EVTX file, serializing every record to XML and JSON.
+pub fn parse_file(data: &[u8]) {
+    let parser = match EvtxParser::from_buffer(data.to_vec()) {
+        Ok(parser) => parser,
+        Err(_) => return,
+    };
+    let mut parser = parser.with_configuration(settings());
+
+    for record in parser.records() {
+        let _ = record;
+    }
+
+    for record in parser.records_json() {
+        let _ = record;
+    }
+}
+
+/// Parses `data` as a single chunk (without validating its checksums),
+/// serializing every record to XML and JSON.
+pub fn parse_chunk(data: &[u8]) {
+    let mut chunk_data = match EvtxChunkData::new(data.to_vec(), false) {
+        Ok(chunk_data) => chunk_data,
+        Err(_) => return,
+    };
+
+    let mut chunk = match chunk_data.parse(Arc::new(settings())) {
+        Ok(chunk) => chunk,
+        Err(_) => return,
+    };
+
+    for record in chunk.iter().flatten() {
+        let _ = record.clone().into_xml();
+        let _ = record.into_json();
+    }
+}
+
+/// Deserializes `data` as a binary XML fragment, outside of any chunk.
+pub fn parse_binxml(data: &[u8]) {
+    let _ = BinXmlDeserializer::read_binxml_fragment(
+        &mut Cursor::new(data),
+        None,
+        None,
+        false,
+        settings().get_ansi_codec(),
+    );
+}
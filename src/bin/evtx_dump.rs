@@ -8,13 +8,18 @@ use indoc::indoc;
 use encoding::all::encodings;
 use encoding::types::Encoding;
 use evtx::err::Result as EvtxResult;
-use evtx::{EvtxParser, ParserSettings, SerializedEvtxRecord};
+use evtx::{
+    CarvedRecord, CsvOutput, EvtxCarver, EvtxParser, EvtxQuery, EvtxStreamParser, JsonFormat,
+    ParserSettings, RecordOrder, SerializedEvtxRecord,
+};
 use log::Level;
 use std::fs::{self, File};
 use std::io::{self, BufWriter, Write};
 use std::ops::RangeInclusive;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
 
 #[cfg(all(feature = "fast-alloc", not(windows)))]
 use jemallocator::Jemalloc;
@@ -27,10 +32,34 @@ static GLOBAL: Jemalloc = Jemalloc;
 #[global_allocator]
 static ALLOC: rpmalloc::RpMalloc = rpmalloc::RpMalloc;
 
+/// The columns used by `-o csv` and `-o tsv` when none are given.
+const DEFAULT_CSV_COLUMNS: [&str; 5] = [
+    "record_id",
+    "timestamp",
+    "System/Provider/@Name",
+    "System/EventID",
+    "System/Computer",
+];
+
+/// The number of records in every row group of `-o parquet`.
+#[cfg(feature = "arrow")]
+const PARQUET_BATCH_SIZE: usize = 8192;
+
+/// How often the input is checked for new records when following it.
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Passed as the input to read the evtx file from stdin.
+const STDIN_INPUT: &str = "-";
+
 #[derive(Copy, Clone, PartialOrd, PartialEq, Eq)]
 pub enum EvtxOutputFormat {
     JSON,
     XML,
+    CSV,
+    #[cfg(feature = "arrow")]
+    Parquet,
+    #[cfg(feature = "sqlite")]
+    SQLite,
 }
 
 struct EvtxDump {
@@ -38,9 +67,18 @@ struct EvtxDump {
     input: PathBuf,
     show_record_number: bool,
     output_format: EvtxOutputFormat,
-    output: Box<dyn Write>,
+    /// The columns of `-o csv` and `-o tsv`.
+    csv: Option<CsvOutput>,
+    output: Box<dyn Write + Send>,
+    /// The database written by `-o sqlite`, records are added to it instead of overwriting it.
+    #[cfg(feature = "sqlite")]
+    database: Option<PathBuf>,
     verbosity_level: Option<Level>,
     stop_after_error: bool,
+    /// When set, new records will be dumped as they are appended to the input.
+    follow: bool,
+    /// When set, input is treated as a raw image, and records will be carved from it.
+    carve: bool,
     /// When set, only the specified events (offseted reltaive to file) will be outputted.
     ranges: Option<Ranges>,
 }
@@ -60,15 +98,38 @@ impl EvtxDump {
         {
             "xml" => EvtxOutputFormat::XML,
             "json" | "jsonl" => EvtxOutputFormat::JSON,
+            "csv" | "tsv" => EvtxOutputFormat::CSV,
+            #[cfg(feature = "arrow")]
+            "parquet" => EvtxOutputFormat::Parquet,
+            #[cfg(feature = "sqlite")]
+            "sqlite" => EvtxOutputFormat::SQLite,
             _ => EvtxOutputFormat::XML,
         };
 
+        let csv = if output_format == EvtxOutputFormat::CSV {
+            let csv = match matches.get_many::<String>("column") {
+                Some(columns) => CsvOutput::new(columns),
+                None => CsvOutput::new(DEFAULT_CSV_COLUMNS),
+            }
+            .context("Failed to parse columns")?;
+
+            match matches
+                .get_one::<String>("output-format")
+                .map(String::as_str)
+            {
+                Some("tsv") => Some(csv.delimiter('\t')),
+                _ => Some(csv),
+            }
+        } else {
+            None
+        };
+
         let no_indent = match (
             matches.get_flag("no-indent"),
             matches.get_one::<String>("output-format"),
         ) {
-            // "jsonl" --> --no-indent
-            (false, Some(fmt)) => fmt == "jsonl",
+            // "jsonl" and "sqlite" --> --no-indent
+            (false, Some(fmt)) => matches!(fmt.as_str(), "jsonl" | "sqlite"),
             (true, Some(fmt)) => {
                 if fmt == "jsonl" {
                     eprintln!("no need to pass both `--no-indent` and `-o jsonl`");
@@ -82,12 +143,18 @@ impl EvtxDump {
 
         let separate_json_attrib_flag = matches.get_flag("separate-json-attributes");
 
+        let json_format = match matches.get_one::<String>("json-format").map(String::as_str) {
+            Some("flat") => JsonFormat::Flat,
+            Some("ecs") => JsonFormat::ecs(),
+            _ => JsonFormat::Nested,
+        };
+
         let no_show_record_number = match (
             matches.get_flag("no-show-record-number"),
             matches.get_one::<String>("output-format"),
         ) {
-            // "jsonl" --> --no-show-record-number
-            (false, Some(fmt)) => fmt == "jsonl",
+            // "jsonl", "csv" and "tsv" --> --no-show-record-number
+            (false, Some(fmt)) => matches!(fmt.as_str(), "jsonl" | "csv" | "tsv"),
             (true, Some(fmt)) => {
                 if fmt == "jsonl" {
                     eprintln!("no need to pass both `--no-show-record-number` and `-o jsonl`");
@@ -111,10 +178,64 @@ impl EvtxDump {
 
         let validate_checksums = matches.get_flag("validate-checksums");
         let stop_after_error = matches.get_flag("stop-after-one-error");
+        let carve = matches.get_flag("carve");
+        let follow = matches.get_flag("follow");
+
+        if input == Path::new(STDIN_INPUT) && (carve || follow) {
+            bail!("`--carve` and `--follow` cannot be used when reading from stdin");
+        }
 
-        let event_ranges = matches
-            .get_one::<&String>("event-ranges")
-            .map(|s| Ranges::from_str(s).expect("used validator"));
+        #[cfg(feature = "arrow")]
+        if output_format == EvtxOutputFormat::Parquet {
+            if carve || follow || input == Path::new(STDIN_INPUT) {
+                bail!("`-o parquet` cannot be used with `--carve`, `--follow` or when reading from stdin");
+            }
+            if !matches.contains_id("output-target") {
+                bail!("`-o parquet` requires an output file (`--output`)");
+            }
+        }
+
+        #[cfg(feature = "sqlite")]
+        let database = if output_format == EvtxOutputFormat::SQLite {
+            if carve || follow || input == Path::new(STDIN_INPUT) {
+                bail!("`-o sqlite` cannot be used with `--carve`, `--follow` or when reading from stdin");
+            }
+
+            match matches.get_one::<String>("output-target") {
+                Some(path) => Some(PathBuf::from(path)),
+                None => bail!("`-o sqlite` requires a database file (`--output`)"),
+            }
+        } else {
+            None
+        };
+
+        let event_ranges = matches.get_one::<Ranges>("event-ranges").cloned();
+
+        let query = match (
+            matches.get_one::<String>("query"),
+            matches.get_one::<String>("query-file"),
+        ) {
+            (Some(query), _) => Some(query.clone()),
+            (None, Some(path)) => Some(
+                fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read query file at: {}", path))?,
+            ),
+            (None, None) => None,
+        };
+        let query = query
+            .map(|query| EvtxQuery::parse(&query))
+            .transpose()
+            .with_context(|| "Failed to parse query")?;
+
+        let record_order = match matches.get_one::<String>("sort-by").map(String::as_str) {
+            Some("record-id") => RecordOrder::RecordId,
+            Some("timestamp") => RecordOrder::Timestamp,
+            _ => RecordOrder::File,
+        };
+
+        if input == Path::new(STDIN_INPUT) && record_order != RecordOrder::File {
+            bail!("`--sort-by` cannot be used when reading from stdin");
+        }
 
         let verbosity_level = match matches.get_count("verbose") {
             0 => None,
@@ -138,8 +259,12 @@ impl EvtxDump {
             })
             .expect("possible values are derived from `encodings()`");
 
-        let output: Box<dyn Write> = if let Some(path) = matches.get_one::<String>("output-target")
-        {
+        let output_target = matches.get_one::<String>("output-target");
+        // The database is opened when dumping, so it is not overwritten.
+        #[cfg(feature = "sqlite")]
+        let output_target = output_target.filter(|_| database.is_none());
+
+        let output: Box<dyn Write + Send> = if let Some(path) = output_target {
             Box::new(BufWriter::new(
                 Self::create_output_file(path, !matches.get_flag("no-confirm-overwrite"))
                     .with_context(|| {
@@ -150,19 +275,42 @@ impl EvtxDump {
             Box::new(BufWriter::new(io::stdout()))
         };
 
+        let mut parser_settings = ParserSettings::new()
+            .num_threads(num_threads.try_into().expect("u32 -> usize"))
+            .validate_checksums(validate_checksums)
+            .separate_json_attributes(separate_json_attrib_flag)
+            .json_format(json_format)
+            .indent(!no_indent)
+            .ansi_codec(*ansi_codec)
+            .record_order(record_order);
+
+        // Skip the records which were not requested before they are serialized.
+        if event_ranges.is_some() || query.is_some() {
+            let ranges = event_ranges.clone();
+            parser_settings = parser_settings.record_filter(move |record| {
+                ranges
+                    .as_ref()
+                    .is_none_or(|ranges| ranges.contains(&(record.event_record_id as usize)))
+                    // Records which cannot be evaluated are kept, so their errors are reported.
+                    && query
+                        .as_ref()
+                        .is_none_or(|query| query.matches(record).unwrap_or(true))
+            });
+        }
+
         Ok(EvtxDump {
-            parser_settings: ParserSettings::new()
-                .num_threads(num_threads.try_into().expect("u32 -> usize"))
-                .validate_checksums(validate_checksums)
-                .separate_json_attributes(separate_json_attrib_flag)
-                .indent(!no_indent)
-                .ansi_codec(*ansi_codec),
+            parser_settings,
             input,
             show_record_number: !no_show_record_number,
             output_format,
+            csv,
             output,
+            #[cfg(feature = "sqlite")]
+            database,
             verbosity_level,
             stop_after_error,
+            carve,
+            follow,
             ranges: event_ranges,
         })
     }
@@ -173,10 +321,209 @@ impl EvtxDump {
             eprintln!("{:?}", err);
         }
 
+        if let Some(csv) = &self.csv {
+            writeln!(self.output, "{}", csv.header())?;
+        }
+
+        if self.carve {
+            return self.run_carver();
+        }
+
+        if self.follow {
+            return self.run_follow();
+        }
+
+        if self.input == Path::new(STDIN_INPUT) {
+            return self.run_stdin();
+        }
+
         let mut parser = EvtxParser::from_path(&self.input)
             .with_context(|| format!("Failed to open evtx file at: {}", &self.input.display()))
             .map(|parser| parser.with_configuration(self.parser_settings.clone()))?;
 
+        // When only specific events are requested, avoid parsing chunks which cannot contain them.
+        let id_bounds = self.ranges.as_ref().and_then(|ranges| ranges.bounds());
+
+        match (self.output_format, id_bounds) {
+            (EvtxOutputFormat::XML, None) => {
+                for record in parser.records() {
+                    self.dump_record(record)?
+                }
+            }
+            (EvtxOutputFormat::XML, Some(bounds)) => {
+                for record in parser.records_by_id_range(bounds) {
+                    self.dump_record(record)?
+                }
+            }
+            (EvtxOutputFormat::JSON, None) => {
+                for record in parser.records_json() {
+                    self.dump_record(record)?
+                }
+            }
+            (EvtxOutputFormat::JSON, Some(bounds)) => {
+                for record in parser.records_json_by_id_range(bounds) {
+                    self.dump_record(record)?
+                }
+            }
+            (EvtxOutputFormat::CSV, None) => {
+                let csv = self.csv.clone().expect("Set when outputting CSV");
+                for record in parser.serialized_records(|record| record?.into_csv(&csv)) {
+                    self.dump_record(record)?
+                }
+            }
+            (EvtxOutputFormat::CSV, Some(bounds)) => {
+                let csv = self.csv.clone().expect("Set when outputting CSV");
+                for record in
+                    parser.serialized_records_by_id_range(bounds, |record| record?.into_csv(&csv))
+                {
+                    self.dump_record(record)?
+                }
+            }
+            // Records outside of the requested ranges are skipped by the record filter.
+            #[cfg(feature = "arrow")]
+            (EvtxOutputFormat::Parquet, _) => self.dump_parquet(&mut parser)?,
+            #[cfg(feature = "sqlite")]
+            (EvtxOutputFormat::SQLite, _) => self.dump_sqlite(&mut parser)?,
+        };
+
+        Ok(())
+    }
+
+    /// Writes all the records of `parser` as a single parquet file, see `RecordBatchBuilder`.
+    #[cfg(feature = "arrow")]
+    fn dump_parquet(&mut self, parser: &mut EvtxParser<File>) -> Result<()> {
+        use evtx::RecordBatchBuilder;
+        use parquet::arrow::ArrowWriter;
+
+        let mut writer = ArrowWriter::try_new(&mut self.output, RecordBatchBuilder::schema(), None)
+            .with_context(|| "Failed to create parquet writer")?;
+
+        for batch in parser.record_batches(PARQUET_BATCH_SIZE) {
+            match batch.with_context(|| "Failed to dump the next record.") {
+                Ok(batch) => writer.write(&batch)?,
+                // This error is non fatal.
+                Err(e) => {
+                    eprintln!("{:?}", format_err!(e));
+
+                    if self.stop_after_error {
+                        std::process::exit(1);
+                    }
+                }
+            }
+        }
+
+        writer.close()?;
+
+        Ok(())
+    }
+
+    /// Adds all the records of `parser` to the database, see `SqliteWriter`.
+    #[cfg(feature = "sqlite")]
+    fn dump_sqlite(&mut self, parser: &mut EvtxParser<File>) -> Result<()> {
+        use evtx::SqliteWriter;
+
+        let database = self.database.as_ref().expect("Set when outputting sqlite");
+        let mut writer = SqliteWriter::open(database)
+            .with_context(|| format!("Failed to open database at: {}", database.display()))?;
+        let source_file = self.input.to_string_lossy();
+
+        for record_id in writer
+            .insert_records(&source_file, parser)
+            .with_context(|| "Failed to prepare database")?
+        {
+            // This error is non fatal.
+            if let Err(e) = record_id.with_context(|| "Failed to dump the next record.") {
+                eprintln!("{:?}", format_err!(e));
+
+                if self.stop_after_error {
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        writer
+            .flush()
+            .with_context(|| "Failed to commit records to the database")?;
+
+        Ok(())
+    }
+
+    /// Dumps all the records in the input, and then keeps polling it for new records.
+    fn run_follow(&mut self) -> Result<()> {
+        let mut follower = EvtxParser::from_path(&self.input)
+            .with_context(|| format!("Failed to open evtx file at: {}", &self.input.display()))
+            .map(|parser| parser.with_configuration(self.parser_settings.clone()))?
+            .follow();
+
+        // The records which already exist are dumped like those of any other file,
+        // afterwards only the chunks which were updated are read.
+        match self.output_format {
+            EvtxOutputFormat::XML => {
+                for record in follower.records() {
+                    self.dump_record(record)?
+                }
+            }
+            EvtxOutputFormat::JSON => {
+                for record in follower.records_json() {
+                    self.dump_record(record)?
+                }
+            }
+            EvtxOutputFormat::CSV => {
+                let csv = self.csv.clone().expect("Set when outputting CSV");
+                for record in follower.serialized_records(|record| record?.into_csv(&csv)) {
+                    self.dump_record(record)?
+                }
+            }
+            #[cfg(feature = "arrow")]
+            EvtxOutputFormat::Parquet => unreachable!("Checked when parsing the arguments"),
+            #[cfg(feature = "sqlite")]
+            EvtxOutputFormat::SQLite => unreachable!("Checked when parsing the arguments"),
+        };
+
+        let input = self.input.clone();
+        let context = || format!("Failed to read evtx file at: {}", input.display());
+
+        loop {
+            self.output.flush()?;
+            thread::sleep(FOLLOW_POLL_INTERVAL);
+
+            match self.output_format {
+                EvtxOutputFormat::XML => {
+                    for record in follower.poll().with_context(context)? {
+                        self.dump_record(record)?
+                    }
+                }
+                EvtxOutputFormat::JSON => {
+                    for record in follower.poll_json().with_context(context)? {
+                        self.dump_record(record)?
+                    }
+                }
+                EvtxOutputFormat::CSV => {
+                    let csv = self.csv.clone().expect("Set when outputting CSV");
+                    let records = follower
+                        .poll_serialized(|record| record?.into_csv(&csv))
+                        .with_context(context)?;
+
+                    for record in records {
+                        self.dump_record(record)?
+                    }
+                }
+                #[cfg(feature = "arrow")]
+                EvtxOutputFormat::Parquet => unreachable!("Checked when parsing the arguments"),
+                #[cfg(feature = "sqlite")]
+                EvtxOutputFormat::SQLite => unreachable!("Checked when parsing the arguments"),
+            };
+        }
+    }
+
+    /// Dumps the records of a file read from stdin, which is consumed chunk by chunk.
+    fn run_stdin(&mut self) -> Result<()> {
+        let stdin = io::stdin();
+        let mut parser = EvtxStreamParser::from_read(stdin.lock())
+            .with_context(|| "Failed to read evtx file from stdin")
+            .map(|parser| parser.with_configuration(self.parser_settings.clone()))?;
+
+        // Records outside of the requested ranges are skipped by the record filter.
         match self.output_format {
             EvtxOutputFormat::XML => {
                 for record in parser.records() {
@@ -188,6 +535,47 @@ impl EvtxDump {
                     self.dump_record(record)?
                 }
             }
+            EvtxOutputFormat::CSV => {
+                let csv = self.csv.clone().expect("Set when outputting CSV");
+                for record in parser.serialized_records(|record| record?.into_csv(&csv)) {
+                    self.dump_record(record)?
+                }
+            }
+            #[cfg(feature = "arrow")]
+            EvtxOutputFormat::Parquet => unreachable!("Checked when parsing the arguments"),
+            #[cfg(feature = "sqlite")]
+            EvtxOutputFormat::SQLite => unreachable!("Checked when parsing the arguments"),
+        };
+
+        Ok(())
+    }
+
+    fn run_carver(&mut self) -> Result<()> {
+        let mut carver = EvtxCarver::from_path(&self.input)
+            .with_context(|| format!("Failed to open image at: {}", &self.input.display()))
+            .map(|carver| carver.with_configuration(self.parser_settings.clone()))?;
+
+        match self.output_format {
+            EvtxOutputFormat::XML => {
+                for carved in carver.records() {
+                    self.dump_carved_record(carved)?
+                }
+            }
+            EvtxOutputFormat::JSON => {
+                for carved in carver.records_json() {
+                    self.dump_carved_record(carved)?
+                }
+            }
+            EvtxOutputFormat::CSV => {
+                let csv = self.csv.clone().expect("Set when outputting CSV");
+                for carved in carver.serialized_records(|record| record?.into_csv(&csv)) {
+                    self.dump_carved_record(carved)?
+                }
+            }
+            #[cfg(feature = "arrow")]
+            EvtxOutputFormat::Parquet => unreachable!("Checked when parsing the arguments"),
+            #[cfg(feature = "sqlite")]
+            EvtxOutputFormat::SQLite => unreachable!("Checked when parsing the arguments"),
         };
 
         Ok(())
@@ -239,20 +627,38 @@ impl EvtxDump {
     }
 
     fn dump_record(&mut self, record: EvtxResult<SerializedEvtxRecord<String>>) -> Result<()> {
+        self.write_record(record, None)
+    }
+
+    /// Like `dump_record`, also showing the offset of the record in the image.
+    fn dump_carved_record(
+        &mut self,
+        carved: EvtxResult<CarvedRecord<SerializedEvtxRecord<String>>>,
+    ) -> Result<()> {
+        match carved {
+            Ok(carved) => self.write_record(Ok(carved.record), Some(carved.offset)),
+            Err(e) => self.write_record(Err(e), None),
+        }
+    }
+
+    fn write_record(
+        &mut self,
+        record: EvtxResult<SerializedEvtxRecord<String>>,
+        offset: Option<u64>,
+    ) -> Result<()> {
         match record.with_context(|| "Failed to dump the next record.") {
             Ok(r) => {
-                let range_filter = if let Some(ranges) = &self.ranges {
-                    ranges.contains(&(r.event_record_id as usize))
-                } else {
-                    true
-                };
-
-                if range_filter {
-                    if self.show_record_number {
-                        writeln!(self.output, "Record {}", r.event_record_id)?;
+                if self.show_record_number {
+                    match offset {
+                        Some(offset) => writeln!(
+                            self.output,
+                            "Record {} (offset {})",
+                            r.event_record_id, offset
+                        )?,
+                        None => writeln!(self.output, "Record {}", r.event_record_id)?,
                     }
-                    writeln!(self.output, "{}", r.data)?;
                 }
+                writeln!(self.output, "{}", r.data)?;
             }
             // This error is non fatal.
             Err(e) => {
@@ -281,12 +687,21 @@ impl EvtxDump {
     }
 }
 
+#[derive(Clone)]
 struct Ranges(Vec<RangeInclusive<usize>>);
 
 impl Ranges {
     fn contains(&self, number: &usize) -> bool {
         self.0.iter().any(|r| r.contains(number))
     }
+
+    /// The smallest range of record ids which contains all the ranges.
+    fn bounds(&self) -> Option<RangeInclusive<u64>> {
+        let start = self.0.iter().map(|r| *r.start()).min()?;
+        let end = self.0.iter().map(|r| *r.end()).max()?;
+
+        Some(start as u64..=end as u64)
+    }
 }
 
 impl FromStr for Ranges {
@@ -339,10 +754,8 @@ impl FromStr for Ranges {
     }
 }
 
-fn matches_ranges(value: &str) -> Result<(), String> {
-    Ranges::from_str(value)
-        .map_err(|e| e.to_string())
-        .map(|_| ())
+fn matches_ranges(value: &str) -> Result<Ranges, String> {
+    Ranges::from_str(value).map_err(|e| e.to_string())
 }
 
 #[test]
@@ -353,6 +766,97 @@ fn test_ranges() {
     assert!(matches_ranges("-2").is_err());
 }
 
+/// Writes an integrity report of the input as JSON.
+/// Exits with an error code if integrity issues were found.
+fn run_verify(matches: &ArgMatches) -> Result<()> {
+    let input = PathBuf::from(
+        matches
+            .get_one::<String>("INPUT")
+            .expect("This is a required argument"),
+    );
+
+    let report = EvtxParser::from_path(&input)
+        .with_context(|| format!("Failed to open evtx file at: {}", input.display()))?
+        .verify()
+        .with_context(|| format!("Failed to verify evtx file at: {}", input.display()))?;
+
+    let mut output = BufWriter::new(io::stdout());
+    serde_json::to_writer_pretty(&mut output, &report)?;
+    writeln!(output)?;
+    output.flush()?;
+
+    if !report.is_valid() {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Writes the signs of tampering found in the input as JSON.
+/// Exits with an error code if anything was found.
+fn run_analyze(matches: &ArgMatches) -> Result<()> {
+    let input = PathBuf::from(
+        matches
+            .get_one::<String>("INPUT")
+            .expect("This is a required argument"),
+    );
+
+    let findings = EvtxParser::from_path(&input)
+        .with_context(|| format!("Failed to open evtx file at: {}", input.display()))?
+        .analyze();
+
+    let mut output = BufWriter::new(io::stdout());
+    serde_json::to_writer_pretty(&mut output, &findings)?;
+    writeln!(output)?;
+    output.flush()?;
+
+    if !findings.is_empty() {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Writes the Sigma rule matches of every record as JSON lines.
+#[cfg(feature = "sigma")]
+fn run_sigma(matches: &ArgMatches) -> Result<()> {
+    let input = PathBuf::from(
+        matches
+            .get_one::<String>("INPUT")
+            .expect("This is a required argument"),
+    );
+    let rules_dir = PathBuf::from(
+        matches
+            .get_one::<String>("rules")
+            .expect("This is a required argument"),
+    );
+
+    let rules = evtx::SigmaRuleSet::load_dir(&rules_dir)
+        .with_context(|| format!("Failed to load sigma rules from: {}", rules_dir.display()))?;
+    for (path, reason) in rules.skipped_rules() {
+        eprintln!("Skipped sigma rule {}: {}", path.display(), reason);
+    }
+
+    let mut parser = EvtxParser::from_path(&input)
+        .with_context(|| format!("Failed to open evtx file at: {}", input.display()))?;
+
+    let mut output = BufWriter::new(io::stdout());
+    for record_matches in parser.serialized_records(|record| rules.evaluate(&record?)) {
+        match record_matches {
+            Ok(record_matches) => {
+                for m in record_matches {
+                    serde_json::to_writer(&mut output, &m)?;
+                    writeln!(output)?;
+                }
+            }
+            Err(e) => eprintln!("{:?}", format_err!(e).context("Failed to evaluate record")),
+        }
+    }
+    output.flush()?;
+
+    Ok(())
+}
+
 fn main() -> Result<()> {
     let all_encoings = encodings()
         .iter()
@@ -360,11 +864,40 @@ fn main() -> Result<()> {
         .map(|e| e.name())
         .collect::<Vec<&'static str>>();
 
-    let matches = Command::new("EVTX Parser")
+    #[allow(unused_mut)]
+    let mut output_formats = vec!["json", "xml", "jsonl", "csv", "tsv"];
+    #[cfg(feature = "arrow")]
+    output_formats.push("parquet");
+    #[cfg(feature = "sqlite")]
+    output_formats.push("sqlite");
+
+    let cli = Command::new("EVTX Parser")
         .version(env!("CARGO_PKG_VERSION"))
         .author("Omer B. <omerbenamram@gmail.com>")
         .about("Utility to parse EVTX files")
-        .arg(Arg::new("INPUT").required(true))
+        .subcommand_negates_reqs(true)
+        .subcommand(
+            Command::new("verify")
+                .about("Checks the integrity of an evtx file, and prints a JSON report")
+                .long_about(indoc!("Checks the integrity of an evtx file, and prints a JSON report of the file header checksum, \
+                the checksums of every chunk, gaps in record ids, and the dirty/full flags. \
+                Exits with code 1 if integrity issues were found (the dirty/full flags are not considered issues)."))
+                .arg(Arg::new("INPUT").required(true)),
+        )
+        .subcommand(
+            Command::new("analyze")
+                .about("Looks for signs of tampering or log clearing in an evtx file, and prints them as JSON")
+                .long_about(indoc!("Looks for signs of tampering or log clearing in an evtx file: record ids or timestamps \
+                which go backwards, records outside of the range declared by their chunk, chunks with valid checksums \
+                but an impossible free space offset, gaps in record numbers between chunks, and log cleared events (1102/104). \
+                Every finding includes the offset it refers to. Exits with code 1 if anything was found."))
+                .arg(Arg::new("INPUT").required(true)),
+        )
+        .arg(
+            Arg::new("INPUT")
+                .required(true)
+                .help("The evtx file to dump, or `-` to read it from stdin."),
+        )
         .arg(
             Arg::new("num-threads")
                 .short('t')
@@ -377,7 +910,7 @@ fn main() -> Result<()> {
             Arg::new("output-format")
                 .short('o')
                 .long("format")
-                .value_parser(["json", "xml", "jsonl"])
+                .value_parser(output_formats)
                 .default_value("xml")
                 .help("Sets the output format")
                 .long_help(indoc!(
@@ -385,8 +918,23 @@ fn main() -> Result<()> {
                      "xml"   - prints XML output.
                      "json"  - prints JSON output.
                      "jsonl" - (jsonlines) same as json with --no-indent --dont-show-record-number
+                     "csv"   - prints a header, and a row with the columns given by --column for every record.
+                     "tsv"   - same as csv, with tab separated fields.
+                     "parquet" - writes an Apache Parquet file to --output (only when built with the `arrow` feature).
+                     "sqlite" - adds the records to the SQLite database at --output, in the `records` and `event_data` tables
+                                (only when built with the `sqlite` feature).
                 "#)),
         )
+        .arg(
+            Arg::new("column")
+                .long("column")
+                .short('c')
+                .action(ArgAction::Append)
+                .help(indoc!("When outputting CSV or TSV, adds a column (may be repeated). \
+                A column is either `record_id`, `timestamp`, or a path relative to the root element of the record, \
+                such as `System/EventID` or `EventData/Data[@Name='IpAddress']`. \
+                Defaults to the record id, timestamp, provider, event id and computer.")),
+        )
         .arg(
             Arg::new("output-target")
                 .long("output")
@@ -413,6 +961,32 @@ fn main() -> Result<()> {
                     --events=0-10,20-30 will output events 0-10 and 20-30.
                 ")),
         )
+        .arg(
+            Arg::new("query")
+                .long("query")
+                .short('q')
+                .action(ArgAction::Set)
+                .conflicts_with("query-file")
+                .help(indoc!(r#"When set, only the events matching the query will be outputted.
+                Accepts the XPath subset used by Windows event queries (as in `wevtutil qe /q:`), or a <QueryList> document.
+                For example:
+                    --query "*[System[(EventID=4624 or EventID=4625)]]"
+                    --query "*[EventData[Data[@Name='TargetUserName']='admin']]"
+                "#)),
+        )
+        .arg(
+            Arg::new("query-file")
+                .long("query-file")
+                .action(ArgAction::Set)
+                .help("Same as `--query`, but reads the query (or <QueryList> document) from a file."),
+        )
+        .arg(
+            Arg::new("sort-by")
+                .long("sort-by")
+                .value_parser(["record-id", "timestamp"])
+                .help(indoc!("When set, records will be sorted by their EventRecordID or by their timestamp \
+                (while still using multiple threads). The output does not depend on the number of threads.")),
+        )
         .arg(
             Arg::new("validate-checksums")
                 .long("validate-checksums")
@@ -420,6 +994,21 @@ fn main() -> Result<()> {
                 .help(indoc!("When set, chunks with invalid checksums will not be parsed. \
                 Usually dirty files have bad checksums, so using this flag will result in fewer records.")),
         )
+        .arg(
+            Arg::new("follow")
+                .long("follow")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("carve")
+                .help(indoc!("When set, after dumping the existing records, keeps watching the input and dumps new records \
+                as they are written (like `tail -f`). Handles logs which wrap around.")),
+        )
+        .arg(
+            Arg::new("carve")
+                .long("carve")
+                .action(ArgAction::SetTrue)
+                .help(indoc!("When set, input is treated as a raw image (disk image, memory dump, unallocated space, etc.), \
+                and records will be carved from any chunks or records found in it.")),
+        )
         .arg(
             Arg::new("no-indent")
                 .long("no-indent")
@@ -432,6 +1021,17 @@ fn main() -> Result<()> {
                 .action(ArgAction::SetTrue)
                 .help("If outputting JSON, XML Element's attributes will be stored in a separate object named '<ELEMENTNAME>_attributes', with <ELEMENTNAME> containing the value of the node."),
         )
+        .arg(
+            Arg::new("json-format")
+                .long("json-format")
+                .value_parser(["nested", "flat", "ecs"])
+                .default_value("nested")
+                .help(indoc!("If outputting JSON, sets the shape of every record: \
+                `nested` mirrors the XML structure, \
+                `flat` produces a single object keyed by paths such as `System.EventID` and `EventData.TargetUserName` \
+                (attributes are folded in, as in `System.Provider.Name`), \
+                and `ecs` is like `flat`, using Elastic Common Schema field names.")),
+        )
         .arg(
             Arg::new("no-show-record-number")
                 .long("dont-show-record-number")
@@ -460,7 +1060,34 @@ fn main() -> Result<()> {
                 -vv  - debug
                 -vvv - trace
             NOTE: trace output is only available in debug builds, as it is extremely verbose."#))
-        ).get_matches();
+        );
+
+    #[cfg(feature = "sigma")]
+    let cli = cli.subcommand(
+        Command::new("sigma")
+            .about("Evaluates Sigma rules against an evtx file, and prints the matches as JSON lines")
+            .long_about(indoc!("Evaluates the Sigma rules (.yml/.yaml files) found in a directory against every record \
+            of an evtx file, and prints a JSON line for every match, with the rule id, title and level. \
+            Rules using unsupported features (such as aggregations) are skipped with a warning."))
+            .arg(
+                Arg::new("rules")
+                    .long("rules")
+                    .short('r')
+                    .required(true)
+                    .help("A directory containing Sigma rules."),
+            )
+            .arg(Arg::new("INPUT").required(true)),
+    );
+
+    let matches = cli.get_matches();
+
+    match matches.subcommand() {
+        Some(("verify", verify_matches)) => return run_verify(verify_matches),
+        Some(("analyze", analyze_matches)) => return run_analyze(analyze_matches),
+        #[cfg(feature = "sigma")]
+        Some(("sigma", sigma_matches)) => return run_sigma(sigma_matches),
+        _ => {}
+    }
 
     EvtxDump::from_cli_matches(&matches)?.run()?;
 
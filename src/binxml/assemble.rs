@@ -51,6 +51,10 @@ pub fn parse_tokens<'a, T: BinXmlOutput>(
             XmlModel::StartOfStream => {}
             XmlModel::PI(pi) => visitor.visit_processing_instruction(&pi)?,
             XmlModel::EntityRef(entity) => visitor.visit_entity_reference(&entity)?,
+            XmlModel::CDATA(data) => visitor.visit_cdata_section(data)?,
+            XmlModel::CharRef(value) => {
+                visitor.visit_character_reference(Cow::Owned(value.to_string()))?
+            }
         };
     }
 
@@ -98,17 +102,15 @@ pub fn create_record_model<'a>(
                     Some(builder) => model.push(XmlModel::OpenElement(builder.finish()?)),
                 };
             }
-            Cow::Owned(BinXMLDeserializedTokens::CDATASection)
-            | Cow::Borrowed(BinXMLDeserializedTokens::CDATASection) => {
-                return Err(EvtxError::FailedToCreateRecordModel(
-                    "Unimplemented - CDATA",
-                ));
+            Cow::Owned(BinXMLDeserializedTokens::CDATASection(data)) => {
+                model.push(XmlModel::CDATA(Cow::Owned(data)))
             }
-            Cow::Owned(BinXMLDeserializedTokens::CharRef)
-            | Cow::Borrowed(BinXMLDeserializedTokens::CharRef) => {
-                return Err(EvtxError::FailedToCreateRecordModel(
-                    "Unimplemented - CharacterReference",
-                ));
+            Cow::Borrowed(BinXMLDeserializedTokens::CDATASection(data)) => {
+                model.push(XmlModel::CDATA(Cow::Borrowed(data)))
+            }
+            Cow::Owned(BinXMLDeserializedTokens::CharRef(value))
+            | Cow::Borrowed(&BinXMLDeserializedTokens::CharRef(value)) => {
+                model.push(XmlModel::CharRef(value))
             }
             Cow::Owned(BinXMLDeserializedTokens::EntityRef(ref entity))
             | Cow::Borrowed(BinXMLDeserializedTokens::EntityRef(ref entity)) => {
@@ -202,7 +204,7 @@ pub fn create_record_model<'a>(
                 trace!("BinXMLDeserializedTokens::Value(value) - {:?}", value);
                 match current_element {
                     None => match value {
-                        BinXmlValue::EvtXml => {
+                        BinXmlValue::BinXmlType(_) | BinXmlValue::BinXmlArrayType(_) => {
                             return Err(EvtxError::FailedToCreateRecordModel(
                                 "Call `expand_templates` before calling this function",
                             ));
@@ -220,7 +222,7 @@ pub fn create_record_model<'a>(
                 trace!("BinXMLDeserializedTokens::Value(value) - {:?}", value);
                 match current_element {
                     None => match value {
-                        BinXmlValue::EvtXml => {
+                        BinXmlValue::BinXmlType(_) | BinXmlValue::BinXmlArrayType(_) => {
                             return Err(EvtxError::FailedToCreateRecordModel(
                                 "Call `expand_templates` before calling this function",
                             ));
@@ -242,7 +244,7 @@ pub fn create_record_model<'a>(
 
 const BINXML_NAME_LINK_SIZE: u32 = 6;
 
-fn expand_string_ref<'a>(
+pub(crate) fn expand_string_ref<'a>(
     string_ref: &BinXmlNameRef,
     chunk: &'a EvtxChunk<'a>,
 ) -> Result<Cow<'a, BinXmlName>> {
@@ -355,6 +357,16 @@ fn _expand_templates<'a>(
                 _expand_templates(Cow::Borrowed(token), chunk, stack)?;
             }
         }
+        Cow::Owned(BinXMLDeserializedTokens::Value(BinXmlValue::BinXmlArrayType(fragments))) => {
+            for token in fragments.into_iter().flatten() {
+                _expand_templates(Cow::Owned(token), chunk, stack)?;
+            }
+        }
+        Cow::Borrowed(BinXMLDeserializedTokens::Value(BinXmlValue::BinXmlArrayType(fragments))) => {
+            for token in fragments.iter().flatten() {
+                _expand_templates(Cow::Borrowed(token), chunk, stack)?;
+            }
+        }
         // Actual template handling.
         Cow::Owned(BinXMLDeserializedTokens::TemplateInstance(template)) => {
             expand_template(template, chunk, stack)?;
@@ -386,3 +398,94 @@ pub fn expand_templates<'a>(
 
     Ok(stack)
 }
+
+/// Walks `tokens` in the same order as the tokens produced by `expand_templates`,
+/// without consuming or cloning them.
+///
+/// `visit` is called with every token other than template instances and substitutions (which
+/// are expanded), and can stop the walk by returning `false`.
+/// Returns `false` if the walk was stopped.
+pub(crate) fn walk_expanded_tokens<'a, F>(
+    tokens: &[BinXMLDeserializedTokens<'a>],
+    substitutions: &[BinXMLDeserializedTokens<'a>],
+    chunk: &'a EvtxChunk<'a>,
+    visit: &mut F,
+) -> Result<bool>
+where
+    F: FnMut(&BinXMLDeserializedTokens) -> Result<bool>,
+{
+    for token in tokens {
+        let keep_walking = match token {
+            BinXMLDeserializedTokens::TemplateInstance(template) => {
+                walk_expanded_template(template, chunk, visit)?
+            }
+            BinXMLDeserializedTokens::Substitution(substitution_descriptor) => {
+                if substitution_descriptor.ignore {
+                    continue;
+                }
+
+                match substitutions.get(substitution_descriptor.substitution_index as usize) {
+                    Some(value) => {
+                        walk_expanded_tokens(std::slice::from_ref(value), &[], chunk, visit)?
+                    }
+                    None => visit(&BinXMLDeserializedTokens::Value(BinXmlValue::NullType))?,
+                }
+            }
+            BinXMLDeserializedTokens::Value(BinXmlValue::BinXmlType(tokens)) => {
+                walk_expanded_tokens(tokens, substitutions, chunk, visit)?
+            }
+            BinXMLDeserializedTokens::Value(BinXmlValue::BinXmlArrayType(fragments)) => {
+                let mut keep_walking = true;
+                for tokens in fragments {
+                    keep_walking = walk_expanded_tokens(tokens, substitutions, chunk, visit)?;
+                    if !keep_walking {
+                        break;
+                    }
+                }
+                keep_walking
+            }
+            _ => visit(token)?,
+        };
+
+        if !keep_walking {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+fn walk_expanded_template<'a, F>(
+    template: &BinXmlTemplateRef<'a>,
+    chunk: &'a EvtxChunk<'a>,
+    visit: &mut F,
+) -> Result<bool>
+where
+    F: FnMut(&BinXMLDeserializedTokens) -> Result<bool>,
+{
+    if let Some(template_def) = chunk
+        .template_table
+        .get_template(template.template_def_offset)
+    {
+        walk_expanded_tokens(
+            &template_def.tokens,
+            &template.substitution_array,
+            chunk,
+            visit,
+        )
+    } else {
+        // Same as `expand_template`, the template may be missing from the cache.
+        let mut cursor = Cursor::new(chunk.data);
+
+        let _ = cursor.seek(SeekFrom::Start(u64::from(template.template_def_offset)));
+        let template_def =
+            read_template_definition(&mut cursor, Some(chunk), chunk.settings.get_ansi_codec())?;
+
+        walk_expanded_tokens(
+            &template_def.tokens,
+            &template.substitution_array,
+            chunk,
+            visit,
+        )
+    }
+}
@@ -1,9 +1,9 @@
-use crate::err::{DeserializationError, DeserializationResult as Result};
+use crate::err::{DeserializationError, DeserializationResult as Result, WrappedIoError};
 
 use byteorder::ReadBytesExt;
 
-use log::trace;
-use std::io::{Seek, SeekFrom};
+use log::{debug, trace};
+use std::io::{self, Seek, SeekFrom};
 
 use crate::binxml::tokens::{
     read_open_start_element, read_processing_instruction_data, read_processing_instruction_target,
@@ -12,8 +12,8 @@ use crate::binxml::value_variant::BinXmlValue;
 
 use crate::{
     binxml::tokens::{
-        read_attribute, read_entity_ref, read_fragment_header, read_substitution_descriptor,
-        read_template,
+        read_attribute, read_cdata_section, read_character_reference, read_entity_ref,
+        read_fragment_header, read_partial_template, read_substitution_descriptor, read_template,
     },
     model::{deserialized::*, raw::*},
 };
@@ -32,6 +32,8 @@ pub struct IterTokens<'a> {
     eof: bool,
     is_inside_substitution: bool,
     ansi_codec: EncodingRef,
+    keep_partial_templates: bool,
+    truncated: bool,
 }
 
 pub struct BinXmlDeserializer<'a> {
@@ -101,6 +103,16 @@ impl<'a> BinXmlDeserializer<'a> {
         let mut cursor = Cursor::new(self.data);
         cursor.seek(SeekFrom::Start(self.offset))?;
 
+        // Offsets come from the data itself, a corrupted one can point past its end.
+        if self.offset > self.data.len() as u64 {
+            return Err(WrappedIoError::io_error_with_message(
+                io::Error::from(io::ErrorKind::UnexpectedEof),
+                "binxml offset is past the end of the data",
+                &mut cursor,
+            )
+            .into());
+        }
+
         Ok(IterTokens {
             cursor,
             chunk: self.chunk,
@@ -109,11 +121,25 @@ impl<'a> BinXmlDeserializer<'a> {
             eof: false,
             is_inside_substitution: self.is_inside_substitution,
             ansi_codec: self.ansi_codec,
+            keep_partial_templates: false,
+            truncated: false,
         })
     }
 }
 
 impl<'a> IterTokens<'a> {
+    /// Yields a template instance whose substitution values could only be partially read,
+    /// instead of an error. The stream ends after such a template, and `is_truncated` is set.
+    pub(crate) fn keep_partial_templates(mut self) -> Self {
+        self.keep_partial_templates = true;
+        self
+    }
+
+    /// Whether the stream ended at a template instance whose values could only be partially read.
+    pub(crate) fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
     /// Reads the next token from the stream, will return error if failed to read from the stream for some reason,
     /// or if reading random bytes (usually because of a bug in the code).
     fn read_next_token(&self, cursor: &mut Cursor<&'a [u8]>) -> Result<BinXMLRawToken> {
@@ -152,14 +178,12 @@ impl<'a> IterTokens<'a> {
             BinXMLRawToken::Attribute(_token_information) => {
                 Ok(BinXMLDeserializedTokens::Attribute(read_attribute(cursor)?))
             }
-            BinXMLRawToken::CDataSection => Err(DeserializationError::UnimplementedToken {
-                name: "CDataSection",
-                offset: cursor.position(),
-            }),
-            BinXMLRawToken::CharReference => Err(DeserializationError::UnimplementedToken {
-                name: "CharReference",
-                offset: cursor.position(),
-            }),
+            BinXMLRawToken::CDataSection => Ok(BinXMLDeserializedTokens::CDATASection(
+                read_cdata_section(cursor)?,
+            )),
+            BinXMLRawToken::CharReference => Ok(BinXMLDeserializedTokens::CharRef(
+                read_character_reference(cursor)?,
+            )),
             BinXMLRawToken::EntityReference => Ok(BinXMLDeserializedTokens::EntityRef(
                 read_entity_ref(cursor)?,
             )),
@@ -217,7 +241,21 @@ impl<'a> IterTokens<'a> {
                 if let BinXMLRawToken::EndOfStream = t {
                     self.eof = true;
                 }
-                let deserialized_token_result = self.visit_token(&mut cursor, t);
+                let deserialized_token_result = match t {
+                    BinXMLRawToken::TemplateInstance if self.keep_partial_templates => {
+                        read_partial_template(&mut cursor, self.chunk, self.ansi_codec).map(
+                            |(template, err)| {
+                                if let Some(err) = err {
+                                    debug!("Template instance is truncated: {}", err);
+                                    self.truncated = true;
+                                    self.eof = true;
+                                }
+                                BinXMLDeserializedTokens::TemplateInstance(template)
+                            },
+                        )
+                    }
+                    _ => self.visit_token(&mut cursor, t),
+                };
 
                 debug_assert!(
                     cursor.position() >= offset_from_chunk_start,
@@ -230,7 +268,8 @@ impl<'a> IterTokens<'a> {
             }
             Err(e) => Some(Err(e)),
         };
-        let total_read = cursor.position() - offset_from_chunk_start;
+        // A failed read past the end of the data moves the cursor back to the end.
+        let total_read = cursor.position().saturating_sub(offset_from_chunk_start);
         self.data_read_so_far += total_read as u32;
 
         mem::swap(&mut self.cursor, &mut cursor);
@@ -249,10 +288,29 @@ impl<'a> Iterator for IterTokens<'a> {
 
 #[cfg(test)]
 mod tests {
+    use super::BinXmlDeserializer;
+    use crate::binxml::value_variant::BinXmlValue;
     use crate::evtx_chunk::EvtxChunkData;
+    use crate::model::deserialized::BinXMLDeserializedTokens;
     use crate::{ensure_env_logger_initialized, ParserSettings};
+    use std::io::Cursor;
     use std::sync::Arc;
 
+    /// A fragment with the CDATA section `<b>&</b>` followed by the character reference `&#65;`.
+    ///
+    /// None of the samples contain CDATA sections or character references, so they are tested
+    /// with this fragment.
+    fn cdata_and_char_ref_fragment() -> Vec<u8> {
+        let cdata = "<b>&</b>";
+        let mut data = vec![0x07];
+        data.extend((cdata.len() as u16).to_le_bytes());
+        data.extend(cdata.encode_utf16().flat_map(u16::to_le_bytes));
+        data.extend([0x08, 0x41, 0x00]);
+        data.push(0x00);
+
+        data
+    }
+
     #[test]
     fn test_reads_a_single_record() {
         ensure_env_logger_initialized();
@@ -314,4 +372,138 @@ mod tests {
             }
         }
     }
+
+    /// Finds the (possibly nested) substitution value which is the string `s`.
+    fn find_string_value<'t, 'a>(
+        tokens: &'t mut [BinXMLDeserializedTokens<'a>],
+        s: &str,
+    ) -> Option<&'t mut BinXMLDeserializedTokens<'a>> {
+        tokens.iter_mut().find_map(|token| match token {
+            BinXMLDeserializedTokens::TemplateInstance(template) => {
+                find_string_value(&mut template.substitution_array, s)
+            }
+            BinXMLDeserializedTokens::Value(BinXmlValue::BinXmlType(tokens)) => {
+                find_string_value(tokens, s)
+            }
+            BinXMLDeserializedTokens::Value(BinXmlValue::StringType(value)) if value == s => {
+                Some(token)
+            }
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn test_reads_cdata_sections_and_character_references() {
+        ensure_env_logger_initialized();
+        let data = cdata_and_char_ref_fragment();
+
+        let tokens = BinXmlDeserializer::read_binxml_fragment(
+            &mut Cursor::new(&data),
+            None,
+            None,
+            false,
+            ParserSettings::default().get_ansi_codec(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            tokens,
+            vec![
+                BinXMLDeserializedTokens::CDATASection("<b>&</b>".to_string()),
+                BinXMLDeserializedTokens::CharRef(65),
+                BinXMLDeserializedTokens::EndOfStream,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_record_with_cdata_sections_and_character_references() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../../samples/security.evtx");
+        let from_start_of_chunk = &evtx_file[4096..];
+
+        let mut chunk = EvtxChunkData::new(from_start_of_chunk.to_vec(), true).unwrap();
+        let settings = ParserSettings::default();
+        let mut evtx_chunk = chunk.parse(Arc::new(settings)).unwrap();
+        let mut record = evtx_chunk.iter().nth(1).unwrap().unwrap();
+
+        let data = cdata_and_char_ref_fragment();
+        let mut tokens = BinXmlDeserializer::read_binxml_fragment(
+            &mut Cursor::new(&data),
+            None,
+            None,
+            false,
+            ParserSettings::default().get_ansi_codec(),
+        )
+        .unwrap();
+        tokens.pop();
+
+        // Replace the value of `TargetUserName` (`SYSTEM`) with the fragment.
+        let substitution = find_string_value(&mut record.tokens, "SYSTEM")
+            .expect("`TargetUserName` is substituted");
+        *substitution = BinXMLDeserializedTokens::Value(BinXmlValue::BinXmlType(tokens));
+
+        let xml = record.clone().into_xml().unwrap().data;
+        assert!(
+            xml.contains(r#"<Data Name="TargetUserName"><![CDATA[<b>&</b>]]>&#65;</Data>"#),
+            "{}",
+            xml
+        );
+
+        let json = record.clone().into_json().unwrap().data;
+        assert!(
+            json.contains(r#""TargetUserName": "<b>&</b>A""#),
+            "{}",
+            json
+        );
+
+        let event_data = record.event_data().unwrap();
+        assert!(event_data.contains(&("TargetUserName".to_string(), Some("<b>&</b>A".to_string()))));
+    }
+
+    #[test]
+    fn test_record_with_binxml_array() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../../samples/security.evtx");
+        let from_start_of_chunk = &evtx_file[4096..];
+
+        let mut chunk = EvtxChunkData::new(from_start_of_chunk.to_vec(), true).unwrap();
+        let settings = ParserSettings::default();
+        let mut evtx_chunk = chunk.parse(Arc::new(settings)).unwrap();
+        let mut record = evtx_chunk.iter().nth(1).unwrap().unwrap();
+
+        // Replace the value of `TargetUserName` (`SYSTEM`) with two fragments.
+        let fragment = |s: &str| {
+            vec![
+                BinXMLDeserializedTokens::Value(BinXmlValue::StringType(s.to_string())),
+                BinXMLDeserializedTokens::EndOfStream,
+            ]
+        };
+        let substitution = find_string_value(&mut record.tokens, "SYSTEM")
+            .expect("`TargetUserName` is substituted");
+        *substitution = BinXMLDeserializedTokens::Value(BinXmlValue::BinXmlArrayType(vec![
+            fragment("first"),
+            fragment("second"),
+        ]));
+
+        let xml = record.clone().into_xml().unwrap().data;
+        assert!(
+            xml.contains(r#"<Data Name="TargetUserName">firstsecond</Data>"#),
+            "{}",
+            xml
+        );
+
+        let json = record.clone().into_json().unwrap().data;
+        assert!(
+            json.contains(r#""TargetUserName": "firstsecond""#),
+            "{}",
+            json
+        );
+
+        let event_data = record.event_data().unwrap();
+        assert!(event_data.contains(&(
+            "TargetUserName".to_string(),
+            Some("firstsecond".to_string())
+        )));
+    }
 }
@@ -67,7 +67,7 @@ impl BinXmlNameRef {
             let len = cursor.read_u16::<LittleEndian>()?;
 
             let nul_terminator_len = 4;
-            let data_size = BinXmlNameLink::data_size() + u32::from(len * 2) + nul_terminator_len;
+            let data_size = BinXmlNameLink::data_size() + u32::from(len) * 2 + nul_terminator_len;
 
             try_seek!(
                 cursor,
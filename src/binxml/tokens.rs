@@ -24,6 +24,21 @@ pub fn read_template<'a>(
     chunk: Option<&'a EvtxChunk<'a>>,
     ansi_codec: EncodingRef,
 ) -> Result<BinXmlTemplateRef<'a>> {
+    match read_partial_template(cursor, chunk, ansi_codec)? {
+        (template, None) => Ok(template),
+        (_, Some(err)) => Err(err),
+    }
+}
+
+/// Like `read_template`, but keeps the substitution values which were read before a value failed
+/// to deserialize (for example, because the end of the record was overwritten).
+/// The error is returned alongside the template, and the cursor is left where the value started.
+/// Missing values are expanded as `NullType`.
+pub(crate) fn read_partial_template<'a>(
+    cursor: &mut Cursor<&'a [u8]>,
+    chunk: Option<&'a EvtxChunk<'a>>,
+    ansi_codec: EncodingRef,
+) -> Result<(BinXmlTemplateRef<'a>, Option<DeserializationError>)> {
     trace!("TemplateInstance at {}", cursor.position());
 
     let _ = try_read!(cursor, u8)?;
@@ -42,7 +57,11 @@ pub fn read_template<'a>(
 
     let number_of_substitutions = try_read!(cursor, u32)?;
 
-    let mut value_descriptors = Vec::with_capacity(number_of_substitutions as usize);
+    // Every descriptor takes 4 bytes, don't trust the count beyond what the data can hold.
+    let remaining_descriptors =
+        (cursor.get_ref().len() as u64).saturating_sub(cursor.position()) / 4;
+    let mut value_descriptors =
+        Vec::with_capacity(u64::from(number_of_substitutions).min(remaining_descriptors) as usize);
 
     for _ in 0..number_of_substitutions {
         let size = try_read!(cursor, u16)?;
@@ -63,7 +82,7 @@ pub fn read_template<'a>(
 
     trace!("{:?}", value_descriptors);
 
-    let mut substitution_array = Vec::with_capacity(number_of_substitutions as usize);
+    let mut substitution_array = Vec::with_capacity(value_descriptors.len());
 
     for descriptor in value_descriptors {
         let position_before_reading_value = cursor.position();
@@ -72,13 +91,25 @@ pub fn read_template<'a>(
             offset = position_before_reading_value,
             substitution = descriptor.value_type,
         );
-        let value = BinXmlValue::deserialize_value_type(
+        let value = match BinXmlValue::deserialize_value_type(
             &descriptor.value_type,
             cursor,
             chunk,
             Some(descriptor.size),
             ansi_codec,
-        )?;
+        ) {
+            Ok(value) => value,
+            Err(err) => {
+                cursor.set_position(position_before_reading_value);
+                return Ok((
+                    BinXmlTemplateRef {
+                        template_def_offset: template_definition_data_offset,
+                        substitution_array,
+                    },
+                    Some(err),
+                ));
+            }
+        };
 
         trace!("\t {:?}", value);
         // NullType can mean deleted substitution (and data need to be skipped)
@@ -113,10 +144,13 @@ pub fn read_template<'a>(
         substitution_array.push(BinXMLDeserializedTokens::Value(value));
     }
 
-    Ok(BinXmlTemplateRef {
-        template_def_offset: template_definition_data_offset,
-        substitution_array,
-    })
+    Ok((
+        BinXmlTemplateRef {
+            template_def_offset: template_definition_data_offset,
+            substitution_array,
+        },
+        None,
+    ))
 }
 
 pub fn read_template_definition_header(
@@ -219,6 +253,22 @@ pub fn read_processing_instruction_data(cursor: &mut Cursor<&[u8]>) -> Result<St
     Ok(data)
 }
 
+pub fn read_cdata_section(cursor: &mut Cursor<&[u8]>) -> Result<String> {
+    trace!("Offset `0x{:08x}` - CDataSection", cursor.position());
+
+    let data = try_read!(cursor, len_prefixed_utf_16_str, "cdata_section")?.unwrap_or_default();
+    trace!("CDataSection - {}", data);
+    Ok(data)
+}
+
+pub fn read_character_reference(cursor: &mut Cursor<&[u8]>) -> Result<u16> {
+    trace!("Offset `0x{:08x}` - CharReference", cursor.position());
+
+    let value = try_read!(cursor, u16, "character_reference")?;
+    trace!("CharReference - {}", value);
+    Ok(value)
+}
+
 pub fn read_substitution_descriptor(
     cursor: &mut Cursor<&[u8]>,
     optional: bool,
@@ -278,7 +328,8 @@ pub fn read_open_start_element(
     // This is a heuristic, sometimes `dependency_identifier` is not present even though it should have been.
     // This will result in interpreting garbage bytes as the data size.
     // We try to recover from this situation by rolling back the cursor and trying again, without reading the `dependency_identifier`.
-    if let Some(c) = chunk {
+    // When it wasn't read in the first place there is nothing to roll back.
+    if let (Some(c), false) = (chunk, is_substitution) {
         if data_size >= c.data.len() as u32 {
             warn!(
                 "Detected a case where `dependency_identifier` should not have been read. \
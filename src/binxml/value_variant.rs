@@ -1,4 +1,7 @@
-use crate::err::{DeserializationError, DeserializationResult as Result, WrappedIoError};
+use crate::err::{
+    DeserializationError, DeserializationResult as Result, SerializationError, SerializationResult,
+    WrappedIoError,
+};
 use encoding::EncodingRef;
 
 pub use byteorder::{LittleEndian, ReadBytesExt};
@@ -9,14 +12,14 @@ use winstructs::guid::Guid;
 
 use crate::model::deserialized::BinXMLDeserializedTokens;
 use crate::utils::{
-    read_ansi_encoded_string, read_len_prefixed_utf16_string, read_null_terminated_utf16_string,
-    read_systemtime, read_utf16_by_size,
+    read_ansi_encoded_string, read_ansi_encoded_string_array, read_len_prefixed_utf16_string,
+    read_null_terminated_utf16_string, read_systemtime, read_utf16_by_size,
 };
 use chrono::{DateTime, Utc};
 use log::trace;
 use serde_json::{json, Value};
 use std::borrow::Cow;
-use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
 use std::string::ToString;
 use winstructs::security::Sid;
 
@@ -53,9 +56,10 @@ pub enum BinXmlValue<'a> {
     EvtHandle,
     // Because of the recursive type, we instantiate this enum via a method of the Deserializer
     BinXmlType(Vec<BinXMLDeserializedTokens<'a>>),
-    EvtXml,
+    /// An XML document, as text.
+    EvtXml(String),
     StringArrayType(Vec<String>),
-    AnsiStringArrayType,
+    AnsiStringArrayType(Vec<String>),
     Int8ArrayType(Vec<i8>),
     UInt8ArrayType(Vec<u8>),
     Int16ArrayType(Vec<i16>),
@@ -67,17 +71,18 @@ pub enum BinXmlValue<'a> {
     Real32ArrayType(Vec<f32>),
     Real64ArrayType(Vec<f64>),
     BoolArrayType(Vec<bool>),
-    BinaryArrayType,
+    /// The boundaries of the elements are not recorded, so the array is kept as a single blob.
+    BinaryArrayType(&'a [u8]),
     GuidArrayType(Vec<Guid>),
-    SizeTArrayType,
+    SizeTArrayType(Vec<Cow<'a, str>>),
     FileTimeArrayType(Vec<DateTime<Utc>>),
     SysTimeArrayType(Vec<DateTime<Utc>>),
     SidArrayType(Vec<Sid>),
     HexInt32ArrayType(Vec<Cow<'a, str>>),
     HexInt64ArrayType(Vec<Cow<'a, str>>),
-    EvtArrayHandle,
-    BinXmlArrayType,
-    EvtXmlArrayType,
+    EvtArrayHandle(Vec<Cow<'a, str>>),
+    BinXmlArrayType(Vec<Vec<BinXMLDeserializedTokens<'a>>>),
+    EvtXmlArrayType(Vec<String>),
 }
 
 #[derive(Debug, PartialOrd, PartialEq, Eq, Clone)]
@@ -182,6 +187,9 @@ impl BinXmlValueType {
             0x93 => Some(BinXmlValueType::SidArrayType),
             0x94 => Some(BinXmlValueType::HexInt32ArrayType),
             0x95 => Some(BinXmlValueType::HexInt64ArrayType),
+            0xa0 => Some(BinXmlValueType::EvtHandleArray),
+            0xa1 => Some(BinXmlValueType::BinXmlArrayType),
+            0xa3 => Some(BinXmlValueType::EvtXmlArrayType),
             _ => None,
         }
     }
@@ -306,26 +314,57 @@ impl<'a> BinXmlValue<'a> {
 
                 BinXmlValue::BinXmlType(tokens)
             }
-            (BinXmlValueType::BinaryType, Some(sz)) => {
-                // Borrow the underlying data from the cursor, and return a ref to it.
-                let data = *cursor.get_ref();
-                let bytes =
-                    &data[cursor.position() as usize..(cursor.position() + u64::from(sz)) as usize];
+            (BinXmlValueType::BinXmlArrayType, Some(sz)) => {
+                let end = cursor.position() + u64::from(sz);
+                let mut fragments = vec![];
 
-                cursor.seek(SeekFrom::Current(i64::from(sz))).map_err(|e| {
-                    WrappedIoError::io_error_with_message(
-                        e,
-                        "failed to read binary value_variant",
+                while cursor.position() < end {
+                    let start = cursor.position();
+                    let tokens = BinXmlDeserializer::read_binxml_fragment(
                         cursor,
-                    )
-                })?;
+                        chunk,
+                        Some((end - start) as u32),
+                        true,
+                        ansi_codec,
+                    )?;
+
+                    // An empty fragment would never advance the cursor.
+                    if cursor.position() == start {
+                        break;
+                    }
 
-                BinXmlValue::BinaryType(bytes)
+                    fragments.push(tokens);
+                }
+
+                BinXmlValue::BinXmlArrayType(fragments)
+            }
+            (BinXmlValueType::EvtXmlType, Some(sz)) => BinXmlValue::EvtXml(
+                read_utf16_by_size(cursor, u64::from(sz))
+                    .map_err(|e| {
+                        WrappedIoError::io_error_with_message(
+                            e,
+                            format!("failed to read sized utf-16 xml (size `{}`)", sz),
+                            cursor,
+                        )
+                    })?
+                    .unwrap_or_default(),
+            ),
+            (BinXmlValueType::EvtXmlType, None) => BinXmlValue::EvtXml(
+                try_read!(cursor, len_prefixed_utf_16_str, "<xml_value>")?.unwrap_or_default(),
+            ),
+            (BinXmlValueType::BinaryType, Some(sz)) => {
+                BinXmlValue::BinaryType(read_borrowed_bytes(cursor, sz)?)
             }
             // The array types are always sized.
             (BinXmlValueType::StringArrayType, Some(sz)) => BinXmlValue::StringArrayType(
                 try_read_sized_array!(cursor, null_terminated_utf_16_str, sz),
             ),
+            (BinXmlValueType::AnsiStringArrayType, Some(sz)) => BinXmlValue::AnsiStringArrayType(
+                read_ansi_encoded_string_array(cursor, u64::from(sz), ansi_codec)?,
+            ),
+            (BinXmlValueType::EvtXmlArrayType, Some(sz)) => BinXmlValue::EvtXmlArrayType(
+                try_read_sized_array!(cursor, null_terminated_utf_16_str, sz),
+            ),
             (BinXmlValueType::Int8ArrayType, Some(sz)) => {
                 BinXmlValue::Int8ArrayType(try_read_sized_array!(cursor, i8, sz))
             }
@@ -368,9 +407,26 @@ impl<'a> BinXmlValue<'a> {
             (BinXmlValueType::BoolArrayType, Some(sz)) => {
                 BinXmlValue::BoolArrayType(try_read_sized_array!(cursor, bool, sz))
             }
+            (BinXmlValueType::BinaryArrayType, Some(sz)) => {
+                BinXmlValue::BinaryArrayType(read_borrowed_bytes(cursor, sz)?)
+            }
             (BinXmlValueType::GuidArrayType, Some(sz)) => {
                 BinXmlValue::GuidArrayType(try_read_sized_array!(cursor, guid, sz))
             }
+            // The size of `size_t` (and of handles) is not recorded,
+            // 64-bit values are assumed whenever the size of the array allows it.
+            (BinXmlValueType::SizeTArrayType, Some(sz)) if sz % 8 == 0 => {
+                BinXmlValue::SizeTArrayType(try_read_sized_array!(cursor, hex64, sz))
+            }
+            (BinXmlValueType::SizeTArrayType, Some(sz)) => {
+                BinXmlValue::SizeTArrayType(try_read_sized_array!(cursor, hex32, sz))
+            }
+            (BinXmlValueType::EvtHandleArray, Some(sz)) if sz % 8 == 0 => {
+                BinXmlValue::EvtArrayHandle(try_read_sized_array!(cursor, hex64, sz))
+            }
+            (BinXmlValueType::EvtHandleArray, Some(sz)) => {
+                BinXmlValue::EvtArrayHandle(try_read_sized_array!(cursor, hex32, sz))
+            }
             (BinXmlValueType::FileTimeArrayType, Some(sz)) => {
                 BinXmlValue::FileTimeArrayType(try_read_sized_array!(cursor, filetime, sz))
             }
@@ -400,6 +456,28 @@ impl<'a> BinXmlValue<'a> {
     }
 }
 
+/// Borrows `size` bytes from the data underlying `cursor`, and advances it past them.
+fn read_borrowed_bytes<'a>(cursor: &mut Cursor<&'a [u8]>, size: u16) -> Result<&'a [u8]> {
+    let data = *cursor.get_ref();
+    let start = cursor.position() as usize;
+
+    let bytes = data.get(start..start + usize::from(size)).ok_or_else(|| {
+        WrappedIoError::io_error_with_message(
+            io::Error::from(io::ErrorKind::UnexpectedEof),
+            "failed to read binary value_variant",
+            cursor,
+        )
+    })?;
+
+    cursor
+        .seek(SeekFrom::Current(i64::from(size)))
+        .map_err(|e| {
+            WrappedIoError::io_error_with_message(e, "failed to read binary value_variant", cursor)
+        })?;
+
+    Ok(bytes)
+}
+
 fn to_delimited_list<N: ToString>(ns: impl AsRef<Vec<N>>) -> String {
     ns.as_ref()
         .iter()
@@ -408,9 +486,11 @@ fn to_delimited_list<N: ToString>(ns: impl AsRef<Vec<N>>) -> String {
         .join(",")
 }
 
-impl<'c> From<BinXmlValue<'c>> for serde_json::Value {
-    fn from(value: BinXmlValue<'c>) -> Self {
-        match value {
+impl<'c> TryFrom<BinXmlValue<'c>> for serde_json::Value {
+    type Error = SerializationError;
+
+    fn try_from(value: BinXmlValue<'c>) -> SerializationResult<Self> {
+        Ok(match value {
             BinXmlValue::NullType => Value::Null,
             BinXmlValue::StringType(s) => json!(s),
             BinXmlValue::AnsiStringType(s) => json!(s.into_owned()),
@@ -425,19 +505,21 @@ impl<'c> From<BinXmlValue<'c>> for serde_json::Value {
             BinXmlValue::Real32Type(num) => json!(num),
             BinXmlValue::Real64Type(num) => json!(num),
             BinXmlValue::BoolType(num) => json!(num),
-            BinXmlValue::BinaryType(bytes) => {
+            BinXmlValue::BinaryType(bytes) | BinXmlValue::BinaryArrayType(bytes) => {
                 // Bytes will be formatted as const length of 2 with '0' padding.
                 let repr: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
                 json!(repr)
             }
             BinXmlValue::GuidType(guid) => json!(guid.to_string()),
-            //            BinXmlValue::SizeTType(sz) => json!(sz.to_string()),
+            BinXmlValue::SizeTType(sz) => json!(sz),
             BinXmlValue::FileTimeType(tm) => json!(tm.format(DATETIME_FORMAT).to_string()),
             BinXmlValue::SysTimeType(tm) => json!(tm.format(DATETIME_FORMAT).to_string()),
             BinXmlValue::SidType(sid) => json!(sid.to_string()),
             BinXmlValue::HexInt32Type(hex_string) => json!(hex_string),
             BinXmlValue::HexInt64Type(hex_string) => json!(hex_string),
+            BinXmlValue::EvtXml(xml) => json!(xml),
             BinXmlValue::StringArrayType(s) => json!(s),
+            BinXmlValue::AnsiStringArrayType(s) => json!(s),
             BinXmlValue::Int8ArrayType(numbers) => json!(numbers),
             BinXmlValue::UInt8ArrayType(numbers) => json!(numbers),
             BinXmlValue::Int16ArrayType(numbers) => json!(numbers),
@@ -459,21 +541,22 @@ impl<'c> From<BinXmlValue<'c>> for serde_json::Value {
             }
             BinXmlValue::HexInt32ArrayType(hex_strings) => json!(hex_strings),
             BinXmlValue::HexInt64ArrayType(hex_strings) => json!(hex_strings),
-            BinXmlValue::EvtHandle => {
-                panic!("Unsupported conversion, call `expand_templates` first")
-            }
-            BinXmlValue::BinXmlType(_) => {
-                panic!("Unsupported conversion, call `expand_templates` first")
-            }
-            BinXmlValue::EvtXml => panic!("Unsupported conversion, call `expand_templates` first"),
-            _ => unimplemented!("{:?}", value),
-        }
+            BinXmlValue::SizeTArrayType(hex_strings) => json!(hex_strings),
+            BinXmlValue::EvtArrayHandle(hex_strings) => json!(hex_strings),
+            BinXmlValue::EvtXmlArrayType(xmls) => json!(xmls),
+            // Binary XML is expanded by `expand_templates`, and handles have no meaningful value.
+            BinXmlValue::EvtHandle
+            | BinXmlValue::BinXmlType(_)
+            | BinXmlValue::BinXmlArrayType(_) => return Err(value.unsupported()),
+        })
     }
 }
 
-impl<'c> From<&'c BinXmlValue<'c>> for serde_json::Value {
-    fn from(value: &'c BinXmlValue) -> Self {
-        match value {
+impl<'c> TryFrom<&'c BinXmlValue<'c>> for serde_json::Value {
+    type Error = SerializationError;
+
+    fn try_from(value: &'c BinXmlValue) -> SerializationResult<Self> {
+        Ok(match value {
             BinXmlValue::NullType => Value::Null,
             BinXmlValue::StringType(s) => json!(s),
             BinXmlValue::AnsiStringType(s) => json!(s.as_ref()),
@@ -488,19 +571,21 @@ impl<'c> From<&'c BinXmlValue<'c>> for serde_json::Value {
             BinXmlValue::Real32Type(num) => json!(num),
             BinXmlValue::Real64Type(num) => json!(num),
             BinXmlValue::BoolType(num) => json!(num),
-            BinXmlValue::BinaryType(bytes) => {
+            BinXmlValue::BinaryType(bytes) | BinXmlValue::BinaryArrayType(bytes) => {
                 // Bytes will be formatted as const length of 2 with '0' padding.
                 let repr: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
                 json!(repr)
             }
             BinXmlValue::GuidType(guid) => json!(guid.to_string()),
-            //            BinXmlValue::SizeTType(sz) => json!(sz.to_string()),
+            BinXmlValue::SizeTType(sz) => json!(sz),
             BinXmlValue::FileTimeType(tm) => json!(tm.format(DATETIME_FORMAT).to_string()),
             BinXmlValue::SysTimeType(tm) => json!(tm.format(DATETIME_FORMAT).to_string()),
             BinXmlValue::SidType(sid) => json!(sid.to_string()),
             BinXmlValue::HexInt32Type(hex_string) => json!(hex_string),
             BinXmlValue::HexInt64Type(hex_string) => json!(hex_string),
+            BinXmlValue::EvtXml(xml) => json!(xml),
             BinXmlValue::StringArrayType(s) => json!(s),
+            BinXmlValue::AnsiStringArrayType(s) => json!(s),
             BinXmlValue::Int8ArrayType(numbers) => json!(numbers),
             BinXmlValue::UInt8ArrayType(numbers) => json!(numbers),
             BinXmlValue::Int16ArrayType(numbers) => json!(numbers),
@@ -522,21 +607,22 @@ impl<'c> From<&'c BinXmlValue<'c>> for serde_json::Value {
             }
             BinXmlValue::HexInt32ArrayType(hex_strings) => json!(hex_strings),
             BinXmlValue::HexInt64ArrayType(hex_strings) => json!(hex_strings),
-            BinXmlValue::EvtHandle => {
-                panic!("Unsupported conversion, call `expand_templates` first")
-            }
-            BinXmlValue::BinXmlType(_) => {
-                panic!("Unsupported conversion, call `expand_templates` first")
-            }
-            BinXmlValue::EvtXml => panic!("Unsupported conversion, call `expand_templates` first"),
-            _ => unimplemented!("{:?}", value),
-        }
+            BinXmlValue::SizeTArrayType(hex_strings) => json!(hex_strings),
+            BinXmlValue::EvtArrayHandle(hex_strings) => json!(hex_strings),
+            BinXmlValue::EvtXmlArrayType(xmls) => json!(xmls),
+            // Binary XML is expanded by `expand_templates`, and handles have no meaningful value.
+            BinXmlValue::EvtHandle
+            | BinXmlValue::BinXmlType(_)
+            | BinXmlValue::BinXmlArrayType(_) => return Err(value.unsupported()),
+        })
     }
 }
 
 impl<'a> BinXmlValue<'a> {
-    pub fn as_cow_str(&self) -> Cow<str> {
-        match self {
+    /// Returns the value as a string, fails for values which have no textual representation
+    /// (handles, and binary XML which was not expanded by `expand_templates`).
+    pub fn as_cow_str(&self) -> SerializationResult<Cow<'_, str>> {
+        Ok(match self {
             BinXmlValue::NullType => Cow::Borrowed(""),
             BinXmlValue::StringType(s) => Cow::Borrowed(s.as_ref()),
             BinXmlValue::AnsiStringType(s) => Cow::Borrowed(s.as_ref()),
@@ -551,7 +637,7 @@ impl<'a> BinXmlValue<'a> {
             BinXmlValue::Real32Type(num) => Cow::Owned(num.to_string()),
             BinXmlValue::Real64Type(num) => Cow::Owned(num.to_string()),
             BinXmlValue::BoolType(num) => Cow::Owned(num.to_string()),
-            BinXmlValue::BinaryType(bytes) => {
+            BinXmlValue::BinaryType(bytes) | BinXmlValue::BinaryArrayType(bytes) => {
                 // Bytes will be formatted as const length of 2 with '0' padding.
                 let mut repr = String::with_capacity(bytes.len() * 2);
 
@@ -568,7 +654,9 @@ impl<'a> BinXmlValue<'a> {
             BinXmlValue::SidType(sid) => Cow::Owned(sid.to_string()),
             BinXmlValue::HexInt32Type(hex_string) => hex_string.clone(),
             BinXmlValue::HexInt64Type(hex_string) => hex_string.clone(),
+            BinXmlValue::EvtXml(xml) => Cow::Borrowed(xml.as_ref()),
             BinXmlValue::StringArrayType(s) => Cow::Owned(s.join(",")),
+            BinXmlValue::AnsiStringArrayType(s) => Cow::Owned(s.join(",")),
             BinXmlValue::Int8ArrayType(numbers) => Cow::Owned(to_delimited_list(numbers)),
             BinXmlValue::UInt8ArrayType(numbers) => Cow::Owned(to_delimited_list(numbers)),
             BinXmlValue::Int16ArrayType(numbers) => Cow::Owned(to_delimited_list(numbers)),
@@ -586,14 +674,165 @@ impl<'a> BinXmlValue<'a> {
             BinXmlValue::SidArrayType(sids) => Cow::Owned(to_delimited_list(sids)),
             BinXmlValue::HexInt32ArrayType(hex_strings) => Cow::Owned(hex_strings.join(",")),
             BinXmlValue::HexInt64ArrayType(hex_strings) => Cow::Owned(hex_strings.join(",")),
-            BinXmlValue::EvtHandle => {
-                panic!("Unsupported conversion, call `expand_templates` first")
-            }
-            BinXmlValue::BinXmlType(_) => {
-                panic!("Unsupported conversion, call `expand_templates` first")
-            }
-            BinXmlValue::EvtXml => panic!("Unsupported conversion, call `expand_templates` first"),
-            _ => unimplemented!("{:?}", self),
+            BinXmlValue::SizeTArrayType(hex_strings) => Cow::Owned(hex_strings.join(",")),
+            BinXmlValue::EvtArrayHandle(hex_strings) => Cow::Owned(hex_strings.join(",")),
+            BinXmlValue::EvtXmlArrayType(xmls) => Cow::Owned(xmls.join(",")),
+            // Binary XML is expanded by `expand_templates`, and handles have no meaningful value.
+            BinXmlValue::EvtHandle
+            | BinXmlValue::BinXmlType(_)
+            | BinXmlValue::BinXmlArrayType(_) => return Err(self.unsupported()),
+        })
+    }
+
+    fn unsupported(&self) -> SerializationError {
+        let value_type = match self {
+            BinXmlValue::EvtHandle => "EvtHandle",
+            BinXmlValue::BinXmlType(_) => "BinXml",
+            BinXmlValue::BinXmlArrayType(_) => "BinXmlArray",
+            _ => "unknown",
+        };
+
+        SerializationError::UnsupportedValueType { value_type }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use serde_json::{json, Value};
+
+    use super::{BinXmlValue, BinXmlValueType};
+    use crate::err::{DeserializationResult, SerializationError};
+    use crate::model::deserialized::BinXMLDeserializedTokens;
+    use crate::ParserSettings;
+
+    fn utf16(s: &str) -> Vec<u8> {
+        s.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    fn read_value(value_type: u8, data: &[u8]) -> DeserializationResult<BinXmlValue<'_>> {
+        BinXmlValue::deserialize_value_type(
+            &BinXmlValueType::from_u8(value_type).unwrap(),
+            &mut Cursor::new(data),
+            None,
+            Some(data.len() as u16),
+            ParserSettings::default().get_ansi_codec(),
+        )
+    }
+
+    #[test]
+    fn test_evt_xml() {
+        let data = utf16("<Data>1</Data>");
+
+        let value = read_value(0x23, &data).unwrap();
+        assert_eq!(value, BinXmlValue::EvtXml("<Data>1</Data>".to_string()));
+        assert_eq!(Value::try_from(&value).unwrap(), json!("<Data>1</Data>"));
+
+        let data = [utf16("<a/>\0"), utf16("<b/>\0")].concat();
+
+        let value = read_value(0xa3, &data).unwrap();
+        assert_eq!(Value::try_from(&value).unwrap(), json!(["<a/>", "<b/>"]));
+        assert_eq!(value.as_cow_str().unwrap(), "<a/>,<b/>");
+    }
+
+    #[test]
+    fn test_ansi_string_array() {
+        let value = read_value(0x82, b"first\0second\0").unwrap();
+
+        assert_eq!(
+            value,
+            BinXmlValue::AnsiStringArrayType(vec!["first".to_string(), "second".to_string()])
+        );
+        assert_eq!(value.as_cow_str().unwrap(), "first,second");
+    }
+
+    #[test]
+    fn test_binary_array() {
+        let value = read_value(0x8e, &[0xde, 0xad, 0xbe, 0xef]).unwrap();
+
+        assert_eq!(Value::try_from(&value).unwrap(), json!("DEADBEEF"));
+    }
+
+    #[test]
+    fn test_size_t_and_handle_arrays() {
+        let data = [1u64.to_le_bytes(), 0xffu64.to_le_bytes()].concat();
+        let value = read_value(0x90, &data).unwrap();
+        assert_eq!(Value::try_from(&value).unwrap(), json!(["0x1", "0xff"]));
+
+        let data = [
+            1u32.to_le_bytes(),
+            0xffu32.to_le_bytes(),
+            2u32.to_le_bytes(),
+        ]
+        .concat();
+        let value = read_value(0x90, &data).unwrap();
+        assert_eq!(value.as_cow_str().unwrap(), "0x1,0xff,0x2");
+
+        let data = 1u64.to_le_bytes();
+        let value = read_value(0xa0, &data).unwrap();
+        assert_eq!(Value::try_from(value).unwrap(), json!(["0x1"]));
+    }
+
+    #[test]
+    fn test_binxml_array() {
+        // Two fragments, each with a single (length prefixed) string value.
+        let mut data = vec![];
+        for s in ["first", "second"] {
+            data.extend([0x05, 0x01]);
+            data.extend((s.len() as u16).to_le_bytes());
+            data.extend(utf16(s));
+            data.push(0x00);
+        }
+
+        let value = read_value(0xa1, &data).unwrap();
+
+        let string_fragment = |s: &str| {
+            vec![
+                BinXMLDeserializedTokens::Value(BinXmlValue::StringType(s.to_string())),
+                BinXMLDeserializedTokens::EndOfStream,
+            ]
+        };
+        assert_eq!(
+            value,
+            BinXmlValue::BinXmlArrayType(vec![string_fragment("first"), string_fragment("second")])
+        );
+    }
+
+    #[test]
+    fn test_truncated_binary_is_an_error() {
+        let data = [0xde, 0xad];
+
+        let value = BinXmlValue::deserialize_value_type(
+            &BinXmlValueType::BinaryType,
+            &mut Cursor::new(&data[..]),
+            None,
+            Some(4),
+            ParserSettings::default().get_ansi_codec(),
+        );
+
+        assert!(value.is_err());
+    }
+
+    #[test]
+    fn test_unexpanded_values_are_not_serialized() {
+        for value in [
+            BinXmlValue::EvtHandle,
+            BinXmlValue::BinXmlType(vec![]),
+            BinXmlValue::BinXmlArrayType(vec![]),
+        ] {
+            assert!(matches!(
+                value.as_cow_str(),
+                Err(SerializationError::UnsupportedValueType { .. })
+            ));
+            assert!(matches!(
+                Value::try_from(&value),
+                Err(SerializationError::UnsupportedValueType { .. })
+            ));
+            assert!(matches!(
+                Value::try_from(value),
+                Err(SerializationError::UnsupportedValueType { .. })
+            ));
         }
     }
 }
@@ -0,0 +1,200 @@
+use crate::binxml::value_variant::BinXmlValue;
+use crate::err::Result;
+use crate::evtx_query::{Document, Selector};
+use crate::evtx_record::RecordId;
+use crate::model::xml::XmlModel;
+
+use chrono::{DateTime, Utc};
+
+/// The separator used when a column selects more than one value.
+const VALUES_SEPARATOR: &str = ", ";
+
+#[derive(Debug, Clone, PartialEq)]
+enum CsvColumn {
+    RecordId,
+    Timestamp,
+    Selector(Selector),
+}
+
+/// Produces one delimited row per record, with user selected columns.
+///
+/// Columns are either `record_id` and `timestamp` (read from the record header),
+/// or paths relative to the root element of the record, using the same syntax as `EvtxQuery`
+/// (for example `System/EventID`, `System/Provider/@Name` or `EventData/Data[@Name='IpAddress']`).
+/// When a path selects several values, they are joined with `, `, and when it selects none,
+/// the field is empty.
+///
+/// Fields are quoted (as described by RFC 4180) when they contain the delimiter, a quote or a line break.
+///
+/// ```rust
+/// # use evtx::{CsvOutput, EvtxParser};
+/// # let fp = std::path::PathBuf::from(format!("{}/samples/security.evtx", std::env::var("CARGO_MANIFEST_DIR").unwrap()));
+/// let csv = CsvOutput::new(["record_id", "System/EventID", "EventData/Data[@Name='TargetUserName']"]).unwrap();
+/// let mut parser = EvtxParser::from_path(fp).unwrap();
+///
+/// println!("{}", csv.header());
+/// for row in parser.serialized_records(|record| record?.into_csv(&csv)) {
+///     println!("{}", row.unwrap().data);
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvOutput {
+    names: Vec<String>,
+    columns: Vec<CsvColumn>,
+    delimiter: char,
+}
+
+impl CsvOutput {
+    /// Parses the columns, returning `EvtxError::InvalidQuery` if a path is invalid.
+    pub fn new<S: AsRef<str>>(columns: impl IntoIterator<Item = S>) -> Result<Self> {
+        let mut names = vec![];
+        let mut parsed = vec![];
+
+        for column in columns {
+            let column = column.as_ref();
+
+            parsed.push(match column {
+                "record_id" => CsvColumn::RecordId,
+                "timestamp" => CsvColumn::Timestamp,
+                path => CsvColumn::Selector(Selector::parse(path)?),
+            });
+            names.push(column.to_string());
+        }
+
+        Ok(CsvOutput {
+            names,
+            columns: parsed,
+            delimiter: ',',
+        })
+    }
+
+    /// Sets the delimiter between fields, `','` by default (use `'\t'` for TSV).
+    pub fn delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = delimiter;
+
+        self
+    }
+
+    pub fn get_delimiter(&self) -> char {
+        self.delimiter
+    }
+
+    /// The header row, made of the column names.
+    pub fn header(&self) -> String {
+        self.join(self.names.iter().map(String::as_str))
+    }
+
+    /// The row of the record described by `model` (see `create_record_model`).
+    pub(crate) fn row(
+        &self,
+        event_record_id: RecordId,
+        timestamp: DateTime<Utc>,
+        model: &[XmlModel],
+    ) -> String {
+        let document = Document::from_model(model, hex_as_written);
+
+        let fields: Vec<String> = self
+            .columns
+            .iter()
+            .map(|column| match column {
+                CsvColumn::RecordId => event_record_id.to_string(),
+                CsvColumn::Timestamp => timestamp.format("%Y-%m-%dT%H:%M:%S%.6fZ").to_string(),
+                CsvColumn::Selector(selector) => document.select(selector).join(VALUES_SEPARATOR),
+            })
+            .collect();
+
+        self.join(fields.iter().map(String::as_str))
+    }
+
+    fn join<'a>(&self, fields: impl Iterator<Item = &'a str>) -> String {
+        let mut row = String::new();
+
+        for (index, field) in fields.enumerate() {
+            if index > 0 {
+                row.push(self.delimiter);
+            }
+
+            let needs_quotes = field
+                .chars()
+                .any(|c| c == self.delimiter || c == '"' || c == '\n' || c == '\r');
+
+            if needs_quotes {
+                row.push('"');
+                row.push_str(&field.replace('"', "\"\""));
+                row.push('"');
+            } else {
+                row.push_str(field);
+            }
+        }
+
+        row
+    }
+}
+
+/// Hexadecimal values (such as `Keywords`) are written as they appear in the record,
+/// rather than as the decimal numbers they are compared as.
+fn hex_as_written(value: &BinXmlValue) -> Option<String> {
+    match value {
+        BinXmlValue::HexInt32Type(s) | BinXmlValue::HexInt64Type(s) => Some(s.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ensure_env_logger_initialized;
+    use crate::EvtxParser;
+
+    #[test]
+    fn test_quotes_fields() {
+        let csv = CsvOutput::new(["record_id"]).unwrap();
+        assert_eq!(
+            csv.join(["a", "b,c", "d\"e", "f\ng"].into_iter()),
+            "a,\"b,c\",\"d\"\"e\",\"f\ng\""
+        );
+
+        let tsv = csv.delimiter('\t');
+        assert_eq!(
+            tsv.join(["a", "b,c", "d\te"].into_iter()),
+            "a\tb,c\t\"d\te\""
+        );
+    }
+
+    #[test]
+    fn test_rows_of_records() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+        let mut parser = EvtxParser::from_buffer(evtx_file.to_vec()).unwrap();
+
+        let csv = CsvOutput::new([
+            "record_id",
+            "timestamp",
+            "System/EventID",
+            "System/Provider/@Name",
+            "System/Keywords",
+            "EventData/Data[@Name='TargetUserName']",
+            "EventData/Data[@Name='DoesNotExist']",
+        ])
+        .unwrap();
+
+        let rows: Vec<String> = parser
+            .serialized_records(|record| record?.into_csv(&csv))
+            .take(2)
+            .map(|row| row.unwrap().data)
+            .collect();
+
+        assert_eq!(
+            rows,
+            vec![
+                "1,2016-07-08T18:12:51.681640Z,4608,Microsoft-Windows-Security-Auditing,0x8020000000000000,,",
+                "2,2016-07-08T18:12:51.681640Z,4624,Microsoft-Windows-Security-Auditing,0x8020000000000000,SYSTEM,",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_column() {
+        assert!(CsvOutput::new(["System/EventID[="]).is_err());
+    }
+}
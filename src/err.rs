@@ -6,6 +6,7 @@ use crate::utils::dump_stream;
 use crate::FileOffset;
 use log::error;
 
+use crate::evtx_chunk::RecordRecovery;
 use crate::evtx_record::RecordId;
 use std::error::Error as StdError;
 use std::io;
@@ -136,6 +137,15 @@ pub enum DeserializationError {
     #[error("Invalid EVTX record header magic, expected `2a2a0000`, found `{magic:2X?}`")]
     InvalidEvtxRecordHeaderMagic { magic: [u8; 4] },
 
+    #[error("EVTX record size `{size}` does not match the size copy at the end of the record")]
+    InvalidEvtxRecordSize { size: u32 },
+
+    #[error("EVTX record size `{size}` is smaller than the record header")]
+    EvtxRecordTooSmall { size: u32 },
+
+    #[error("EVTX record size `{size}` runs past the end of the chunk")]
+    EvtxRecordPastChunkEnd { size: u32 },
+
     #[error("Invalid EVTX chunk header magic, expected `ElfChnk0`, found `{magic:2X?}`")]
     InvalidEvtxChunkMagic { magic: [u8; 8] },
 
@@ -186,6 +196,11 @@ pub enum SerializationError {
 
     #[error("Unimplemented: {message}")]
     Unimplemented { message: String },
+
+    #[error(
+        "Values of type `{value_type}` cannot be serialized, binary XML must be expanded first"
+    )]
+    UnsupportedValueType { value_type: &'static str },
 }
 
 #[derive(Debug, Error)]
@@ -217,12 +232,18 @@ pub enum ChunkError {
     #[error("Failed to seek to start of chunk.")]
     FailedToSeekToChunk(io::Error),
 
+    #[error("Failed to read chunk data.")]
+    FailedToReadChunk(io::Error),
+
     #[error("Failed to parse chunk header")]
     FailedToParseChunkHeader(#[from] DeserializationError),
 
-    #[error("chunk data CRC32 invalid")]
+    #[error("chunk data CRC32 invalid (expected `{expected:#010x}`, found `{found:#010x}`)")]
     InvalidChunkChecksum { expected: u32, found: u32 },
 
+    #[error("chunk header CRC32 invalid (expected `{expected:#010x}`, found `{found:#010x}`)")]
+    InvalidChunkHeaderChecksum { expected: u32, found: u32 },
+
     #[error("Failed to build string cache")]
     FailedToBuildStringCache { source: DeserializationError },
 
@@ -250,6 +271,39 @@ pub enum EvtxError {
     #[error("Failed to parse chunk number {chunk_id}")]
     FailedToParseChunk { chunk_id: u64, source: ChunkError },
 
+    #[error("Failed to parse carved chunk at offset {offset}")]
+    FailedToParseCarvedChunk { offset: u64, source: ChunkError },
+
+    #[error(
+        "Skipped corrupt record data at chunk offsets 0x{:08x}..0x{:08x}",
+        .recovery.skipped_from,
+        .recovery.resumed_at
+    )]
+    SkippedCorruptRecordData {
+        recovery: RecordRecovery,
+        source: DeserializationError,
+    },
+
+    #[error("Checkpoint was taken from a different file (or the file was overwritten since)")]
+    InvalidCheckpoint { expected: u32, found: u32 },
+
+    #[error("Invalid query (at position {position}): {message}")]
+    InvalidQuery { position: usize, message: String },
+
+    #[error("Invalid sigma rule: {message}")]
+    InvalidSigmaRule { message: String },
+
+    #[error("Failed to deserialize record: {message}")]
+    FailedToDeserializeRecord { message: String },
+
+    #[cfg(feature = "arrow")]
+    #[error("Failed to build arrow record batch")]
+    ArrowError(#[from] arrow_schema::ArrowError),
+
+    #[cfg(feature = "sqlite")]
+    #[error("Failed to write to sqlite database")]
+    SqliteError(#[from] rusqlite::Error),
+
     #[error("Failed to parse record number {record_id}")]
     FailedToParseRecord {
         record_id: RecordId,
@@ -284,7 +338,6 @@ impl EvtxError {
     }
 }
 
-
 /// Errors on unimplemented functions instead on panicking.
 #[macro_export]
 macro_rules! unimplemented_fn {
@@ -0,0 +1,397 @@
+use crate::evtx_chunk::{EvtxChunkData, EVTX_CHUNK_HEADER_SIZE};
+use crate::evtx_parser::{ReadSeek, EVTX_CHUNK_SIZE, EVTX_FILE_HEADER_SIZE};
+use crate::evtx_record::{EvtxRecordHeader, RecordId};
+use crate::evtx_system::SystemFields;
+use crate::{EvtxParser, ParserSettings};
+
+use chrono::{DateTime, Duration, Utc};
+use log::debug;
+use serde::Serialize;
+use std::io::Cursor;
+use std::sync::Arc;
+
+/// The provider of the events which are written when a log is cleared.
+const EVENTLOG_PROVIDER_NAME: &str = "Microsoft-Windows-Eventlog";
+/// "The audit log was cleared" (Security log).
+const SECURITY_LOG_CLEARED_EVENT_ID: u16 = 1102;
+/// "The log file was cleared" (System log).
+const LOG_CLEARED_EVENT_ID: u16 = 104;
+/// Records are not always written in the order of their timestamps (by up to a few seconds),
+/// so a record is only considered out of order if it is older than this.
+const TIMESTAMP_TOLERANCE_SECONDS: i64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingKind {
+    /// A record whose id is not larger than the id of the record before it in the chunk.
+    NonMonotonicRecordId,
+    /// A record which was written (well) before the record preceding it.
+    NonMonotonicTimestamp,
+    /// A record whose id is outside of the range declared in the header of its chunk.
+    RecordIdOutsideChunkRange,
+    /// A chunk with valid checksums, whose `free_space_offset` does not match its records.
+    ImpossibleFreeSpaceOffset,
+    /// A gap between the record numbers of consecutive chunks.
+    RecordNumberGap,
+    /// An event which is written when the log is cleared.
+    LogCleared,
+}
+
+/// A single sign of tampering found by `EvtxParser::analyze`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    pub kind: FindingKind,
+    pub chunk_number: u64,
+    /// Offset in the file of the record (or the chunk header) the finding refers to.
+    pub offset: u64,
+    pub record_id: Option<RecordId>,
+    pub description: String,
+}
+
+/// Summary of a chunk used to compare consecutive chunks.
+struct ChunkSummary {
+    chunk_number: u64,
+    first_event_record_number: u64,
+    last_event_record_number: u64,
+    /// Offset and timestamp of the first and last records in the chunk.
+    first_record: Option<(u64, DateTime<Utc>)>,
+    last_record: Option<(u64, DateTime<Utc>)>,
+}
+
+fn chunk_offset(chunk_number: u64) -> u64 {
+    EVTX_FILE_HEADER_SIZE as u64 + chunk_number * EVTX_CHUNK_SIZE as u64
+}
+
+fn is_out_of_order(previous: &DateTime<Utc>, current: &DateTime<Utc>) -> bool {
+    *previous - *current > Duration::seconds(TIMESTAMP_TOLERANCE_SECONDS)
+}
+
+/// Returns the event id of a record, if it was written by the event log service.
+fn eventlog_event_id(system: &SystemFields) -> Option<u16> {
+    if system.provider_name.as_deref() != Some(EVENTLOG_PROVIDER_NAME) {
+        return None;
+    }
+
+    system.event_id
+}
+
+/// Checks the records headers of a chunk, and its `free_space_offset`.
+fn analyze_chunk_headers(
+    chunk_number: u64,
+    chunk: &EvtxChunkData,
+    findings: &mut Vec<Finding>,
+) -> ChunkSummary {
+    let chunk_start = chunk_offset(chunk_number);
+    let header = &chunk.header;
+    let mut previous: Option<EvtxRecordHeader> = None;
+    let mut summary = ChunkSummary {
+        chunk_number,
+        first_event_record_number: header.first_event_record_number,
+        last_event_record_number: header.last_event_record_number,
+        first_record: None,
+        last_record: None,
+    };
+    let mut records_end = EVTX_CHUNK_HEADER_SIZE as u64;
+    let mut last_record_offset = None;
+
+    for (record_offset, record_header) in chunk.record_headers() {
+        let record_header = match record_header {
+            Ok(record_header) => record_header,
+            Err(err) => {
+                debug!(
+                    "Chunk {} - Failed to read record header at offset {}: {}",
+                    chunk_number, record_offset, err
+                );
+                break;
+            }
+        };
+
+        let offset = chunk_start + u64::from(record_offset);
+        let mut add_finding = |kind, description| {
+            findings.push(Finding {
+                kind,
+                chunk_number,
+                offset,
+                record_id: Some(record_header.event_record_id),
+                description,
+            })
+        };
+
+        if !(header.first_event_record_id..=header.last_event_record_id)
+            .contains(&record_header.event_record_id)
+        {
+            add_finding(
+                FindingKind::RecordIdOutsideChunkRange,
+                format!(
+                    "Record id {} is outside of the chunk range {}-{}",
+                    record_header.event_record_id,
+                    header.first_event_record_id,
+                    header.last_event_record_id
+                ),
+            );
+        }
+
+        if let Some(previous) = &previous {
+            if record_header.event_record_id <= previous.event_record_id {
+                add_finding(
+                    FindingKind::NonMonotonicRecordId,
+                    format!(
+                        "Record id {} follows record id {}",
+                        record_header.event_record_id, previous.event_record_id
+                    ),
+                );
+            }
+
+            if is_out_of_order(&previous.timestamp, &record_header.timestamp) {
+                add_finding(
+                    FindingKind::NonMonotonicTimestamp,
+                    format!(
+                        "Record written at {} follows a record written at {}",
+                        record_header.timestamp, previous.timestamp
+                    ),
+                );
+            }
+        }
+
+        summary
+            .first_record
+            .get_or_insert((offset, record_header.timestamp));
+        summary.last_record = Some((offset, record_header.timestamp));
+        records_end = u64::from(record_offset) + u64::from(record_header.data_size);
+        last_record_offset = Some(record_offset);
+        previous = Some(record_header);
+    }
+
+    // The checksums would not match if the chunk header was corrupted by accident.
+    if chunk.validate_checksum() {
+        let free_space_offset = u64::from(header.free_space_offset);
+        let mut problems = vec![];
+
+        if !(EVTX_CHUNK_HEADER_SIZE as u64..=EVTX_CHUNK_SIZE as u64).contains(&free_space_offset) {
+            problems.push("it is outside of the chunk".to_string());
+        } else {
+            if records_end != free_space_offset {
+                problems.push(format!("the records end at offset {}", records_end));
+            }
+
+            if let Some(last_record_offset) = last_record_offset {
+                if last_record_offset != header.last_event_record_data_offset {
+                    problems.push(format!(
+                        "the last record is at offset {}, not at offset {}",
+                        last_record_offset, header.last_event_record_data_offset
+                    ));
+                }
+            }
+
+            let free_space = &chunk.data[(free_space_offset as usize).min(chunk.data.len())..];
+            if EvtxRecordHeader::from_reader(&mut Cursor::new(free_space)).is_ok() {
+                problems.push("a record follows it".to_string());
+            }
+        }
+
+        if !problems.is_empty() {
+            findings.push(Finding {
+                kind: FindingKind::ImpossibleFreeSpaceOffset,
+                chunk_number,
+                offset: chunk_start,
+                record_id: None,
+                description: format!(
+                    "Free space offset {} is impossible: {}",
+                    free_space_offset,
+                    problems.join(", ")
+                ),
+            });
+        }
+    }
+
+    summary
+}
+
+/// Compares consecutive chunks (ordered by their record numbers, since the log may wrap around).
+fn analyze_consecutive_chunks(mut summaries: Vec<ChunkSummary>, findings: &mut Vec<Finding>) {
+    summaries
+        .sort_unstable_by_key(|summary| (summary.first_event_record_number, summary.chunk_number));
+
+    for pair in summaries.windows(2) {
+        let (previous, current) = (&pair[0], &pair[1]);
+        let expected = previous.last_event_record_number.saturating_add(1);
+
+        if current.first_event_record_number != expected {
+            findings.push(Finding {
+                kind: FindingKind::RecordNumberGap,
+                chunk_number: current.chunk_number,
+                offset: chunk_offset(current.chunk_number),
+                record_id: None,
+                description: format!(
+                    "Chunk starts at record number {}, but the previous chunk (number {}) ends at record number {}",
+                    current.first_event_record_number,
+                    previous.chunk_number,
+                    previous.last_event_record_number
+                ),
+            });
+        }
+
+        if let (Some((_, previous_timestamp)), Some((offset, timestamp))) =
+            (previous.last_record, current.first_record)
+        {
+            if is_out_of_order(&previous_timestamp, &timestamp) {
+                findings.push(Finding {
+                    kind: FindingKind::NonMonotonicTimestamp,
+                    chunk_number: current.chunk_number,
+                    offset,
+                    record_id: None,
+                    description: format!(
+                        "First record of the chunk was written at {}, before the last record of chunk {} ({})",
+                        timestamp, previous.chunk_number, previous_timestamp
+                    ),
+                });
+            }
+        }
+    }
+}
+
+impl<T: ReadSeek> EvtxParser<T> {
+    /// Looks for signs of tampering or log clearing in the file:
+    /// record ids or timestamps which go backwards, records whose id is outside of the range
+    /// declared by their chunk, chunks with valid checksums but an impossible `free_space_offset`,
+    /// gaps in record numbers between chunks, and the events written when a log is cleared
+    /// (1102 and 104).
+    ///
+    /// These are heuristics, and findings should be reviewed manually.
+    /// Chunks which cannot be read are skipped (see `verify`).
+    pub fn analyze(&mut self) -> Vec<Finding> {
+        let settings = Arc::new(ParserSettings::clone(&self.config).recover_slack_records(false));
+        let mut findings = vec![];
+        let mut summaries = vec![];
+
+        for chunk_number in 0..self.calculated_chunk_count {
+            let mut chunk = match self.allocate_chunk(chunk_number, false) {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => continue,
+                Err(err) => {
+                    debug!("Chunk {} will not be analyzed: {}", chunk_number, err);
+                    continue;
+                }
+            };
+
+            summaries.push(analyze_chunk_headers(chunk_number, &chunk, &mut findings));
+
+            let mut chunk_records = match chunk.parse(Arc::clone(&settings)) {
+                Ok(chunk_records) => chunk_records,
+                Err(err) => {
+                    debug!(
+                        "Chunk {} records will not be analyzed: {}",
+                        chunk_number, err
+                    );
+                    continue;
+                }
+            };
+
+            for record in chunk_records.iter().filter_map(|record| record.ok()) {
+                let offset = chunk_offset(chunk_number) + u64::from(record.record_offset);
+                let system = match record.system() {
+                    Ok(system) => system,
+                    Err(_) => continue,
+                };
+
+                let description = match eventlog_event_id(&system) {
+                    Some(SECURITY_LOG_CLEARED_EVENT_ID) => "The audit log was cleared",
+                    Some(LOG_CLEARED_EVENT_ID) => "The log file was cleared",
+                    _ => continue,
+                };
+
+                findings.push(Finding {
+                    kind: FindingKind::LogCleared,
+                    chunk_number,
+                    offset,
+                    record_id: Some(record.event_record_id),
+                    description: format!("{} at {}", description, record.timestamp),
+                });
+            }
+        }
+
+        analyze_consecutive_chunks(summaries, &mut findings);
+        findings.sort_by_key(|finding| (finding.offset, finding.kind));
+
+        findings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ensure_env_logger_initialized;
+
+    fn kinds(findings: &[Finding]) -> Vec<FindingKind> {
+        findings.iter().map(|finding| finding.kind).collect()
+    }
+
+    #[test]
+    fn test_finds_log_cleared_events() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/system.evtx");
+        let mut parser = EvtxParser::from_buffer(evtx_file.to_vec()).unwrap();
+
+        let cleared: Vec<_> = parser
+            .analyze()
+            .into_iter()
+            .filter(|finding| finding.kind == FindingKind::LogCleared)
+            .map(|finding| finding.record_id.unwrap())
+            .collect();
+
+        assert_eq!(cleared, vec![1847, 1848]);
+    }
+
+    #[test]
+    fn test_finds_tampered_record_ids() {
+        ensure_env_logger_initialized();
+        let mut evtx_file = include_bytes!("../samples/security.evtx").to_vec();
+        let mut parser = EvtxParser::from_buffer(evtx_file.clone()).unwrap();
+        assert_eq!(kinds(&parser.analyze()), vec![]);
+
+        // Rewrite the id of the second record in the first chunk to be the id of the first record.
+        let record_offset = {
+            let chunk = parser.allocate_chunk(0, false).unwrap().unwrap();
+            let (offset, _) = chunk.record_headers().nth(1).unwrap();
+            EVTX_FILE_HEADER_SIZE + offset as usize
+        };
+        evtx_file[record_offset + 8..record_offset + 16].copy_from_slice(&1_u64.to_le_bytes());
+
+        let mut parser = EvtxParser::from_buffer(evtx_file).unwrap();
+        let findings = parser.analyze();
+
+        assert_eq!(kinds(&findings), vec![FindingKind::NonMonotonicRecordId]);
+        assert_eq!(findings[0].offset, record_offset as u64);
+        assert_eq!(findings[0].record_id, Some(1));
+    }
+
+    #[test]
+    fn test_finds_hidden_records() {
+        ensure_env_logger_initialized();
+        let mut evtx_file = include_bytes!("../samples/security.evtx").to_vec();
+        let mut parser = EvtxParser::from_buffer(evtx_file.clone()).unwrap();
+
+        // Hide the last record of the first chunk by moving the free space offset back,
+        // and fix the checksums to match.
+        let mut chunk = parser.allocate_chunk(0, false).unwrap().unwrap();
+        let (last_record_offset, _) = chunk.record_headers().last().unwrap();
+        chunk.data.to_mut()[48..52].copy_from_slice(&last_record_offset.to_le_bytes());
+        chunk.header.free_space_offset = last_record_offset;
+        let data_checksum = chunk.computed_data_checksum();
+        chunk.data.to_mut()[52..56].copy_from_slice(&data_checksum.to_le_bytes());
+        let header_checksum = chunk.computed_header_checksum();
+        chunk.data.to_mut()[124..128].copy_from_slice(&header_checksum.to_le_bytes());
+
+        evtx_file[EVTX_FILE_HEADER_SIZE..EVTX_FILE_HEADER_SIZE + EVTX_CHUNK_SIZE]
+            .copy_from_slice(&chunk.data);
+
+        let mut parser = EvtxParser::from_buffer(evtx_file).unwrap();
+        let findings = parser.analyze();
+
+        assert_eq!(
+            kinds(&findings),
+            vec![FindingKind::ImpossibleFreeSpaceOffset]
+        );
+        assert_eq!(findings[0].offset, EVTX_FILE_HEADER_SIZE as u64);
+    }
+}
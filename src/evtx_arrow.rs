@@ -0,0 +1,322 @@
+use crate::err::Result;
+use crate::evtx_parser::ReadSeek;
+use crate::evtx_record::RecordId;
+use crate::{EvtxParser, EvtxRecord, SystemFields};
+
+use arrow_array::builder::{
+    MapBuilder, StringBuilder, TimestampMicrosecondBuilder, UInt16Builder, UInt32Builder,
+    UInt64Builder, UInt8Builder,
+};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Fields, Schema, SchemaRef, TimeUnit};
+use chrono::{DateTime, Utc};
+use std::sync::{Arc, OnceLock};
+
+/// The values of a record, as stored in a `RecordBatch`.
+#[derive(Debug, Clone)]
+struct ArrowRow {
+    event_record_id: RecordId,
+    timestamp: DateTime<Utc>,
+    system: SystemFields,
+    event_data: Vec<(String, Option<String>)>,
+}
+
+impl ArrowRow {
+    fn from_record(record: &EvtxRecord) -> Result<Self> {
+        Ok(ArrowRow {
+            event_record_id: record.event_record_id,
+            timestamp: record.timestamp,
+            system: record.system()?,
+            event_data: record.event_data()?,
+        })
+    }
+}
+
+fn timestamp_type() -> DataType {
+    DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()))
+}
+
+fn event_data_type() -> DataType {
+    let entries = Fields::from(vec![
+        Field::new("keys", DataType::Utf8, false),
+        Field::new("values", DataType::Utf8, true),
+    ]);
+
+    DataType::Map(
+        Arc::new(Field::new("entries", DataType::Struct(entries), false)),
+        false,
+    )
+}
+
+/// Builds Arrow `RecordBatch`es out of records.
+///
+/// The schema has a column for every `System` field (see `SystemFields`), using the native type
+/// of the field (`event_id` is a `UInt16`, `keywords` a `UInt64`, `time_created` a timestamp, ...),
+/// along with the record id and the timestamp of the record header.
+/// GUIDs and SIDs are stored as strings, in their usual textual form.
+///
+/// The fields of `EventData` (or `UserData`) differ between events, so they are stored in a single
+/// `event_data` map column, from the name of every field to its value as a string.
+///
+/// ```rust
+/// # use evtx::{EvtxParser, RecordBatchBuilder};
+/// # let fp = std::path::PathBuf::from(format!("{}/samples/security.evtx", std::env::var("CARGO_MANIFEST_DIR").unwrap()));
+/// let mut parser = EvtxParser::from_path(fp).unwrap();
+///
+/// for batch in parser.record_batches(1024) {
+///     let batch = batch.unwrap();
+///     assert_eq!(batch.schema(), RecordBatchBuilder::schema());
+/// }
+/// ```
+#[derive(Debug)]
+pub struct RecordBatchBuilder {
+    event_record_id: UInt64Builder,
+    timestamp: TimestampMicrosecondBuilder,
+    provider_name: StringBuilder,
+    provider_guid: StringBuilder,
+    event_id: UInt16Builder,
+    version: UInt8Builder,
+    level: UInt8Builder,
+    task: UInt16Builder,
+    opcode: UInt8Builder,
+    keywords: UInt64Builder,
+    time_created: TimestampMicrosecondBuilder,
+    process_id: UInt32Builder,
+    thread_id: UInt32Builder,
+    channel: StringBuilder,
+    computer: StringBuilder,
+    user_sid: StringBuilder,
+    event_data: MapBuilder<StringBuilder, StringBuilder>,
+    len: usize,
+}
+
+impl Default for RecordBatchBuilder {
+    fn default() -> Self {
+        RecordBatchBuilder::new()
+    }
+}
+
+impl RecordBatchBuilder {
+    pub fn new() -> Self {
+        RecordBatchBuilder {
+            event_record_id: UInt64Builder::new(),
+            timestamp: TimestampMicrosecondBuilder::new().with_timezone("UTC"),
+            provider_name: StringBuilder::new(),
+            provider_guid: StringBuilder::new(),
+            event_id: UInt16Builder::new(),
+            version: UInt8Builder::new(),
+            level: UInt8Builder::new(),
+            task: UInt16Builder::new(),
+            opcode: UInt8Builder::new(),
+            keywords: UInt64Builder::new(),
+            time_created: TimestampMicrosecondBuilder::new().with_timezone("UTC"),
+            process_id: UInt32Builder::new(),
+            thread_id: UInt32Builder::new(),
+            channel: StringBuilder::new(),
+            computer: StringBuilder::new(),
+            user_sid: StringBuilder::new(),
+            event_data: MapBuilder::new(None, StringBuilder::new(), StringBuilder::new()),
+            len: 0,
+        }
+    }
+
+    /// The schema of the batches.
+    pub fn schema() -> SchemaRef {
+        static SCHEMA: OnceLock<SchemaRef> = OnceLock::new();
+
+        SCHEMA
+            .get_or_init(|| {
+                Arc::new(Schema::new(vec![
+                    Field::new("event_record_id", DataType::UInt64, false),
+                    Field::new("timestamp", timestamp_type(), false),
+                    Field::new("provider_name", DataType::Utf8, true),
+                    Field::new("provider_guid", DataType::Utf8, true),
+                    Field::new("event_id", DataType::UInt16, true),
+                    Field::new("version", DataType::UInt8, true),
+                    Field::new("level", DataType::UInt8, true),
+                    Field::new("task", DataType::UInt16, true),
+                    Field::new("opcode", DataType::UInt8, true),
+                    Field::new("keywords", DataType::UInt64, true),
+                    Field::new("time_created", timestamp_type(), true),
+                    Field::new("process_id", DataType::UInt32, true),
+                    Field::new("thread_id", DataType::UInt32, true),
+                    Field::new("channel", DataType::Utf8, true),
+                    Field::new("computer", DataType::Utf8, true),
+                    Field::new("user_sid", DataType::Utf8, true),
+                    Field::new("event_data", event_data_type(), false),
+                ]))
+            })
+            .clone()
+    }
+
+    /// Appends a row for `record`.
+    pub fn append(&mut self, record: &EvtxRecord) -> Result<()> {
+        self.append_row(ArrowRow::from_record(record)?);
+
+        Ok(())
+    }
+
+    fn append_row(&mut self, row: ArrowRow) {
+        let system = row.system;
+
+        self.event_record_id.append_value(row.event_record_id);
+        self.timestamp
+            .append_value(row.timestamp.timestamp_micros());
+        self.provider_name.append_option(system.provider_name);
+        self.provider_guid.append_option(system.provider_guid);
+        self.event_id.append_option(system.event_id);
+        self.version.append_option(system.version);
+        self.level.append_option(system.level);
+        self.task.append_option(system.task);
+        self.opcode.append_option(system.opcode);
+        self.keywords.append_option(system.keywords);
+        self.time_created
+            .append_option(system.time_created.map(|tm| tm.timestamp_micros()));
+        self.process_id.append_option(system.process_id);
+        self.thread_id.append_option(system.thread_id);
+        self.channel.append_option(system.channel);
+        self.computer.append_option(system.computer);
+        self.user_sid.append_option(system.user_sid);
+
+        for (name, value) in row.event_data {
+            self.event_data.keys().append_value(name);
+            self.event_data.values().append_option(value);
+        }
+        self.event_data
+            .append(true)
+            .expect("keys and values are always appended together");
+
+        self.len += 1;
+    }
+
+    /// The number of rows appended since the last batch was built.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Builds a batch out of the rows appended so far, and resets the builder.
+    pub fn finish(&mut self) -> Result<RecordBatch> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.event_record_id.finish()),
+            Arc::new(self.timestamp.finish()),
+            Arc::new(self.provider_name.finish()),
+            Arc::new(self.provider_guid.finish()),
+            Arc::new(self.event_id.finish()),
+            Arc::new(self.version.finish()),
+            Arc::new(self.level.finish()),
+            Arc::new(self.task.finish()),
+            Arc::new(self.opcode.finish()),
+            Arc::new(self.keywords.finish()),
+            Arc::new(self.time_created.finish()),
+            Arc::new(self.process_id.finish()),
+            Arc::new(self.thread_id.finish()),
+            Arc::new(self.channel.finish()),
+            Arc::new(self.computer.finish()),
+            Arc::new(self.user_sid.finish()),
+            Arc::new(self.event_data.finish()),
+        ];
+        self.len = 0;
+
+        Ok(RecordBatch::try_new(Self::schema(), columns)?)
+    }
+}
+
+impl<T: ReadSeek> EvtxParser<T> {
+    /// Return an iterator over batches of (at most) `batch_size` records, see `RecordBatchBuilder`.
+    ///
+    /// Records are read like `records` does (possibly using multiple threads).
+    /// A record which cannot be read is returned as an error, and is not part of any batch.
+    pub fn record_batches(
+        &mut self,
+        batch_size: usize,
+    ) -> impl Iterator<Item = Result<RecordBatch>> + '_ {
+        let batch_size = batch_size.max(1);
+        let mut rows = self.serialized_records(|record| ArrowRow::from_record(&record?));
+        let mut builder = RecordBatchBuilder::new();
+
+        std::iter::from_fn(move || {
+            for row in rows.by_ref() {
+                match row {
+                    Ok(row) => {
+                        builder.append_row(row);
+
+                        if builder.len() >= batch_size {
+                            return Some(builder.finish());
+                        }
+                    }
+                    Err(err) => return Some(Err(err)),
+                }
+            }
+
+            if builder.is_empty() {
+                None
+            } else {
+                Some(builder.finish())
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ensure_env_logger_initialized;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{TimestampMicrosecondType, UInt16Type, UInt64Type};
+    use arrow_array::Array;
+
+    #[test]
+    fn test_record_batches() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+        let mut parser = EvtxParser::from_buffer(evtx_file.to_vec()).unwrap();
+
+        let batches: Vec<RecordBatch> = parser
+            .record_batches(100)
+            .filter_map(|batch| batch.ok())
+            .collect();
+
+        let mut parser = EvtxParser::from_buffer(evtx_file.to_vec()).unwrap();
+        let record_count = parser.records().filter(|record| record.is_ok()).count();
+
+        assert!(batches.iter().all(|batch| batch.num_rows() <= 100));
+        assert_eq!(
+            batches.iter().map(RecordBatch::num_rows).sum::<usize>(),
+            record_count
+        );
+
+        let batch = &batches[0];
+        assert_eq!(batch.schema(), RecordBatchBuilder::schema());
+
+        let ids = batch.column(0).as_primitive::<UInt64Type>();
+        let event_ids = batch.column(4).as_primitive::<UInt16Type>();
+        let keywords = batch.column(9).as_primitive::<UInt64Type>();
+        let time_created = batch.column(10).as_primitive::<TimestampMicrosecondType>();
+        let channels = batch.column(13).as_string::<i32>();
+
+        assert_eq!(ids.value(1), 2);
+        assert_eq!(event_ids.value(1), 4624);
+        assert_eq!(keywords.value(1), 0x8020000000000000);
+        assert_eq!(channels.value(1), "Security");
+        assert_eq!(
+            time_created.value(1),
+            "2016-07-08T18:12:51.681640Z"
+                .parse::<DateTime<Utc>>()
+                .unwrap()
+                .timestamp_micros()
+        );
+
+        let event_data = batch.column(16).as_map();
+        let fields = event_data.value(1);
+        let names = fields.column(0).as_string::<i32>();
+        let values = fields.column(1).as_string::<i32>();
+        let target_user = (0..fields.len())
+            .find(|&i| names.value(i) == "TargetUserName")
+            .unwrap();
+        assert_eq!(values.value(target_user), "SYSTEM");
+    }
+}
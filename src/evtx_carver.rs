@@ -0,0 +1,592 @@
+use crate::err::{EvtxError, InputError, Result};
+
+use crate::evtx_chunk::{
+    EvtxChunkData, EvtxChunkHeader, EVTX_CHUNK_HEADER_SIZE, EVTX_RECORD_HEADER_SIZE,
+    EVTX_RECORD_MAGIC,
+};
+use crate::evtx_parser::{ReadSeek, EVTX_CHUNK_SIZE};
+use crate::evtx_record::{EvtxRecordHeader, SerializedEvtxRecord};
+use crate::{EvtxRecord, FileOffset, ParserSettings};
+
+use byteorder::{ByteOrder, LittleEndian};
+use log::{debug, trace};
+use memchr::memchr2;
+use std::fmt;
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{Cursor, Read, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+
+const EVTX_CHUNK_MAGIC: &[u8] = b"ElfChnk\x00";
+
+/// Amount of data read from the stream at once while searching for signatures.
+const SCAN_WINDOW_SIZE: usize = 4 * 1024 * 1024;
+
+/// Records are expected to begin with a fragment header (`0f 01 01 00`), followed by a template instance (`0c`).
+const RECORD_BINXML_PREFIX: &[u8] = b"\x0f\x01\x01\x00\x0c";
+/// Offset of the template definition offset, relative to the start of the record.
+/// (record header, fragment header, template instance token, unknown byte, template id)
+const TEMPLATE_DEFINITION_OFFSET_POSITION: usize = EVTX_RECORD_HEADER_SIZE + 4 + 1 + 1 + 4;
+/// A template definition header is followed by the fragment header of the template.
+const TEMPLATE_DEFINITION_HEADER_SIZE: usize = 24;
+/// Chunks inside a file are always aligned to the sector size of the underlying device.
+const SECTOR_SIZE: u64 = 512;
+
+/// Where a carved record was recovered from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CarvedRecordSource {
+    /// The record was found inside an intact chunk which starts at `chunk_offset`.
+    Chunk { chunk_offset: FileOffset },
+    /// The record was found outside of any intact chunk.
+    /// Names and templates were resolved by locating the (lost) start of the chunk it belongs to.
+    Orphan,
+}
+
+/// A record which was recovered by an `EvtxCarver`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CarvedRecord<T> {
+    /// Offset of the record header from the start of the carved stream.
+    pub offset: FileOffset,
+    pub source: CarvedRecordSource,
+    pub record: T,
+}
+
+/// Data recovered by the carver, which may contain records.
+enum CarvedItem {
+    Chunk {
+        offset: FileOffset,
+        chunk: EvtxChunkData,
+    },
+    OrphanRecord {
+        offset: FileOffset,
+        /// Offset of the record inside of `chunk`.
+        record_offset: u64,
+        chunk: EvtxChunkData,
+    },
+}
+
+/// Recovers records from an arbitrary stream of bytes, such as a raw disk image, a memory dump
+/// or a pagefile.
+///
+/// Unlike `EvtxParser`, the carver does not require the stream to start with an EVTX file header.
+/// Instead, it scans the entire stream for chunk headers (`ElfChnk\x00`) and record headers (`**\x00\x00`).
+/// Records inside of intact chunks are parsed as usual, while records which are found outside of
+/// any intact chunk (for example, when the chunk header has been overwritten) are recovered on their own.
+///
+/// Example usage:
+///
+/// ```rust
+/// # use evtx::EvtxCarver;
+/// # let fp = std::path::PathBuf::from(format!("{}/samples/security.evtx", std::env::var("CARGO_MANIFEST_DIR").unwrap()));
+///
+/// let mut carver = EvtxCarver::from_path(fp).unwrap();
+///
+/// for carved in carver.records() {
+///     match carved {
+///         Ok(c) => println!("Record {} at offset {}\n{}", c.record.event_record_id, c.offset, c.record.data),
+///         Err(e) => eprintln!("{}", e),
+///     }
+/// }
+/// ```
+pub struct EvtxCarver<T: ReadSeek> {
+    data: T,
+    config: Arc<ParserSettings>,
+    /// Offset of the next byte to be scanned.
+    position: FileOffset,
+    window: Vec<u8>,
+    window_start: FileOffset,
+    window_at_eof: bool,
+}
+
+impl<T: ReadSeek> Debug for EvtxCarver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> ::std::fmt::Result {
+        f.debug_struct("EvtxCarver")
+            .field("config", &self.config)
+            .field("position", &self.position)
+            .finish()
+    }
+}
+
+impl EvtxCarver<File> {
+    /// Attempts to open the file at the given path for carving.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path
+            .as_ref()
+            .canonicalize()
+            .map_err(|e| InputError::failed_to_open_file(e, &path))?;
+
+        let f = File::open(&path).map_err(|e| InputError::failed_to_open_file(e, &path))?;
+
+        Ok(Self::from_read_seek(f))
+    }
+}
+
+impl EvtxCarver<Cursor<Vec<u8>>> {
+    pub fn from_buffer(buffer: Vec<u8>) -> Self {
+        Self::from_read_seek(Cursor::new(buffer))
+    }
+}
+
+impl<T: ReadSeek> EvtxCarver<T> {
+    pub fn from_read_seek(read_seek: T) -> Self {
+        EvtxCarver {
+            data: read_seek,
+            config: Arc::new(ParserSettings::default()),
+            position: 0,
+            window: Vec::new(),
+            window_start: 0,
+            window_at_eof: false,
+        }
+    }
+
+    pub fn with_configuration(mut self, configuration: ParserSettings) -> Self {
+        self.config = Arc::new(configuration);
+        self
+    }
+
+    /// Reads up to `len` bytes starting at `offset`, fewer bytes are returned when EOF is reached.
+    fn read_at(&mut self, offset: FileOffset, len: usize) -> Result<Vec<u8>> {
+        let mut buffer = Vec::with_capacity(len);
+
+        self.data.seek(SeekFrom::Start(offset))?;
+        (&mut self.data).take(len as u64).read_to_end(&mut buffer)?;
+
+        Ok(buffer)
+    }
+
+    fn refill_window(&mut self) -> Result<()> {
+        self.window = self.read_at(self.position, SCAN_WINDOW_SIZE)?;
+        self.window_start = self.position;
+        self.window_at_eof = self.window.len() < SCAN_WINDOW_SIZE;
+
+        Ok(())
+    }
+
+    /// Finds the offset of the next byte sequence which looks like either a chunk or a record magic.
+    fn next_signature(&mut self) -> Result<Option<FileOffset>> {
+        loop {
+            let relative_position = (self.position - self.window_start) as usize;
+
+            // Make sure that a whole signature starting at `position` is inside of the window.
+            if relative_position + EVTX_CHUNK_MAGIC.len() > self.window.len() && !self.window_at_eof
+            {
+                self.refill_window()?;
+                continue;
+            }
+
+            let haystack = match self.window.get(relative_position..) {
+                Some(haystack) if !haystack.is_empty() => haystack,
+                _ => return Ok(None),
+            };
+
+            match memchr2(EVTX_CHUNK_MAGIC[0], EVTX_RECORD_MAGIC[0], haystack) {
+                None => self.position += haystack.len() as u64,
+                Some(i) => {
+                    let candidate = &haystack[i..];
+                    let offset = self.position + i as u64;
+
+                    // The signature might be cut at the end of the window.
+                    if candidate.len() < EVTX_CHUNK_MAGIC.len() && !self.window_at_eof {
+                        self.position = offset;
+                        self.refill_window()?;
+                        continue;
+                    }
+
+                    self.position = offset + 1;
+
+                    if candidate.starts_with(EVTX_CHUNK_MAGIC)
+                        || candidate.starts_with(EVTX_RECORD_MAGIC)
+                    {
+                        return Ok(Some(offset));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Scans the stream for the next chunk or orphan record.
+    fn next_item(&mut self) -> Option<Result<CarvedItem>> {
+        loop {
+            let offset = match self.next_signature() {
+                Ok(Some(offset)) => offset,
+                Ok(None) => return None,
+                Err(e) => return Some(Err(e)),
+            };
+
+            let magic = match self.read_at(offset, EVTX_CHUNK_MAGIC.len()) {
+                Ok(magic) => magic,
+                Err(e) => return Some(Err(e)),
+            };
+
+            let item = if magic.as_slice() == EVTX_CHUNK_MAGIC {
+                self.carve_chunk(offset)
+            } else {
+                self.carve_orphan_record(offset)
+            };
+
+            match item {
+                Ok(Some(item)) => return Some(Ok(item)),
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+
+    fn carve_chunk(&mut self, offset: FileOffset) -> Result<Option<CarvedItem>> {
+        let data = self.read_at(offset, EVTX_CHUNK_SIZE)?;
+
+        // A truncated chunk cannot be parsed as a whole, but the records inside of it may still be
+        // recovered on their own.
+        if data.len() != EVTX_CHUNK_SIZE {
+            debug!("Offset `0x{:08x}` - Chunk is truncated", offset);
+            return Ok(None);
+        }
+
+        let chunk = match EvtxChunkData::new(data, self.config.should_validate_checksums()) {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                debug!("Offset `0x{:08x}` - Invalid chunk: {}", offset, e);
+                return Ok(None);
+            }
+        };
+
+        let free_space_offset = chunk.header.free_space_offset as usize;
+        if !(EVTX_CHUNK_HEADER_SIZE..=EVTX_CHUNK_SIZE).contains(&free_space_offset)
+            || chunk.header.first_event_record_id > chunk.header.last_event_record_id
+        {
+            debug!("Offset `0x{:08x}` - Chunk header is implausible", offset);
+            return Ok(None);
+        }
+
+        trace!("Offset `0x{:08x}` - Carved chunk", offset);
+        // Records inside of the chunk will be read from the chunk.
+        self.position = offset + EVTX_CHUNK_SIZE as u64;
+
+        Ok(Some(CarvedItem::Chunk { offset, chunk }))
+    }
+
+    fn carve_orphan_record(&mut self, offset: FileOffset) -> Result<Option<CarvedItem>> {
+        let header_data = self.read_at(offset, EVTX_RECORD_HEADER_SIZE)?;
+        let header = match EvtxRecordHeader::from_reader(&mut Cursor::new(header_data.as_slice())) {
+            Ok(header) => header,
+            Err(_) => return Ok(None),
+        };
+
+        let record_size = header.data_size as usize;
+        if !(TEMPLATE_DEFINITION_OFFSET_POSITION + 4..=EVTX_CHUNK_SIZE - EVTX_CHUNK_HEADER_SIZE)
+            .contains(&record_size)
+        {
+            return Ok(None);
+        }
+
+        // The record may be preceded by the rest of the chunk it was written to.
+        let region_end = offset + record_size as u64;
+        let region_start = region_end.saturating_sub(EVTX_CHUNK_SIZE as u64);
+        let region = self.read_at(region_start, (region_end - region_start) as usize)?;
+
+        if region.len() as u64 != region_end - region_start {
+            return Ok(None);
+        }
+
+        let record = &region[(offset - region_start) as usize..];
+
+        // Records end with a copy of their size.
+        if LittleEndian::read_u32(&record[record_size - 4..]) != header.data_size {
+            return Ok(None);
+        }
+
+        let chunk_start = match Self::find_orphan_chunk_start(&region, region_start, offset) {
+            Some(chunk_start) => chunk_start,
+            None => {
+                debug!(
+                    "Offset `0x{:08x}` - Could not locate the chunk of record {}",
+                    offset, header.event_record_id
+                );
+                return Ok(None);
+            }
+        };
+
+        trace!(
+            "Offset `0x{:08x}` - Carved orphan record {} (chunk starting at `0x{:08x}`)",
+            offset,
+            header.event_record_id,
+            chunk_start
+        );
+
+        let mut data = vec![0; EVTX_CHUNK_SIZE];
+        let available = &region[(chunk_start - region_start) as usize..];
+        data[..available.len()].copy_from_slice(available);
+
+        let record_offset = offset - chunk_start;
+        let chunk = EvtxChunkData {
+            header: EvtxChunkHeader::for_orphan_record(
+                header.event_record_id,
+                record_offset as u32,
+                header.data_size,
+            ),
+            data: data.into(),
+        };
+
+        self.position = region_end;
+
+        Ok(Some(CarvedItem::OrphanRecord {
+            offset,
+            record_offset,
+            chunk,
+        }))
+    }
+
+    /// All offsets inside of a record are relative to the start of its chunk,
+    /// so we need to find out where the chunk started in order to parse the record.
+    ///
+    /// Records begin with a template instance, which points to a template definition.
+    /// The template is either defined inline (right after the instance), or earlier in the chunk.
+    fn find_orphan_chunk_start(
+        region: &[u8],
+        region_start: FileOffset,
+        record_start: FileOffset,
+    ) -> Option<FileOffset> {
+        let record = &region[(record_start - region_start) as usize..];
+
+        if !record[EVTX_RECORD_HEADER_SIZE..].starts_with(RECORD_BINXML_PREFIX) {
+            return None;
+        }
+
+        let template_definition_offset = u64::from(LittleEndian::read_u32(
+            &record[TEMPLATE_DEFINITION_OFFSET_POSITION..],
+        ));
+
+        let is_valid_chunk_start = |chunk_start: FileOffset| {
+            let record_offset = record_start - chunk_start;
+
+            if record_offset < EVTX_CHUNK_HEADER_SIZE as u64 {
+                return false;
+            }
+
+            let definition_start = chunk_start + template_definition_offset;
+            let fragment_start = definition_start + TEMPLATE_DEFINITION_HEADER_SIZE as u64;
+
+            match region.get((fragment_start - region_start) as usize..) {
+                Some(fragment) => fragment.starts_with(&RECORD_BINXML_PREFIX[..3]),
+                None => false,
+            }
+        };
+
+        // Inline template, the definition starts right after the template instance.
+        let inline_definition = record_start + TEMPLATE_DEFINITION_OFFSET_POSITION as u64 + 4;
+        if let Some(chunk_start) = inline_definition.checked_sub(template_definition_offset) {
+            if chunk_start >= region_start && is_valid_chunk_start(chunk_start) {
+                return Some(chunk_start);
+            }
+        }
+
+        // The template was defined earlier in the chunk, try every sector aligned chunk start.
+        let last_candidate = record_start.checked_sub(EVTX_CHUNK_HEADER_SIZE as u64)?;
+        let mut chunk_start = last_candidate - last_candidate % SECTOR_SIZE;
+
+        while chunk_start >= region_start {
+            if chunk_start + template_definition_offset < record_start
+                && is_valid_chunk_start(chunk_start)
+            {
+                return Some(chunk_start);
+            }
+
+            chunk_start = chunk_start.checked_sub(SECTOR_SIZE)?;
+        }
+
+        None
+    }
+
+    /// Return an iterator over all the carved records.
+    /// Records will be mapped `f`, which must produce owned data from the records.
+    ///
+    /// Carving is done sequentially, regardless of the configured number of threads.
+    /// Records rejected by `ParserSettings::record_filter` are skipped.
+    pub fn serialized_records<'a, U>(
+        &'a mut self,
+        mut f: impl FnMut(Result<EvtxRecord<'_>>) -> Result<U> + 'a,
+    ) -> impl Iterator<Item = Result<CarvedRecord<U>>> + 'a {
+        let settings = Arc::clone(&self.config);
+
+        let records_per_item = std::iter::from_fn(move || {
+            let item = self.next_item()?;
+
+            let records: Vec<Result<CarvedRecord<U>>> = match item {
+                Err(err) => vec![Err(err)],
+                Ok(CarvedItem::Chunk { offset, mut chunk }) => {
+                    match chunk.parse(Arc::clone(&settings)) {
+                        Err(err) => vec![Err(EvtxError::FailedToParseCarvedChunk {
+                            offset,
+                            source: err,
+                        })],
+                        Ok(mut chunk_records) => chunk_records
+                            .iter()
+                            .filter(|record| settings.should_serialize(record))
+                            .map(|record| {
+                                let record_offset =
+                                    record.as_ref().map(|r| r.record_offset).unwrap_or(0);
+
+                                f(record).map(|record| CarvedRecord {
+                                    offset: offset + u64::from(record_offset),
+                                    source: CarvedRecordSource::Chunk {
+                                        chunk_offset: offset,
+                                    },
+                                    record,
+                                })
+                            })
+                            .collect(),
+                    }
+                }
+                Ok(CarvedItem::OrphanRecord {
+                    offset,
+                    record_offset,
+                    mut chunk,
+                }) => match chunk.parse(Arc::clone(&settings)) {
+                    Err(err) => vec![Err(EvtxError::FailedToParseCarvedChunk {
+                        offset,
+                        source: err,
+                    })],
+                    Ok(mut chunk_records) => chunk_records
+                        .iter_from_offset(record_offset)
+                        .take(1)
+                        .filter(|record| settings.should_serialize(record))
+                        .map(|record| {
+                            f(record).map(|record| CarvedRecord {
+                                offset,
+                                source: CarvedRecordSource::Orphan,
+                                record,
+                            })
+                        })
+                        .collect(),
+                },
+            };
+
+            Some(records)
+        });
+
+        records_per_item.flatten()
+    }
+
+    /// Return an iterator over all the carved records.
+    /// Records will be XML-formatted.
+    pub fn records(
+        &mut self,
+    ) -> impl Iterator<Item = Result<CarvedRecord<SerializedEvtxRecord<String>>>> + '_ {
+        self.serialized_records(|record| record.and_then(|record| record.into_xml()))
+    }
+
+    /// Return an iterator over all the carved records.
+    /// Records will be JSON-formatted.
+    pub fn records_json(
+        &mut self,
+    ) -> impl Iterator<Item = Result<CarvedRecord<SerializedEvtxRecord<String>>>> + '_ {
+        self.serialized_records(|record| record.and_then(|record| record.into_json()))
+    }
+
+    /// Return an iterator over all the carved records.
+    /// Records will have a `serde_json::Value` data attribute.
+    pub fn records_json_value(
+        &mut self,
+    ) -> impl Iterator<Item = Result<CarvedRecord<SerializedEvtxRecord<serde_json::Value>>>> + '_
+    {
+        self.serialized_records(|record| record.and_then(|record| record.into_json_value()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ensure_env_logger_initialized;
+    use crate::evtx_parser::EVTX_FILE_HEADER_SIZE;
+    use crate::EvtxParser;
+
+    #[test]
+    fn test_carves_all_records_from_evtx_file() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+
+        let expected: Vec<_> = EvtxParser::from_buffer(evtx_file.to_vec())
+            .unwrap()
+            .records()
+            .filter_map(|r| r.ok())
+            .map(|r| r.event_record_id)
+            .collect();
+
+        let mut carver = EvtxCarver::from_buffer(evtx_file.to_vec());
+        let carved: Vec<_> = carver
+            .records()
+            .map(|r| r.unwrap().record.event_record_id)
+            .collect();
+
+        assert_eq!(carved, expected);
+    }
+
+    #[test]
+    fn test_carves_chunk_without_file_header() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+        let chunk = &evtx_file[EVTX_FILE_HEADER_SIZE..EVTX_FILE_HEADER_SIZE + EVTX_CHUNK_SIZE];
+
+        // Unaligned garbage before and after the chunk.
+        let mut image = vec![0xAA; 1001];
+        image.extend_from_slice(chunk);
+        image.extend_from_slice(&[0x2A; 3000]);
+
+        let mut carver = EvtxCarver::from_buffer(image);
+        let carved: Vec<_> = carver.records().map(|r| r.unwrap()).collect();
+
+        assert_eq!(carved.len(), 91);
+        assert_eq!(carved[0].offset, 1001 + EVTX_CHUNK_HEADER_SIZE as u64);
+        assert_eq!(
+            carved[0].source,
+            CarvedRecordSource::Chunk { chunk_offset: 1001 }
+        );
+
+        for (i, c) in carved.iter().enumerate() {
+            assert_eq!(c.record.event_record_id, i as u64 + 1);
+            assert_eq!(&image_record_magic(chunk, c.offset - 1001), b"**\x00\x00");
+        }
+    }
+
+    fn image_record_magic(chunk: &[u8], offset: u64) -> [u8; 4] {
+        let mut magic = [0; 4];
+        magic.copy_from_slice(&chunk[offset as usize..offset as usize + 4]);
+        magic
+    }
+
+    #[test]
+    fn test_carves_orphan_records_when_chunk_header_is_lost() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+        let mut chunk =
+            evtx_file[EVTX_FILE_HEADER_SIZE..EVTX_FILE_HEADER_SIZE + EVTX_CHUNK_SIZE].to_vec();
+
+        // Wipe the chunk header.
+        for b in chunk[..EVTX_CHUNK_HEADER_SIZE].iter_mut() {
+            *b = 0;
+        }
+
+        let mut image = vec![0; 4096];
+        image.extend_from_slice(&chunk);
+
+        let mut carver = EvtxCarver::from_buffer(image);
+        let carved: Vec<_> = carver.records().map(|r| r.unwrap()).collect();
+
+        assert_eq!(carved.len(), 91);
+
+        for (i, c) in carved.iter().enumerate() {
+            assert_eq!(c.source, CarvedRecordSource::Orphan);
+            assert_eq!(c.record.event_record_id, i as u64 + 1);
+        }
+
+        let expected = EvtxParser::from_buffer(evtx_file.to_vec())
+            .unwrap()
+            .records()
+            .next()
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(carved[0].record.data, expected.data);
+    }
+}
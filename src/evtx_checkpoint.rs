@@ -0,0 +1,24 @@
+use crate::evtx_record::RecordId;
+use crate::ChunkOffset;
+
+use serde::{Deserialize, Serialize};
+
+/// A position in an evtx file, which can be used to resume parsing right after a record
+/// that was already processed (for example, after the process was restarted).
+///
+/// Checkpoints are yielded along with records by `EvtxParser::records_with_checkpoints`,
+/// and can be passed back to `EvtxParser::resume_from`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Identifies the file the checkpoint was taken from.
+    /// This is a checksum of the header of the first record in the file, which does not change
+    /// while the file is being written to, but does change if the file wraps around and its
+    /// first chunk is overwritten.
+    pub file_identity: u32,
+    /// Number of the chunk which contains the last processed record.
+    pub chunk_number: u64,
+    /// Offset of the last processed record, relative to the start of the chunk.
+    pub record_offset: ChunkOffset,
+    /// Id of the last processed record.
+    pub last_record_id: RecordId,
+}
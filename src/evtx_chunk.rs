@@ -2,23 +2,32 @@ use crate::err::{
     ChunkError, DeserializationError, DeserializationResult, EvtxChunkResult, EvtxError,
 };
 
-use crate::evtx_record::{EvtxRecord, EvtxRecordHeader};
+#[cfg(feature = "mmap")]
+use crate::evtx_mmap::MappedRange;
+use crate::evtx_record::{EvtxRecord, EvtxRecordHeader, RecordId};
+use crate::model::deserialized::BinXMLDeserializedTokens;
 
 use log::{debug, info, trace};
 use std::{
     io::Cursor,
     io::{Read, Seek, SeekFrom},
+    ops::Deref,
 };
 
 use crate::binxml::deserializer::BinXmlDeserializer;
 use crate::string_cache::StringCache;
 use crate::template_cache::TemplateCache;
-use crate::{ParserSettings, checksum_ieee};
+use crate::{checksum_ieee, ChunkOffset, ParserSettings};
 
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt};
+use memchr::memmem;
 use std::sync::Arc;
 
-const EVTX_CHUNK_HEADER_SIZE: usize = 512;
+pub(crate) const EVTX_CHUNK_HEADER_SIZE: usize = 512;
+pub(crate) const EVTX_RECORD_MAGIC: &[u8] = b"\x2a\x2a\x00\x00";
+pub(crate) const EVTX_RECORD_HEADER_SIZE: usize = 24;
+/// Record header and the size copy at the end of the record.
+const EVTX_RECORD_MIN_SIZE: u32 = EVTX_RECORD_HEADER_SIZE as u32 + 4;
 
 bitflags! {
     #[derive(Debug)]
@@ -54,27 +63,109 @@ pub struct EvtxChunkHeader {
     template_offsets: Vec<u32>,
 }
 
-/// A struct which owns all the data associated with a chunk.
+/// The data of a chunk, either owned or borrowed from a memory mapped file
+/// (see `EvtxParser::from_mmap`).
+#[derive(Clone)]
+pub struct ChunkBytes(ChunkBytesInner);
+
+#[derive(Clone)]
+enum ChunkBytesInner {
+    Owned(Vec<u8>),
+    #[cfg(feature = "mmap")]
+    Mapped(MappedRange),
+}
+
+impl ChunkBytes {
+    /// Returns true if the data is borrowed from a memory mapped file.
+    pub fn is_borrowed(&self) -> bool {
+        !matches!(self.0, ChunkBytesInner::Owned(_))
+    }
+
+    /// Returns a mutable reference to the data, copying it first if it is borrowed.
+    pub fn to_mut(&mut self) -> &mut Vec<u8> {
+        if self.is_borrowed() {
+            self.0 = ChunkBytesInner::Owned(self.to_vec());
+        }
+
+        match &mut self.0 {
+            ChunkBytesInner::Owned(data) => data,
+            #[cfg(feature = "mmap")]
+            ChunkBytesInner::Mapped(_) => unreachable!("borrowed data was copied above"),
+        }
+    }
+
+    /// Returns the owned data, copying it if it is borrowed.
+    pub fn into_owned(self) -> Vec<u8> {
+        match self.0 {
+            ChunkBytesInner::Owned(data) => data,
+            #[cfg(feature = "mmap")]
+            ChunkBytesInner::Mapped(range) => range.as_ref().to_vec(),
+        }
+    }
+}
+
+impl Deref for ChunkBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.0 {
+            ChunkBytesInner::Owned(data) => data,
+            #[cfg(feature = "mmap")]
+            ChunkBytesInner::Mapped(range) => range.as_ref(),
+        }
+    }
+}
+
+impl AsRef<[u8]> for ChunkBytes {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl From<Vec<u8>> for ChunkBytes {
+    fn from(data: Vec<u8>) -> Self {
+        ChunkBytes(ChunkBytesInner::Owned(data))
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl From<MappedRange> for ChunkBytes {
+    fn from(range: MappedRange) -> Self {
+        ChunkBytes(ChunkBytesInner::Mapped(range))
+    }
+}
+
+/// A struct which holds all the data associated with a chunk.
 /// See EvtxChunk for more.
 pub struct EvtxChunkData {
     pub header: EvtxChunkHeader,
-    pub data: Vec<u8>,
+    pub data: ChunkBytes,
 }
 
 impl EvtxChunkData {
     /// Construct a new chunk from the given data.
     /// Note that even when validate_checksum is set to false, the header magic is still checked.
-    pub fn new(data: Vec<u8>, validate_checksum: bool) -> EvtxChunkResult<Self> {
-        let mut cursor = Cursor::new(data.as_slice());
+    pub fn new(data: impl Into<ChunkBytes>, validate_checksum: bool) -> EvtxChunkResult<Self> {
+        let data = data.into();
+        let mut cursor = Cursor::new(&*data);
         let header = EvtxChunkHeader::from_reader(&mut cursor)?;
 
         let chunk = EvtxChunkData { header, data };
-        if validate_checksum && !chunk.validate_checksum() {
-            // TODO: return checksum here.
-            return Err(ChunkError::InvalidChunkChecksum {
-                expected: 0,
-                found: 0,
-            });
+
+        if validate_checksum {
+            if !chunk.validate_header_checksum() {
+                return Err(ChunkError::InvalidChunkHeaderChecksum {
+                    expected: chunk.header.header_chunk_checksum,
+                    found: chunk.computed_header_checksum(),
+                });
+            }
+
+            if !chunk.validate_data_checksum() {
+                return Err(ChunkError::InvalidChunkChecksum {
+                    expected: chunk.header.events_checksum,
+                    found: chunk.computed_data_checksum(),
+                });
+            }
         }
 
         Ok(chunk)
@@ -85,6 +176,30 @@ impl EvtxChunkData {
         EvtxChunk::new(&self.data, &self.header, Arc::clone(&settings))
     }
 
+    /// Computes the checksum of the records in the chunk (the data up to the free space offset).
+    /// This is computed even if the chunk has the `NO_CRC32` flag set.
+    pub fn computed_data_checksum(&self) -> u32 {
+        let end =
+            (self.header.free_space_offset as usize).clamp(EVTX_CHUNK_HEADER_SIZE, self.data.len());
+
+        checksum_ieee(&self.data[EVTX_CHUNK_HEADER_SIZE..end])
+    }
+
+    /// Computes the checksum of the chunk header (excluding the flags and the checksum itself).
+    /// This is computed even if the chunk has the `NO_CRC32` flag set.
+    pub fn computed_header_checksum(&self) -> u32 {
+        let header_bytes_1 = &self.data[..120];
+        let header_bytes_2 = &self.data[128..512];
+
+        let bytes_for_checksum: Vec<u8> = header_bytes_1
+            .iter()
+            .chain(header_bytes_2)
+            .cloned()
+            .collect();
+
+        checksum_ieee(bytes_for_checksum.as_slice())
+    }
+
     pub fn validate_data_checksum(&self) -> bool {
         debug!("Validating data checksum");
 
@@ -97,9 +212,7 @@ impl EvtxChunkData {
         };
 
         let computed_checksum = if !checksum_disabled {
-            checksum_ieee(
-                &self.data[EVTX_CHUNK_HEADER_SIZE..self.header.free_space_offset as usize],
-            )
+            self.computed_data_checksum()
         } else {
             0
         };
@@ -123,17 +236,8 @@ impl EvtxChunkData {
             0
         };
 
-        let header_bytes_1 = &self.data[..120];
-        let header_bytes_2 = &self.data[128..512];
-
-        let bytes_for_checksum: Vec<u8> = header_bytes_1
-            .iter()
-            .chain(header_bytes_2)
-            .cloned()
-            .collect();
-
         let computed_checksum = if !checksum_disabled {
-            checksum_ieee(bytes_for_checksum.as_slice())
+            self.computed_header_checksum()
         } else {
             0
         };
@@ -149,6 +253,41 @@ impl EvtxChunkData {
     pub fn validate_checksum(&self) -> bool {
         self.validate_header_checksum() && self.validate_data_checksum()
     }
+
+    /// Reads the headers of the records in the chunk (along with their offsets), without parsing
+    /// the records themselves. Iteration stops after the first header which cannot be read.
+    pub(crate) fn record_headers(
+        &self,
+    ) -> impl Iterator<Item = (ChunkOffset, DeserializationResult<EvtxRecordHeader>)> + '_ {
+        let end = (self.header.free_space_offset as usize).min(self.data.len());
+        let mut offset = EVTX_CHUNK_HEADER_SIZE;
+        let mut failed = false;
+
+        std::iter::from_fn(move || {
+            if failed || offset >= end {
+                return None;
+            }
+
+            let header = EvtxRecordHeader::from_reader(&mut Cursor::new(&self.data[offset..]))
+                .and_then(|header| {
+                    if header.data_size < EVTX_RECORD_MIN_SIZE {
+                        Err(DeserializationError::InvalidEvtxRecordSize {
+                            size: header.data_size,
+                        })
+                    } else {
+                        Ok(header)
+                    }
+                });
+
+            let header_offset = offset as ChunkOffset;
+            match &header {
+                Ok(header) => offset += header.data_size as usize,
+                Err(_) => failed = true,
+            }
+
+            Some((header_offset, header))
+        })
+    }
 }
 
 /// A struct which can hold references to chunk data (`EvtxChunkData`).
@@ -195,15 +334,36 @@ impl<'chunk> EvtxChunk<'chunk> {
     /// See `IterChunkRecords` for a more detailed explanation regarding the lifetime scopes of the
     /// resulting records.
     pub fn iter(&mut self) -> IterChunkRecords {
+        self.iter_from_offset(EVTX_CHUNK_HEADER_SIZE as u64)
+    }
+
+    /// Return an iterator of records from the chunk, starting with the record at `offset`
+    /// (relative to the start of the chunk).
+    pub(crate) fn iter_from_offset(&mut self, offset: u64) -> IterChunkRecords<'_> {
+        // Records past the free space offset can only be found when recovering slack space.
+        let in_slack = self.settings.should_recover_slack_records()
+            && offset >= u64::from(self.header.free_space_offset);
+
         IterChunkRecords {
             settings: Arc::clone(&self.settings),
             chunk: self,
-            offset_from_chunk_start: EVTX_CHUNK_HEADER_SIZE as u64,
+            offset_from_chunk_start: offset,
             exhausted: false,
+            in_slack,
         }
     }
 }
 
+/// Describes chunk data which was skipped while resynchronizing after a corrupt record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordRecovery {
+    /// Offset of the corrupt record, relative to the start of the chunk.
+    pub skipped_from: ChunkOffset,
+    /// Offset of the next valid record, relative to the start of the chunk.
+    /// If no valid record was found, this is the end of the chunk's used space.
+    pub resumed_at: ChunkOffset,
+}
+
 /// An iterator over a chunk, yielding records.
 /// This iterator can be created using the `iter` function on `EvtxChunk`.
 ///
@@ -228,35 +388,85 @@ pub struct IterChunkRecords<'a> {
     chunk: &'a EvtxChunk<'a>,
     offset_from_chunk_start: u64,
     exhausted: bool,
+    /// Set once all the records in the used space of the chunk were read,
+    /// and the slack space is being scanned.
+    in_slack: bool,
     settings: Arc<ParserSettings>,
 }
 
-impl<'a> Iterator for IterChunkRecords<'a> {
-    type Item = std::result::Result<EvtxRecord<'a>, EvtxError>;
+impl<'a> IterChunkRecords<'a> {
+    /// End of the area of the chunk which may contain records.
+    fn records_end(&self) -> u64 {
+        u64::from(self.chunk.header.free_space_offset).min(self.chunk.data.len() as u64)
+    }
 
-    fn next(&mut self) -> Option<<Self as Iterator>::Item> {
-        if self.exhausted
-            || self.offset_from_chunk_start >= u64::from(self.chunk.header.free_space_offset)
-        {
-            return None;
-        }
+    /// Checks that a record starts at `offset`, and that its size matches the copy at its end.
+    fn is_valid_record(&self, offset: u64) -> bool {
+        let data = &self.chunk.data[..self.records_end() as usize];
+        let record = match data.get(offset as usize..) {
+            Some(record) if record.len() >= EVTX_RECORD_MIN_SIZE as usize => record,
+            _ => return false,
+        };
 
-        let mut cursor = Cursor::new(&self.chunk.data[self.offset_from_chunk_start as usize..]);
+        let size = LittleEndian::read_u32(&record[4..8]);
 
-        let record_header = match EvtxRecordHeader::from_reader(&mut cursor) {
-            Ok(record_header) => record_header,
-            Err(err) => {
-                // We currently do not try to recover after an invalid record.
-                self.exhausted = true;
+        record.starts_with(EVTX_RECORD_MAGIC)
+            && size >= EVTX_RECORD_MIN_SIZE
+            && size as usize <= record.len()
+            && LittleEndian::read_u32(&record[size as usize - 4..size as usize]) == size
+    }
 
-                return Some(Err(EvtxError::DeserializationError(err)));
-            }
-        };
+    /// Skips to the next valid record after the corrupt record at `corrupt_record_offset`.
+    fn resync(&mut self, corrupt_record_offset: u64, err: DeserializationError) -> EvtxError {
+        let end = self.records_end();
+        let search_start = (corrupt_record_offset + 1).min(end);
+
+        let resumed_at = memmem::find_iter(
+            &self.chunk.data[search_start as usize..end as usize],
+            EVTX_RECORD_MAGIC,
+        )
+        .map(|i| search_start + i as u64)
+        .find(|&offset| self.is_valid_record(offset))
+        .unwrap_or(end);
+
+        debug!(
+            "Skipping corrupt record data at 0x{:08x}..0x{:08x}",
+            corrupt_record_offset, resumed_at
+        );
+
+        self.offset_from_chunk_start = resumed_at;
+        self.exhausted = resumed_at == end;
 
+        EvtxError::SkippedCorruptRecordData {
+            recovery: RecordRecovery {
+                skipped_from: corrupt_record_offset as ChunkOffset,
+                resumed_at: resumed_at as ChunkOffset,
+            },
+            source: err,
+        }
+    }
+
+    /// Deserializes the tokens of the record whose header was read at `record_offset`.
+    fn read_record(
+        &self,
+        record_offset: u64,
+        record_header: &EvtxRecordHeader,
+    ) -> Result<EvtxRecord<'a>, EvtxError> {
         info!("Record id - {}", record_header.event_record_id);
         debug!("Record header - {:?}", record_header);
 
-        let binxml_data_size = record_header.record_data_size();
+        let record_data_offset = record_offset + EVTX_RECORD_HEADER_SIZE as u64;
+        let mut binxml_data_size = record_header.record_data_size();
+        let mut truncated = false;
+
+        // A slack record may claim more data than is left in the chunk, read what is there.
+        if self.in_slack {
+            let available = (self.chunk.data.len() as u64).saturating_sub(record_data_offset);
+            if u64::from(binxml_data_size) > available {
+                binxml_data_size = available as u32;
+                truncated = true;
+            }
+        }
 
         trace!("Need to deserialize {} bytes of binxml", binxml_data_size);
 
@@ -265,53 +475,233 @@ impl<'a> Iterator for IterChunkRecords<'a> {
         // We avoid creating new references so that `BinXmlDeserializer` can still generate 'a data.
         let deserializer = BinXmlDeserializer::init(
             self.chunk.data,
-            self.offset_from_chunk_start + cursor.position(),
+            record_data_offset,
             Some(self.chunk),
             false,
             self.settings.get_ansi_codec(),
         );
 
-        let mut tokens = vec![];
-        let iter = match deserializer
-            .iter_tokens(Some(binxml_data_size))
-            .map_err(|e| EvtxError::FailedToParseRecord {
-                record_id: record_header.event_record_id,
-                source: Box::new(EvtxError::DeserializationError(e)),
-            }) {
-            Ok(iter) => iter,
-            Err(err) => return Some(Err(err)),
+        let to_record_error = |e| EvtxError::FailedToParseRecord {
+            record_id: record_header.event_record_id,
+            source: Box::new(EvtxError::DeserializationError(e)),
         };
 
-        for token in iter {
-            match token.map_err(|e| EvtxError::FailedToParseRecord {
-                source: Box::new(EvtxError::DeserializationError(e)),
-                record_id: record_header.event_record_id,
-            }) {
+        let mut iter = deserializer
+            .iter_tokens(Some(binxml_data_size))
+            .map_err(to_record_error)?;
+
+        if self.in_slack {
+            iter = iter.keep_partial_templates();
+        }
+
+        let mut tokens = vec![];
+        let mut first_err = None;
+        for token in iter.by_ref() {
+            match token {
                 Ok(token) => tokens.push(token),
-                Err(err) => {
-                    self.offset_from_chunk_start += u64::from(record_header.data_size);
-                    return Some(Err(err));
+                // The data of a slack record may have been partially overwritten,
+                // keep whatever was read before it.
+                Err(err) if self.in_slack => {
+                    first_err = Some(err);
+                    break;
                 }
+                Err(err) => return Err(to_record_error(err)),
             }
         }
 
-        self.offset_from_chunk_start += u64::from(record_header.data_size);
+        truncated |= first_err.is_some() || iter.is_truncated();
 
-        if self.chunk.header.last_event_record_id == record_header.event_record_id {
-            self.exhausted = true;
+        if truncated {
+            let is_partial_record = matches!(
+                tokens.first(),
+                Some(BinXMLDeserializedTokens::FragmentHeader(_))
+            ) && tokens.len() > 1;
+
+            if !is_partial_record {
+                return Err(to_record_error(first_err.unwrap_or(
+                    DeserializationError::EvtxRecordPastChunkEnd {
+                        size: record_header.data_size,
+                    },
+                )));
+            }
+
+            close_open_elements(&mut tokens);
         }
 
-        Some(Ok(EvtxRecord {
+        Ok(EvtxRecord {
             chunk: self.chunk,
             event_record_id: record_header.event_record_id,
             timestamp: record_header.timestamp,
+            record_offset: record_offset as ChunkOffset,
+            recovered_from_slack: self.in_slack,
+            truncated,
             tokens,
             settings: Arc::clone(&self.settings),
-        }))
+        })
+    }
+
+    /// Scans the slack space of the chunk (the data after `free_space_offset`) for records which
+    /// were logically discarded, but whose data still exists.
+    ///
+    /// Records in the slack space are not required to have a valid size copy at their end,
+    /// as long as their binxml can be deserialized. Records whose data was cut short by the end of
+    /// the chunk, or by data which overwrote them, are returned with what could still be read,
+    /// and have `truncated` set.
+    fn next_slack_record(&mut self) -> Option<<Self as Iterator>::Item> {
+        let data = self.chunk.data;
+
+        loop {
+            let search_start = self.offset_from_chunk_start as usize;
+            let record_offset =
+                search_start + memmem::find(data.get(search_start..)?, EVTX_RECORD_MAGIC)?;
+
+            // Unless a record is found, keep scanning right after this magic.
+            self.offset_from_chunk_start = record_offset as u64 + 1;
+
+            let mut cursor = Cursor::new(&data[record_offset..]);
+            let record_header = match EvtxRecordHeader::from_reader(&mut cursor) {
+                Ok(record_header) => record_header,
+                Err(_) => continue,
+            };
+
+            if record_header.data_size < EVTX_RECORD_MIN_SIZE {
+                continue;
+            }
+
+            match self.read_record(record_offset as u64, &record_header) {
+                Ok(record) => {
+                    debug!(
+                        "Recovered record {} from slack space at 0x{:08x}",
+                        record.event_record_id, record_offset
+                    );
+                    // The data which overwrote a truncated record may hold other records.
+                    if !record.truncated {
+                        self.offset_from_chunk_start =
+                            record_offset as u64 + u64::from(record_header.data_size);
+                    }
+
+                    return Some(Ok(record));
+                }
+                Err(err) => {
+                    debug!(
+                        "Failed to recover record from slack space at 0x{:08x}: {}",
+                        record_offset, err
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Closes the elements which were left open by the tokens of a truncated record.
+fn close_open_elements(tokens: &mut Vec<BinXMLDeserializedTokens<'_>>) {
+    let mut open_elements = 0_usize;
+    let mut in_start_element = false;
+
+    for token in tokens.iter() {
+        match token {
+            BinXMLDeserializedTokens::OpenStartElement(_) => in_start_element = true,
+            BinXMLDeserializedTokens::CloseStartElement => {
+                in_start_element = false;
+                open_elements += 1;
+            }
+            BinXMLDeserializedTokens::CloseEmptyElement => in_start_element = false,
+            BinXMLDeserializedTokens::CloseElement => {
+                open_elements = open_elements.saturating_sub(1)
+            }
+            _ => {}
+        }
+    }
+
+    if in_start_element {
+        // An attribute name without its value cannot be written.
+        if let Some(BinXMLDeserializedTokens::Attribute(_)) = tokens.last() {
+            tokens.pop();
+        }
+        tokens.push(BinXMLDeserializedTokens::CloseEmptyElement);
+    }
+
+    tokens.extend((0..open_elements).map(|_| BinXMLDeserializedTokens::CloseElement));
+}
+
+impl<'a> Iterator for IterChunkRecords<'a> {
+    type Item = std::result::Result<EvtxRecord<'a>, EvtxError>;
+
+    fn next(&mut self) -> Option<<Self as Iterator>::Item> {
+        if self.in_slack {
+            return self.next_slack_record();
+        }
+
+        if self.exhausted || self.offset_from_chunk_start >= self.records_end() {
+            if self.settings.should_recover_slack_records() {
+                self.in_slack = true;
+                self.offset_from_chunk_start = u64::from(self.chunk.header.free_space_offset);
+
+                return self.next_slack_record();
+            }
+
+            return None;
+        }
+
+        let record_offset = self.offset_from_chunk_start;
+        let mut cursor = Cursor::new(&self.chunk.data[self.offset_from_chunk_start as usize..]);
+
+        let resync = self.settings.should_resync_after_corrupt_records();
+
+        let record_header = match EvtxRecordHeader::from_reader(&mut cursor) {
+            Ok(record_header) if resync && !self.is_valid_record(record_offset) => {
+                let err = DeserializationError::InvalidEvtxRecordSize {
+                    size: record_header.data_size,
+                };
+                return Some(Err(self.resync(record_offset, err)));
+            }
+            Ok(record_header) => record_header,
+            Err(err) if resync => return Some(Err(self.resync(record_offset, err))),
+            Err(err) => {
+                // Without resynchronization, we do not try to recover after an invalid record.
+                self.exhausted = true;
+
+                return Some(Err(EvtxError::DeserializationError(err)));
+            }
+        };
+
+        let record = self.read_record(record_offset, &record_header);
+        self.offset_from_chunk_start += u64::from(record_header.data_size);
+
+        if record.is_ok() && self.chunk.header.last_event_record_id == record_header.event_record_id
+        {
+            self.exhausted = true;
+        }
+
+        Some(record)
     }
 }
 
 impl EvtxChunkHeader {
+    /// Creates a header for a chunk whose original header was lost, and which only holds the record
+    /// at `record_offset`. The string and template tables are left empty, so names and templates
+    /// will be read directly from the chunk data.
+    pub(crate) fn for_orphan_record(
+        event_record_id: RecordId,
+        record_offset: u32,
+        record_size: u32,
+    ) -> EvtxChunkHeader {
+        EvtxChunkHeader {
+            first_event_record_number: event_record_id,
+            last_event_record_number: event_record_id,
+            first_event_record_id: event_record_id,
+            last_event_record_id: event_record_id,
+            header_size: 128,
+            last_event_record_data_offset: record_offset,
+            free_space_offset: record_offset + record_size,
+            events_checksum: 0,
+            header_chunk_checksum: 0,
+            flags: ChunkFlags::NO_CRC32,
+            strings_offsets: vec![0_u32; 64],
+            template_offsets: vec![0_u32; 32],
+        }
+    }
+
     pub fn from_reader(input: &mut Cursor<&[u8]>) -> DeserializationResult<EvtxChunkHeader> {
         let mut magic = [0_u8; 8];
         input.take(8).read_exact(&mut magic)?;
@@ -437,4 +827,165 @@ mod tests {
         let chunk = EvtxChunkData::new(chunk_data, false).unwrap();
         assert!(chunk.validate_checksum());
     }
+
+    #[test]
+    fn test_resyncs_after_corrupt_record() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+        let mut chunk_data =
+            evtx_file[EVTX_FILE_HEADER_SIZE..EVTX_FILE_HEADER_SIZE + EVTX_CHUNK_SIZE].to_vec();
+
+        let mut chunk = EvtxChunkData::new(chunk_data.clone(), false).unwrap();
+        let settings = Arc::new(ParserSettings::new());
+        let offsets: Vec<u64> = chunk
+            .parse(Arc::clone(&settings))
+            .unwrap()
+            .iter()
+            .map(|r| u64::from(r.unwrap().record_offset))
+            .collect();
+
+        // Corrupt the magic of the 5th record.
+        let corrupt_offset = offsets[4] as usize;
+        chunk_data[corrupt_offset] = 0xFF;
+
+        let mut chunk = EvtxChunkData::new(chunk_data, false).unwrap();
+
+        // Without resynchronization, the rest of the chunk is lost.
+        {
+            let mut parsed = chunk.parse(Arc::clone(&settings)).unwrap();
+            let records: Vec<_> = parsed.iter().collect();
+            assert_eq!(records.len(), 5);
+            assert!(records[4].is_err());
+        }
+
+        let settings = Arc::new(ParserSettings::new().resync_after_corrupt_records(true));
+        let mut parsed = chunk.parse(settings).unwrap();
+        let records: Vec<_> = parsed.iter().collect();
+
+        assert_eq!(records.len(), 91);
+        match &records[4] {
+            Err(EvtxError::SkippedCorruptRecordData { recovery, .. }) => assert_eq!(
+                *recovery,
+                RecordRecovery {
+                    skipped_from: offsets[4] as ChunkOffset,
+                    resumed_at: offsets[5] as ChunkOffset,
+                }
+            ),
+            other => panic!(
+                "Expected a recovery event, found {:?}",
+                other.as_ref().err()
+            ),
+        }
+
+        let ids: Vec<_> = records[5..]
+            .iter()
+            .map(|r| r.as_ref().unwrap().event_record_id)
+            .collect();
+        assert_eq!(ids, (6..=91).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_recovers_records_from_slack_space() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+        let mut chunk_data =
+            evtx_file[EVTX_FILE_HEADER_SIZE..EVTX_FILE_HEADER_SIZE + EVTX_CHUNK_SIZE].to_vec();
+
+        let mut chunk = EvtxChunkData::new(chunk_data.clone(), false).unwrap();
+        let expected: Vec<_> = chunk
+            .parse(Arc::new(ParserSettings::new()))
+            .unwrap()
+            .iter()
+            .map(|r| r.unwrap().into_xml().unwrap())
+            .collect();
+
+        let record_80_offset = chunk
+            .parse(Arc::new(ParserSettings::new()))
+            .unwrap()
+            .iter()
+            .nth(79)
+            .unwrap()
+            .unwrap()
+            .record_offset;
+
+        // Pretend the chunk ends before record 80, which leaves the last 12 records in the slack space.
+        chunk_data[32..40].copy_from_slice(&79_u64.to_le_bytes());
+        chunk_data[48..52].copy_from_slice(&record_80_offset.to_le_bytes());
+
+        let mut chunk = EvtxChunkData::new(chunk_data, false).unwrap();
+        assert_eq!(
+            chunk
+                .parse(Arc::new(ParserSettings::new()))
+                .unwrap()
+                .iter()
+                .count(),
+            79
+        );
+
+        let settings = Arc::new(ParserSettings::new().recover_slack_records(true));
+        let recovered: Vec<_> = chunk
+            .parse(settings)
+            .unwrap()
+            .iter()
+            .map(|r| r.unwrap().into_xml().unwrap())
+            .collect();
+
+        assert_eq!(recovered.len(), 91);
+        for (record, expected) in recovered.iter().zip(expected.iter()) {
+            assert_eq!(record.event_record_id, expected.event_record_id);
+            assert_eq!(record.data, expected.data);
+            assert_eq!(record.recovered_from_slack, record.event_record_id >= 80);
+        }
+    }
+
+    #[test]
+    fn test_recovers_partially_overwritten_records_from_slack_space() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+        let mut chunk_data =
+            evtx_file[EVTX_FILE_HEADER_SIZE..EVTX_FILE_HEADER_SIZE + EVTX_CHUNK_SIZE].to_vec();
+
+        let mut chunk = EvtxChunkData::new(chunk_data.clone(), false).unwrap();
+        let settings = Arc::new(ParserSettings::new());
+        let mut parsed_chunk = chunk.parse(settings).unwrap();
+        let last_record = parsed_chunk.iter().last().unwrap().unwrap();
+        let record_offset = last_record.record_offset as usize;
+        let expected = last_record.into_json_value().unwrap();
+
+        let record_size = LittleEndian::read_u32(&chunk_data[record_offset + 4..]) as usize;
+        let record_end = record_offset + record_size;
+
+        // Pretend the chunk ends before the last record, and overwrite the second half of it.
+        let last_record_id = expected.event_record_id - 1;
+        chunk_data[32..40].copy_from_slice(&last_record_id.to_le_bytes());
+        chunk_data[48..52].copy_from_slice(&(record_offset as u32).to_le_bytes());
+        chunk_data[record_end - record_size / 2..record_end].fill(0xff);
+
+        let mut chunk = EvtxChunkData::new(chunk_data, false).unwrap();
+        let settings = Arc::new(ParserSettings::new().recover_slack_records(true));
+        let recovered = chunk
+            .parse(settings)
+            .unwrap()
+            .iter()
+            .last()
+            .unwrap()
+            .unwrap()
+            .into_json_value()
+            .unwrap();
+
+        assert_eq!(recovered.event_record_id, expected.event_record_id);
+        assert!(recovered.recovered_from_slack);
+        assert!(recovered.truncated);
+        assert!(!expected.truncated);
+
+        // The values stored at the start of the record were not overwritten.
+        let (recovered, expected) = (
+            &recovered.data["Event"]["System"],
+            &expected.data["Event"]["System"],
+        );
+        for field in ["EventID", "TimeCreated", "Computer"] {
+            assert_eq!(recovered[field], expected[field]);
+        }
+        assert_ne!(recovered["Channel"], expected["Channel"]);
+    }
 }
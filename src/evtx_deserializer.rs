@@ -0,0 +1,841 @@
+use crate::binxml::assemble::{create_record_model, expand_templates};
+use crate::binxml::value_variant::BinXmlValue;
+use crate::err::{EvtxError, Result};
+use crate::evtx_system::parse_number;
+use crate::model::xml::{char_from_reference, XmlAttribute, XmlModel};
+use crate::EvtxRecord;
+
+use serde::de::value::SeqDeserializer;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, Visitor};
+use serde::forward_to_deserialize_any;
+use std::borrow::Cow;
+use std::fmt;
+
+/// The key of the text of elements which also have attributes or child elements.
+const TEXT_KEY: &str = "#text";
+
+impl de::Error for EvtxError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        EvtxError::FailedToDeserializeRecord {
+            message: msg.to_string(),
+        }
+    }
+}
+
+/// An element of a record, borrowing its attributes and values from the record model.
+#[derive(Debug, Default)]
+struct Element<'m, 'a> {
+    name: &'m str,
+    attributes: &'m [XmlAttribute<'a>],
+    text: Vec<Cow<'m, BinXmlValue<'a>>>,
+    children: Vec<Element<'m, 'a>>,
+}
+
+impl<'m, 'a> Element<'m, 'a> {
+    /// Builds the root element of the record.
+    fn from_model(model: &'m [XmlModel<'a>]) -> Result<Element<'m, 'a>> {
+        let mut stack = vec![Element::default()];
+
+        for token in model {
+            match token {
+                XmlModel::OpenElement(element) => stack.push(Element {
+                    name: element.name.as_str(),
+                    attributes: &element.attributes,
+                    ..Element::default()
+                }),
+                XmlModel::CloseElement if stack.len() > 1 => {
+                    let element = stack.pop().expect("stack has more than one element");
+                    if let Some(parent) = stack.last_mut() {
+                        parent.children.push(element);
+                    }
+                }
+                XmlModel::Value(value) => {
+                    if let Some(element) = stack.last_mut() {
+                        element.text.push(Cow::Borrowed(value.as_ref()));
+                    }
+                }
+                XmlModel::EntityRef(entity) => {
+                    if let Some(element) = stack.last_mut() {
+                        let text = match entity.as_str() {
+                            "amp" => "&".to_string(),
+                            "lt" => "<".to_string(),
+                            "gt" => ">".to_string(),
+                            "quot" => "\"".to_string(),
+                            "apos" => "'".to_string(),
+                            other => format!("&{};", other),
+                        };
+                        element.text.push(Cow::Owned(BinXmlValue::StringType(text)));
+                    }
+                }
+                XmlModel::CDATA(data) => {
+                    if let Some(element) = stack.last_mut() {
+                        element
+                            .text
+                            .push(Cow::Owned(BinXmlValue::StringType(data.to_string())));
+                    }
+                }
+                XmlModel::CharRef(value) => {
+                    if let Some(element) = stack.last_mut() {
+                        element.text.push(Cow::Owned(BinXmlValue::StringType(
+                            char_from_reference(*value).to_string(),
+                        )));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Close the elements which were left open.
+        while stack.len() > 1 {
+            let element = stack.pop().expect("stack has more than one element");
+            if let Some(parent) = stack.last_mut() {
+                parent.children.push(element);
+            }
+        }
+
+        stack
+            .pop()
+            .and_then(|document| document.children.into_iter().next())
+            .ok_or_else(|| de::Error::custom("record has no root element"))
+    }
+
+    /// Set for `Data` elements with a `Name` attribute, which are named after it.
+    fn data_name(&self) -> Option<&'m BinXmlValue<'a>> {
+        if self.name != "Data" {
+            return None;
+        }
+
+        self.attributes
+            .iter()
+            .find(|attr| attr.name.as_str() == "Name")
+            .map(|attr| attr.value.as_ref())
+    }
+
+    /// The key of the element in its parent.
+    fn key(&self) -> Result<Cow<'m, str>> {
+        match self.data_name() {
+            Some(name) => value_as_str(name),
+            None => Ok(Cow::Borrowed(self.name)),
+        }
+    }
+
+    /// The attributes of the element, without empty attributes
+    /// (and without the `Name` of `Data` elements, which is their key).
+    fn attributes(&self) -> impl Iterator<Item = &'m XmlAttribute<'a>> {
+        let is_named_data = self.data_name().is_some();
+
+        self.attributes.iter().filter(move |attr| {
+            !matches!(attr.value.as_ref(), BinXmlValue::NullType)
+                && (!is_named_data || attr.name.as_str() != "Name")
+        })
+    }
+
+    /// Elements without attributes or child elements are deserialized as their text.
+    fn is_text_only(&self) -> bool {
+        self.children.is_empty() && self.attributes().next().is_none()
+    }
+
+    /// The text of the element, as a single value.
+    fn text(&self) -> Result<Cow<'_, BinXmlValue<'a>>> {
+        match self.text.as_slice() {
+            [] => Ok(Cow::Owned(BinXmlValue::NullType)),
+            [value] => Ok(Cow::Borrowed(value.as_ref())),
+            values => {
+                let mut text = String::new();
+                for value in values {
+                    text.push_str(&value_as_str(value)?);
+                }
+
+                Ok(Cow::Owned(BinXmlValue::StringType(text)))
+            }
+        }
+    }
+}
+
+/// Like `BinXmlValue::as_cow_str`, as a deserialization error.
+fn value_as_str<'v>(value: &'v BinXmlValue) -> Result<Cow<'v, str>> {
+    value.as_cow_str().map_err(de::Error::custom)
+}
+
+/// The items of an array value, `None` if the value is not an array.
+fn array_items<'v>(value: &BinXmlValue<'v>) -> Option<Vec<BinXmlValue<'v>>> {
+    macro_rules! items {
+        ($values: expr, $variant: ident) => {
+            Some($values.iter().cloned().map(BinXmlValue::$variant).collect())
+        };
+    }
+
+    match value {
+        BinXmlValue::StringArrayType(values) => items!(values, StringType),
+        BinXmlValue::AnsiStringArrayType(values) => items!(values, StringType),
+        BinXmlValue::EvtXmlArrayType(values) => items!(values, EvtXml),
+        BinXmlValue::Int8ArrayType(values) => items!(values, Int8Type),
+        BinXmlValue::UInt8ArrayType(values) => items!(values, UInt8Type),
+        BinXmlValue::Int16ArrayType(values) => items!(values, Int16Type),
+        BinXmlValue::UInt16ArrayType(values) => items!(values, UInt16Type),
+        BinXmlValue::Int32ArrayType(values) => items!(values, Int32Type),
+        BinXmlValue::UInt32ArrayType(values) => items!(values, UInt32Type),
+        BinXmlValue::Int64ArrayType(values) => items!(values, Int64Type),
+        BinXmlValue::UInt64ArrayType(values) => items!(values, UInt64Type),
+        BinXmlValue::Real32ArrayType(values) => items!(values, Real32Type),
+        BinXmlValue::Real64ArrayType(values) => items!(values, Real64Type),
+        BinXmlValue::BoolArrayType(values) => items!(values, BoolType),
+        BinXmlValue::GuidArrayType(values) => items!(values, GuidType),
+        BinXmlValue::FileTimeArrayType(values) => items!(values, FileTimeType),
+        BinXmlValue::SysTimeArrayType(values) => items!(values, SysTimeType),
+        BinXmlValue::SidArrayType(values) => items!(values, SidType),
+        BinXmlValue::HexInt32ArrayType(values) => items!(values, HexInt32Type),
+        BinXmlValue::HexInt64ArrayType(values) => items!(values, HexInt64Type),
+        BinXmlValue::SizeTArrayType(values) => items!(values, HexInt64Type),
+        BinXmlValue::EvtArrayHandle(values) => items!(values, HexInt64Type),
+        _ => None,
+    }
+}
+
+/// Deserializes a single value, keeping its native type.
+///
+/// Integers, floats and booleans are deserialized as such, arrays as sequences,
+/// and other values (strings, GUIDs, SIDs, hex integers, timestamps, binary data) as strings,
+/// in the same representation used by the JSON output.
+/// Strings (and hex integers) can also be deserialized as numbers and booleans.
+struct ValueDeserializer<'v, 'a> {
+    value: Cow<'v, BinXmlValue<'a>>,
+}
+
+impl<'v, 'a> ValueDeserializer<'v, 'a> {
+    fn new(value: Cow<'v, BinXmlValue<'a>>) -> Self {
+        ValueDeserializer { value }
+    }
+
+    /// The value as a string, for values which are strings in the record.
+    fn as_text(&self) -> Option<&str> {
+        match self.value.as_ref() {
+            BinXmlValue::StringType(s) => Some(s),
+            BinXmlValue::AnsiStringType(s)
+            | BinXmlValue::HexInt32Type(s)
+            | BinXmlValue::HexInt64Type(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn deserialize_number<'de, V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let text = match self.as_text() {
+            Some(text) => text.trim(),
+            None => return de::Deserializer::deserialize_any(self, visitor),
+        };
+
+        if let Some(number) = parse_number(text) {
+            visitor.visit_u64(number)
+        } else if let Ok(number) = text.parse::<i64>() {
+            visitor.visit_i64(number)
+        } else if let Ok(number) = text.parse::<f64>() {
+            visitor.visit_f64(number)
+        } else {
+            visitor.visit_str(text)
+        }
+    }
+}
+
+impl<'v, 'a> IntoDeserializer<'_, EvtxError> for ValueDeserializer<'v, 'a> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! deserialize_number {
+    ($($method: ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+                self.deserialize_number(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de, 'v, 'a> de::Deserializer<'de> for ValueDeserializer<'v, 'a> {
+    type Error = EvtxError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.value.as_ref() {
+            BinXmlValue::NullType => visitor.visit_unit(),
+            BinXmlValue::StringType(s) => visitor.visit_str(s),
+            BinXmlValue::AnsiStringType(s) => visitor.visit_str(s),
+            BinXmlValue::Int8Type(n) => visitor.visit_i8(*n),
+            BinXmlValue::UInt8Type(n) => visitor.visit_u8(*n),
+            BinXmlValue::Int16Type(n) => visitor.visit_i16(*n),
+            BinXmlValue::UInt16Type(n) => visitor.visit_u16(*n),
+            BinXmlValue::Int32Type(n) => visitor.visit_i32(*n),
+            BinXmlValue::UInt32Type(n) => visitor.visit_u32(*n),
+            BinXmlValue::Int64Type(n) => visitor.visit_i64(*n),
+            BinXmlValue::UInt64Type(n) => visitor.visit_u64(*n),
+            BinXmlValue::Real32Type(n) => visitor.visit_f32(*n),
+            BinXmlValue::Real64Type(n) => visitor.visit_f64(*n),
+            BinXmlValue::BoolType(b) => visitor.visit_bool(*b),
+            BinXmlValue::SizeTType(n) => visitor.visit_u64(*n as u64),
+            value => match array_items(value) {
+                Some(_) => self.deserialize_seq(visitor),
+                None => visitor.visit_str(&value_as_str(value)?),
+            },
+        }
+    }
+
+    deserialize_number! {
+        deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64
+        deserialize_f32 deserialize_f64
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.as_text().map(str::trim) {
+            Some(text) if text.eq_ignore_ascii_case("true") => visitor.visit_bool(true),
+            Some(text) if text.eq_ignore_ascii_case("false") => visitor.visit_bool(false),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_str(&value_as_str(&self.value)?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.value.as_ref() {
+            BinXmlValue::BinaryType(bytes) | BinXmlValue::BinaryArrayType(bytes) => {
+                visitor.visit_bytes(bytes)
+            }
+            value => visitor.visit_bytes(value_as_str(value)?.as_bytes()),
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.value.as_ref() {
+            BinXmlValue::NullType => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match array_items(&self.value) {
+            Some(items) => SeqDeserializer::new(
+                items
+                    .into_iter()
+                    .map(|item| ValueDeserializer::new(Cow::Owned(item))),
+            )
+            .deserialize_any(visitor),
+            // A single value is a sequence of one item.
+            None => SeqDeserializer::new(std::iter::once(self)).deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        let variant: String = value_as_str(&self.value)?.into_owned();
+        visitor.visit_enum(variant.into_deserializer())
+    }
+
+    forward_to_deserialize_any! {
+        unit unit_struct map struct identifier ignored_any
+    }
+}
+
+/// Deserializes an element.
+///
+/// Elements without attributes or child elements are deserialized as their text (see `ValueDeserializer`),
+/// other elements as maps of their attributes and child elements (and their text, under `#text`).
+/// `Data` elements with a `Name` attribute are keyed by it, and elements appearing several times
+/// under the same key are deserialized as sequences.
+///
+/// Primitive types are always deserialized from the text of the element, ignoring its attributes.
+struct ElementDeserializer<'e, 'a> {
+    element: &'e Element<'e, 'a>,
+}
+
+impl<'e, 'a> IntoDeserializer<'_, EvtxError> for ElementDeserializer<'e, 'a> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! deserialize_text {
+    ($($method: ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+                ValueDeserializer::new(self.element.text()?).$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de, 'e, 'a> de::Deserializer<'de> for ElementDeserializer<'e, 'a> {
+    type Error = EvtxError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        if self.element.is_text_only() {
+            ValueDeserializer::new(self.element.text()?).deserialize_any(visitor)
+        } else {
+            self.deserialize_map(visitor)
+        }
+    }
+
+    deserialize_text! {
+        deserialize_bool
+        deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64
+        deserialize_f32 deserialize_f64
+        deserialize_char deserialize_str deserialize_string
+        deserialize_bytes deserialize_byte_buf
+        deserialize_unit deserialize_identifier
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        if self.element.is_text_only() && self.element.text.is_empty() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        if self.element.is_text_only() {
+            ValueDeserializer::new(self.element.text()?).deserialize_seq(visitor)
+        } else {
+            SeqDeserializer::new(std::iter::once(self)).deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_map(ElementMapAccess::new(self.element)?)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        ValueDeserializer::new(self.element.text()?).deserialize_enum(name, variants, visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+}
+
+/// Deserializes the elements which appear several times under the same key, as a sequence.
+struct ElementsDeserializer<'e, 'a> {
+    elements: Vec<&'e Element<'e, 'a>>,
+}
+
+impl<'de, 'e, 'a> de::Deserializer<'de> for ElementsDeserializer<'e, 'a> {
+    type Error = EvtxError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        SeqDeserializer::new(
+            self.elements
+                .into_iter()
+                .map(|element| ElementDeserializer { element }),
+        )
+        .deserialize_any(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_some(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf unit unit_struct
+        newtype_struct seq tuple tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+/// A value of an element, when it is deserialized as a map.
+enum Entry<'e, 'a> {
+    Value(Cow<'e, BinXmlValue<'a>>),
+    Elements(Vec<&'e Element<'e, 'a>>),
+}
+
+struct ElementMapAccess<'e, 'a> {
+    entries: std::vec::IntoIter<(Cow<'e, str>, Entry<'e, 'a>)>,
+    value: Option<Entry<'e, 'a>>,
+}
+
+impl<'e, 'a> ElementMapAccess<'e, 'a> {
+    fn new(element: &'e Element<'e, 'a>) -> Result<Self> {
+        let mut entries: Vec<(Cow<'e, str>, Entry<'e, 'a>)> = vec![];
+
+        for attr in element.attributes() {
+            entries.push((
+                Cow::Borrowed(attr.name.as_str()),
+                Entry::Value(Cow::Borrowed(attr.value.as_ref())),
+            ));
+        }
+
+        if !element.text.is_empty() {
+            entries.push((Cow::Borrowed(TEXT_KEY), Entry::Value(element.text()?)));
+        }
+
+        for child in &element.children {
+            let key = child.key()?;
+
+            match entries.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, Entry::Elements(elements))) => elements.push(child),
+                _ => entries.push((key, Entry::Elements(vec![child]))),
+            }
+        }
+
+        Ok(ElementMapAccess {
+            entries: entries.into_iter(),
+            value: None,
+        })
+    }
+}
+
+impl<'de, 'e, 'a> MapAccess<'de> for ElementMapAccess<'e, 'a> {
+    type Error = EvtxError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(key.as_ref().into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        match self.value.take() {
+            Some(Entry::Value(value)) => seed.deserialize(ValueDeserializer::new(value)),
+            Some(Entry::Elements(mut elements)) => {
+                if elements.len() == 1 {
+                    seed.deserialize(ElementDeserializer {
+                        element: elements.remove(0),
+                    })
+                } else {
+                    seed.deserialize(ElementsDeserializer { elements })
+                }
+            }
+            None => Err(de::Error::custom("value is missing")),
+        }
+    }
+}
+
+impl<'a> EvtxRecord<'a> {
+    /// Consumes the record, deserializing it into `T` directly from its tokens
+    /// (without building an intermediate XML or JSON document).
+    ///
+    /// The root `Event` element is deserialized as a map of its child elements (`System`, `EventData`, ...),
+    /// where elements without attributes or children are deserialized as their text, and other elements
+    /// as maps of their attributes and children (the text of such elements is under `#text`).
+    /// `Data` elements with a `Name` attribute are keyed by it, and repeated elements are sequences.
+    ///
+    /// Values keep their native type: integers are integers, timestamps are RFC 3339 strings which
+    /// can be deserialized into `DateTime<Utc>`, and GUIDs, SIDs and hex integers are strings.
+    /// Strings (and hex integers) can also be deserialized as numbers.
+    ///
+    /// ```rust
+    /// # use evtx::EvtxParser;
+    /// # use serde::Deserialize;
+    /// # use chrono::{DateTime, Utc};
+    /// # let fp = std::path::PathBuf::from(format!("{}/samples/security.evtx", std::env::var("CARGO_MANIFEST_DIR").unwrap()));
+    /// #[derive(Deserialize)]
+    /// #[serde(rename_all = "PascalCase")]
+    /// struct Event {
+    ///     system: System,
+    ///     event_data: Option<Logon>,
+    /// }
+    ///
+    /// #[derive(Deserialize)]
+    /// #[serde(rename_all = "PascalCase")]
+    /// struct System {
+    ///     #[serde(rename = "EventID")]
+    ///     event_id: u16,
+    ///     time_created: TimeCreated,
+    /// }
+    ///
+    /// #[derive(Deserialize)]
+    /// #[serde(rename_all = "PascalCase")]
+    /// struct TimeCreated {
+    ///     system_time: DateTime<Utc>,
+    /// }
+    ///
+    /// #[derive(Deserialize)]
+    /// #[serde(rename_all = "PascalCase")]
+    /// struct Logon {
+    ///     target_user_name: Option<String>,
+    ///     logon_type: Option<u32>,
+    /// }
+    ///
+    /// let mut parser = EvtxParser::from_path(fp).unwrap();
+    ///
+    /// for event in parser.serialized_records(|record| record?.deserialize::<Event>()) {
+    ///     let event = event.unwrap();
+    ///     println!("{} {}", event.system.event_id, event.system.time_created.system_time);
+    /// }
+    /// ```
+    pub fn deserialize<T: DeserializeOwned>(self) -> Result<T> {
+        let event_record_id = self.event_record_id;
+
+        let tokens = expand_templates(self.tokens, self.chunk)?;
+        let model = create_record_model(tokens, self.chunk)?;
+
+        Element::from_model(&model)
+            .and_then(|root| T::deserialize(ElementDeserializer { element: &root }))
+            .map_err(|e| EvtxError::FailedToParseRecord {
+                record_id: event_record_id,
+                source: Box::new(e),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ensure_env_logger_initialized, EvtxParser};
+    use chrono::{DateTime, Utc};
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Event {
+        system: System,
+        event_data: HashMap<String, Option<String>>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct System {
+        provider: Provider,
+        #[serde(rename = "EventID")]
+        event_id: u16,
+        level: u8,
+        keywords: u64,
+        time_created: TimeCreated,
+        #[serde(rename = "EventRecordID")]
+        event_record_id: u64,
+        execution: Execution,
+        channel: String,
+        security: Option<Security>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Provider {
+        name: String,
+        guid: String,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct TimeCreated {
+        system_time: DateTime<Utc>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Execution {
+        #[serde(rename = "ProcessID")]
+        process_id: u32,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Security {
+        #[serde(rename = "UserID")]
+        user_id: Option<String>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Logon {
+        target_user_name: String,
+        target_logon_id: u64,
+        logon_type: u32,
+        ip_address: Option<String>,
+        ip_port: String,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct LogonEvent {
+        event_data: Logon,
+    }
+
+    #[test]
+    fn test_deserializes_record_into_struct() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+        let mut parser = EvtxParser::from_buffer(evtx_file.to_vec()).unwrap();
+
+        let events: Vec<Event> = parser
+            .serialized_records(|record| record?.deserialize::<Event>())
+            .take(2)
+            .map(|event| event.unwrap())
+            .collect();
+
+        let system = &events[1].system;
+        assert_eq!(system.provider.name, "Microsoft-Windows-Security-Auditing");
+        assert_eq!(system.provider.guid, "54849625-5478-4994-A5BA-3E3B0328C30D");
+        assert_eq!(system.event_id, 4624);
+        assert_eq!(system.level, 0);
+        assert_eq!(system.keywords, 0x8020000000000000);
+        assert_eq!(
+            system.time_created.system_time,
+            "2016-07-08T18:12:51.681640Z"
+                .parse::<DateTime<Utc>>()
+                .unwrap()
+        );
+        assert_eq!(system.event_record_id, 2);
+        assert_eq!(system.execution.process_id, 456);
+        assert_eq!(system.channel, "Security");
+        // `<Security />` is empty in this record.
+        assert!(system.security.is_none());
+
+        assert_eq!(
+            events[1].event_data.get("TargetUserName"),
+            Some(&Some("SYSTEM".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_deserializes_typed_event_data() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+        let mut parser = EvtxParser::from_buffer(evtx_file.to_vec()).unwrap();
+
+        let logon = parser
+            .serialized_records(|record| record?.deserialize::<LogonEvent>())
+            .nth(1)
+            .unwrap()
+            .unwrap()
+            .event_data;
+
+        assert_eq!(logon.target_user_name, "SYSTEM");
+        assert_eq!(logon.target_logon_id, 0x3e7);
+        assert_eq!(logon.logon_type, 0);
+        assert_eq!(logon.ip_address.as_deref(), Some("-"));
+        assert_eq!(logon.ip_port, "-");
+    }
+
+    #[test]
+    fn test_deserializes_record_into_json_value() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+        let mut parser = EvtxParser::from_buffer(evtx_file.to_vec()).unwrap();
+
+        let value = parser
+            .serialized_records(|record| record?.deserialize::<serde_json::Value>())
+            .nth(1)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(value["System"]["EventID"], 4624);
+        assert_eq!(value["System"]["Execution"]["ProcessID"], 456);
+        assert_eq!(
+            value["System"]["Provider"]["Name"],
+            "Microsoft-Windows-Security-Auditing"
+        );
+        assert_eq!(value["EventData"]["TargetUserName"], "SYSTEM");
+    }
+
+    #[test]
+    fn test_reports_type_mismatches() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+        let mut parser = EvtxParser::from_buffer(evtx_file.to_vec()).unwrap();
+
+        #[derive(Debug, Deserialize)]
+        #[serde(rename_all = "PascalCase")]
+        struct Invalid {
+            #[allow(dead_code)]
+            system: u32,
+        }
+
+        let result = parser
+            .serialized_records(|record| record?.deserialize::<Invalid>())
+            .next()
+            .unwrap();
+
+        assert!(matches!(
+            result,
+            Err(EvtxError::FailedToParseRecord { record_id: 1, .. })
+        ));
+    }
+}
@@ -0,0 +1,490 @@
+use crate::err::{EvtxError, Result};
+
+use crate::evtx_parser::ReadSeek;
+use crate::evtx_record::{RecordId, SerializedEvtxRecord};
+use crate::{EvtxParser, EvtxRecord};
+
+use hashbrown::HashMap;
+use log::debug;
+use std::fmt;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// Yields the records which are appended to an evtx file while it is being written to,
+/// similar to `tail -f`.
+///
+/// The follower remembers the last record it has yielded from every chunk.
+/// On every poll, the file header is re-read (to find chunks which were added to the file),
+/// and the chunk headers are read forward from the chunk of the newest record yielded so far.
+/// Any chunk whose header claims to hold newer records is parsed again.
+/// When the file wraps around, the headers of all the chunks are read, since a chunk which
+/// is overwritten by the event log service will contain records with newer ids.
+///
+/// Example usage:
+///
+/// ```rust,no_run
+/// # use evtx::EvtxParser;
+/// # use std::time::Duration;
+/// let parser = EvtxParser::from_path("Security.evtx").unwrap();
+/// let mut follower = parser.follow();
+///
+/// // The records which already exist are parsed in parallel.
+/// for record in follower.records() {
+///     match record {
+///         Ok(r) => println!("Record {}\n{}", r.event_record_id, r.data),
+///         Err(e) => eprintln!("{}", e),
+///     }
+/// }
+///
+/// loop {
+///     for record in follower.poll().unwrap() {
+///         match record {
+///             Ok(r) => println!("Record {}\n{}", r.event_record_id, r.data),
+///             Err(e) => eprintln!("{}", e),
+///         }
+///     }
+///
+///     std::thread::sleep(Duration::from_secs(1));
+/// }
+/// ```
+pub struct EvtxFollower<T: ReadSeek> {
+    parser: EvtxParser<T>,
+    /// The id of the last record yielded from each chunk.
+    last_record_ids: HashMap<u64, RecordId>,
+}
+
+impl<T: ReadSeek> Debug for EvtxFollower<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> ::std::fmt::Result {
+        f.debug_struct("EvtxFollower")
+            .field("parser", &self.parser)
+            .field("last_record_ids", &self.last_record_ids)
+            .finish()
+    }
+}
+
+impl<T: ReadSeek> EvtxFollower<T> {
+    pub fn new(parser: EvtxParser<T>) -> Self {
+        EvtxFollower {
+            parser,
+            last_record_ids: HashMap::new(),
+        }
+    }
+
+    /// Marks all the records which currently exist in the file as seen,
+    /// so that only records appended from now on will be yielded.
+    pub fn skip_existing(&mut self) -> Result<()> {
+        self.parser.reload_file_header()?;
+
+        for chunk_number in 0..self.parser.calculated_chunk_count {
+            if let Ok(Some(header)) =
+                EvtxParser::read_chunk_header(&mut self.parser.data, chunk_number)
+            {
+                self.last_record_ids
+                    .insert(chunk_number, header.last_event_record_id);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Return an iterator over the records which currently exist in the file, like
+    /// `EvtxParser::serialized_records` (chunks are parsed in parallel).
+    /// Records will be mapped `f`, which must produce owned data from the records.
+    ///
+    /// The records which were yielded are remembered, so the following polls only return newer records.
+    pub fn serialized_records<'a, U: Send + 'a>(
+        &'a mut self,
+        mut f: impl FnMut(Result<EvtxRecord<'_>>) -> Result<U> + Send + Sync + Clone + 'a,
+    ) -> impl Iterator<Item = Result<U>> + 'a {
+        let last_record_ids = &mut self.last_record_ids;
+
+        self.parser
+            .serialized_records_with_chunk_number(move |record, chunk_number| {
+                let record_id = record_id(&record);
+                Ok((chunk_number, record_id, f(record)))
+            })
+            .map(move |record| {
+                let (chunk_number, record_id, record) = record?;
+
+                if let Some(record_id) = record_id {
+                    let last_record_id = last_record_ids.entry(chunk_number).or_insert(record_id);
+                    *last_record_id = (*last_record_id).max(record_id);
+                }
+
+                record
+            })
+    }
+
+    /// Return an iterator over the records which currently exist in the file.
+    /// Records will be XML-formatted.
+    pub fn records(&mut self) -> impl Iterator<Item = Result<SerializedEvtxRecord<String>>> + '_ {
+        self.serialized_records(|record| record.and_then(|record| record.into_xml()))
+    }
+
+    /// Return an iterator over the records which currently exist in the file.
+    /// Records will be JSON-formatted.
+    pub fn records_json(
+        &mut self,
+    ) -> impl Iterator<Item = Result<SerializedEvtxRecord<String>>> + '_ {
+        self.serialized_records(|record| record.and_then(|record| record.into_json()))
+    }
+
+    /// Returns the records which were added to the file since the previous poll
+    /// (the first poll returns all the records in the file, unless `skip_existing` was called,
+    /// or they were already yielded by `serialized_records`).
+    /// Records will be mapped `f`, which must produce owned data from the records.
+    ///
+    /// Only the headers of the chunks are read before returning, the chunks which have new records
+    /// are read and parsed one at a time while iterating.
+    /// Records are returned in the order they were written.
+    /// Records rejected by `ParserSettings::record_filter` are skipped.
+    pub fn poll_serialized<'a, U: 'a>(
+        &'a mut self,
+        mut f: impl FnMut(Result<EvtxRecord<'_>>) -> Result<U> + 'a,
+    ) -> Result<impl Iterator<Item = Result<U>> + 'a> {
+        self.parser.reload_file_header()?;
+
+        let updated_chunks = self.updated_chunks();
+
+        Ok(updated_chunks
+            .into_iter()
+            .flat_map(move |chunk_number| self.poll_chunk(chunk_number, &mut f)))
+    }
+
+    /// Returns the chunks which have new records, in the order they were written.
+    ///
+    /// The file header is only flushed periodically by the event log service, so the chunk headers
+    /// are used to find the chunks which have new records. Chunks are written one after the other,
+    /// so the headers are read forward from the chunk of the newest record yielded so far,
+    /// until a chunk without new records is found.
+    ///
+    /// The headers of all the chunks are read when no record was yielded yet, when the file wrapped
+    /// around (the first chunk was updated after the scan reached the end of the file, or the current
+    /// chunk of the file header is before the chunk of the newest record), or when the file header
+    /// claims records which were not found by the forward scan.
+    fn updated_chunks(&mut self) -> Vec<u64> {
+        let (start, newest_record_id) = match self
+            .last_record_ids
+            .iter()
+            .max_by_key(|(_, record_id)| **record_id)
+        {
+            Some((chunk_number, record_id)) => (*chunk_number, *record_id),
+            None => return self.scan_all_chunks(),
+        };
+
+        let next_record_id = self.parser.header.next_record_id;
+        let header_chunk_number = self.parser.header.last_chunk_number;
+        let header_has_new_records = next_record_id > newest_record_id.saturating_add(1);
+
+        if header_has_new_records && header_chunk_number < start {
+            debug!("File header moved back to chunk {}", header_chunk_number);
+            return self.scan_all_chunks();
+        }
+
+        let mut updated_chunks = vec![];
+        let mut chunk_number = start;
+
+        while chunk_number < self.parser.calculated_chunk_count {
+            if self.is_chunk_updated(chunk_number) {
+                updated_chunks.push(chunk_number);
+            } else if chunk_number != start {
+                // The chunk of the newest record may be full, with new records in the next chunk.
+                break;
+            }
+
+            chunk_number += 1;
+        }
+
+        // After the last chunk, the event log service continues writing in the first chunk.
+        if chunk_number == self.parser.calculated_chunk_count
+            && start != 0
+            && self.is_chunk_updated(0)
+        {
+            debug!("The first chunk was updated, the file wrapped around");
+            return self.scan_all_chunks();
+        }
+
+        if updated_chunks.is_empty() && header_has_new_records {
+            debug!(
+                "File header claims records up to {}, but no chunk was updated",
+                next_record_id
+            );
+            return self.scan_all_chunks();
+        }
+
+        updated_chunks
+    }
+
+    /// Returns all the chunks which have new records, ordered by the first record they hold,
+    /// since after the file wraps around the oldest chunk is not necessarily the first one.
+    fn scan_all_chunks(&mut self) -> Vec<u64> {
+        let mut updated_chunks = vec![];
+
+        for chunk_number in 0..self.parser.calculated_chunk_count {
+            if let Some(first_record_id) = self.updated_chunk_first_record_id(chunk_number) {
+                updated_chunks.push((first_record_id, chunk_number));
+            }
+        }
+
+        updated_chunks.sort_unstable();
+        updated_chunks
+            .into_iter()
+            .map(|(_, chunk_number)| chunk_number)
+            .collect()
+    }
+
+    fn is_chunk_updated(&mut self, chunk_number: u64) -> bool {
+        self.updated_chunk_first_record_id(chunk_number).is_some()
+    }
+
+    /// Reads the header of a chunk, returning the first record it holds if it has new records.
+    /// Only the header of the chunk is read, the rest of the chunk is read only if it was updated.
+    fn updated_chunk_first_record_id(&mut self, chunk_number: u64) -> Option<RecordId> {
+        match EvtxParser::read_chunk_header(&mut self.parser.data, chunk_number) {
+            Ok(Some(header)) => {
+                let is_updated = match self.last_record_ids.get(&chunk_number) {
+                    Some(last_record_id) => header.last_event_record_id > *last_record_id,
+                    None => true,
+                };
+
+                is_updated.then_some(header.first_event_record_id)
+            }
+            Ok(None) => None,
+            // The chunk may still be in the process of being written.
+            Err(err) => {
+                debug!("Skipping chunk {}: {}", chunk_number, err);
+                None
+            }
+        }
+    }
+
+    /// Returns the records of a chunk which are newer than the last record yielded from it.
+    fn poll_chunk<U>(
+        &mut self,
+        chunk_number: u64,
+        f: &mut impl FnMut(Result<EvtxRecord<'_>>) -> Result<U>,
+    ) -> Vec<Result<U>> {
+        let settings = Arc::clone(&self.parser.config);
+
+        let mut chunk = match self
+            .parser
+            .allocate_chunk(chunk_number, settings.should_validate_checksums())
+        {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => return vec![],
+            Err(err) => return vec![Err(err)],
+        };
+
+        let mut chunk_records = match chunk.parse(Arc::clone(&settings)) {
+            Ok(chunk_records) => chunk_records,
+            Err(err) => {
+                return vec![Err(EvtxError::FailedToParseChunk {
+                    chunk_id: chunk_number,
+                    source: err,
+                })]
+            }
+        };
+
+        let last_record_id = self.last_record_ids.get(&chunk_number).copied();
+        let is_new = |record_id: RecordId| last_record_id < Some(record_id);
+        let mut newest_record_id = last_record_id;
+        let mut records = vec![];
+
+        for record in chunk_records.iter() {
+            if let Some(record_id) = record_id(&record) {
+                if !is_new(record_id) {
+                    continue;
+                }
+
+                newest_record_id = newest_record_id.max(Some(record_id));
+            }
+
+            if settings.should_serialize(&record) {
+                records.push(f(record));
+            }
+        }
+
+        if let Some(newest_record_id) = newest_record_id {
+            self.last_record_ids.insert(chunk_number, newest_record_id);
+        }
+
+        records
+    }
+
+    /// Returns the records which were added to the file since the previous poll.
+    /// Records will be XML-formatted.
+    pub fn poll(
+        &mut self,
+    ) -> Result<impl Iterator<Item = Result<SerializedEvtxRecord<String>>> + '_> {
+        self.poll_serialized(|record| record.and_then(|record| record.into_xml()))
+    }
+
+    /// Returns the records which were added to the file since the previous poll.
+    /// Records will be JSON-formatted.
+    pub fn poll_json(
+        &mut self,
+    ) -> Result<impl Iterator<Item = Result<SerializedEvtxRecord<String>>> + '_> {
+        self.poll_serialized(|record| record.and_then(|record| record.into_json()))
+    }
+
+    /// Consumes the follower, returning the underlying parser.
+    pub fn into_parser(self) -> EvtxParser<T> {
+        self.parser
+    }
+}
+
+/// The id of a record, or of a record which failed to parse.
+fn record_id(record: &Result<EvtxRecord<'_>>) -> Option<RecordId> {
+    match record {
+        Ok(record) => Some(record.event_record_id),
+        Err(EvtxError::FailedToParseRecord { record_id, .. }) => Some(*record_id),
+        Err(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ensure_env_logger_initialized;
+    use crate::evtx_parser::{EVTX_CHUNK_SIZE, EVTX_FILE_HEADER_SIZE};
+    use crate::{EvtxChunkData, ParserSettings};
+
+    fn chunk(evtx_file: &[u8], chunk_number: usize) -> &[u8] {
+        let start = EVTX_FILE_HEADER_SIZE + chunk_number * EVTX_CHUNK_SIZE;
+        &evtx_file[start..start + EVTX_CHUNK_SIZE]
+    }
+
+    fn ids(records: impl Iterator<Item = Result<SerializedEvtxRecord<String>>>) -> Vec<RecordId> {
+        records.map(|r| r.unwrap().event_record_id).collect()
+    }
+
+    #[test]
+    fn test_follows_appended_chunks_and_wrap_around() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+
+        let mut data = evtx_file[..EVTX_FILE_HEADER_SIZE].to_vec();
+        data.extend_from_slice(chunk(evtx_file, 0));
+
+        let mut follower = EvtxParser::from_buffer(data).unwrap().follow();
+        assert_eq!(ids(follower.poll().unwrap()), (1..=91).collect::<Vec<_>>());
+        assert!(follower.poll().unwrap().next().is_none());
+
+        // A new chunk is appended.
+        follower
+            .parser
+            .data
+            .get_mut()
+            .extend_from_slice(chunk(evtx_file, 1));
+
+        let records = ids(follower.poll().unwrap());
+        assert_eq!(records.first(), Some(&92));
+        assert!(records.iter().all(|id| *id > 91));
+
+        let last_id = *records.last().unwrap();
+
+        // The log wraps around, and the first chunk is overwritten with newer records.
+        follower.parser.data.get_mut()
+            [EVTX_FILE_HEADER_SIZE..EVTX_FILE_HEADER_SIZE + EVTX_CHUNK_SIZE]
+            .copy_from_slice(chunk(evtx_file, 2));
+
+        let records = ids(follower.poll().unwrap());
+        assert_eq!(records.first(), Some(&(last_id + 1)));
+    }
+
+    #[test]
+    fn test_polls_after_existing_records() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+
+        let mut data = evtx_file[..EVTX_FILE_HEADER_SIZE].to_vec();
+        data.extend_from_slice(chunk(evtx_file, 0));
+
+        let mut follower = EvtxParser::from_buffer(data)
+            .unwrap()
+            .with_configuration(ParserSettings::new().num_threads(4))
+            .follow();
+        assert_eq!(ids(follower.records()), (1..=91).collect::<Vec<_>>());
+        assert!(follower.poll().unwrap().next().is_none());
+
+        follower
+            .parser
+            .data
+            .get_mut()
+            .extend_from_slice(chunk(evtx_file, 1));
+
+        assert_eq!(ids(follower.poll().unwrap()).first(), Some(&92));
+    }
+
+    #[test]
+    fn test_follows_records_appended_to_chunk() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+
+        let full_chunk = chunk(evtx_file, 0).to_vec();
+        let record_51_offset = EvtxParser::from_buffer(evtx_file.to_vec())
+            .unwrap()
+            .chunks()
+            .next()
+            .unwrap()
+            .unwrap()
+            .parse(Arc::new(Default::default()))
+            .unwrap()
+            .iter()
+            .nth(50)
+            .unwrap()
+            .unwrap()
+            .record_offset;
+
+        // Only the first 50 records were written so far.
+        let mut partial_chunk = full_chunk.clone();
+        partial_chunk[32..40].copy_from_slice(&50_u64.to_le_bytes());
+        partial_chunk[48..52].copy_from_slice(&record_51_offset.to_le_bytes());
+
+        let mut data = evtx_file[..EVTX_FILE_HEADER_SIZE].to_vec();
+        data.extend_from_slice(&partial_chunk);
+
+        let mut follower = EvtxParser::from_buffer(data).unwrap().follow();
+        follower.skip_existing().unwrap();
+        assert!(follower.poll().unwrap().next().is_none());
+
+        let data: &mut Vec<u8> = follower.parser.data.get_mut();
+        data[EVTX_FILE_HEADER_SIZE..].copy_from_slice(&full_chunk);
+
+        assert_eq!(ids(follower.poll().unwrap()), (51..=91).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_polls_forward_from_newest_chunk() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+
+        // The file header says the first chunk is being written, and the next record is 92.
+        let mut data = evtx_file[..EVTX_FILE_HEADER_SIZE].to_vec();
+        data[16..24].copy_from_slice(&0_u64.to_le_bytes());
+        data[24..32].copy_from_slice(&92_u64.to_le_bytes());
+        data.extend_from_slice(chunk(evtx_file, 0));
+        data.resize(EVTX_FILE_HEADER_SIZE + 3 * EVTX_CHUNK_SIZE, 0);
+
+        let mut follower = EvtxParser::from_buffer(data).unwrap().follow();
+        assert_eq!(ids(follower.records()), (1..=91).collect::<Vec<_>>());
+
+        // Records are written to the third chunk, while the second chunk is still empty.
+        // The forward scan stops at the empty chunk, and the file header does not claim newer records.
+        let third_chunk = EVTX_FILE_HEADER_SIZE + 2 * EVTX_CHUNK_SIZE;
+        follower.parser.data.get_mut()[third_chunk..].copy_from_slice(chunk(evtx_file, 2));
+        assert!(follower.poll().unwrap().next().is_none());
+
+        // Once the file header claims newer records, all the chunk headers are read.
+        follower.parser.data.get_mut()[24..32].copy_from_slice(&u64::MAX.to_le_bytes());
+
+        let first_record_id = EvtxChunkData::new(chunk(evtx_file, 2).to_vec(), false)
+            .unwrap()
+            .header
+            .first_event_record_id;
+        assert_eq!(
+            ids(follower.poll().unwrap()).first(),
+            Some(&first_record_id)
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use crate::evtx_chunk::EvtxChunkData;
+use crate::evtx_record::RecordId;
+
+use chrono::{DateTime, Utc};
+use log::debug;
+use std::ops::RangeInclusive;
+
+/// Summary of a single chunk, built only from the chunk header and the record headers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkIndexEntry {
+    /// Number of the chunk in the file.
+    pub chunk_number: u64,
+    pub first_event_record_id: RecordId,
+    pub last_event_record_id: RecordId,
+    /// Earliest and latest timestamps of the records in the chunk.
+    /// `None` when some of the record headers in the chunk could not be read,
+    /// in which case the chunk may contain records from any time.
+    pub timestamps: Option<RangeInclusive<DateTime<Utc>>>,
+}
+
+impl ChunkIndexEntry {
+    pub(crate) fn from_chunk_data(chunk_number: u64, chunk: &EvtxChunkData) -> Self {
+        let mut timestamps: Option<RangeInclusive<DateTime<Utc>>> = None;
+
+        for (_, header) in chunk.record_headers() {
+            let header = match header {
+                Ok(header) => header,
+                Err(err) => {
+                    debug!(
+                        "Chunk {} - Failed to read record header ({}), timestamps are unknown",
+                        chunk_number, err
+                    );
+                    timestamps = None;
+                    break;
+                }
+            };
+
+            timestamps = Some(match timestamps {
+                None => header.timestamp..=header.timestamp,
+                Some(range) => {
+                    (*range.start()).min(header.timestamp)..=(*range.end()).max(header.timestamp)
+                }
+            });
+        }
+
+        ChunkIndexEntry {
+            chunk_number,
+            first_event_record_id: chunk.header.first_event_record_id,
+            last_event_record_id: chunk.header.last_event_record_id,
+            timestamps,
+        }
+    }
+
+    /// Returns true if the chunk may contain records written between `start` and `end` (inclusive).
+    pub fn overlaps(&self, start: &DateTime<Utc>, end: &DateTime<Utc>) -> bool {
+        match &self.timestamps {
+            Some(timestamps) => timestamps.start() <= end && start <= timestamps.end(),
+            None => true,
+        }
+    }
+}
+
+/// A lightweight index over the chunks of an evtx file.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ChunkIndex {
+    pub entries: Vec<ChunkIndexEntry>,
+    /// Numbers of the chunks which could not be read, these may contain records from any time.
+    pub unreadable_chunks: Vec<u64>,
+}
+
+impl ChunkIndex {
+    /// Returns the numbers of the chunks which may contain records written between `start`
+    /// and `end` (inclusive), including the chunks which could not be read.
+    pub fn chunks_between(&self, start: &DateTime<Utc>, end: &DateTime<Utc>) -> Vec<u64> {
+        let mut chunk_numbers: Vec<u64> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.overlaps(start, end))
+            .map(|entry| entry.chunk_number)
+            .chain(self.unreadable_chunks.iter().copied())
+            .collect();
+
+        chunk_numbers.sort_unstable();
+        chunk_numbers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ensure_env_logger_initialized;
+    use crate::evtx_parser::{EVTX_CHUNK_SIZE, EVTX_FILE_HEADER_SIZE};
+    use crate::ParserSettings;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_index_entry_matches_record_timestamps() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+        let chunk_data =
+            evtx_file[EVTX_FILE_HEADER_SIZE..EVTX_FILE_HEADER_SIZE + EVTX_CHUNK_SIZE].to_vec();
+
+        let mut chunk = EvtxChunkData::new(chunk_data, false).unwrap();
+        let entry = ChunkIndexEntry::from_chunk_data(0, &chunk);
+
+        let timestamps: Vec<_> = chunk
+            .parse(Arc::new(ParserSettings::new()))
+            .unwrap()
+            .iter()
+            .map(|r| r.unwrap().timestamp)
+            .collect();
+
+        let expected = *timestamps.iter().min().unwrap()..=*timestamps.iter().max().unwrap();
+
+        assert_eq!(entry.first_event_record_id, 1);
+        assert_eq!(entry.last_event_record_id, 91);
+        assert_eq!(entry.timestamps, Some(expected));
+    }
+}
@@ -0,0 +1,82 @@
+use crate::err::Result;
+use crate::EvtxParser;
+
+use memmap2::Mmap;
+use std::io::Cursor;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// A memory mapped EVTX file, shared between the parser and the chunks read from it.
+///
+/// Chunks read from a `MappedFile` borrow their data from the mapping instead of copying it,
+/// see `EvtxParser::from_mmap`.
+#[derive(Clone)]
+pub struct MappedFile(Arc<Mmap>);
+
+impl MappedFile {
+    /// Returns the given range of the file, or `None` if it extends past its end.
+    pub(crate) fn range(&self, range: Range<usize>) -> Option<MappedRange> {
+        if range.start > range.end || range.end > self.0.len() {
+            return None;
+        }
+
+        Some(MappedRange {
+            file: self.clone(),
+            range,
+        })
+    }
+}
+
+impl AsRef<[u8]> for MappedFile {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// A range of a `MappedFile`.
+#[derive(Clone)]
+pub(crate) struct MappedRange {
+    file: MappedFile,
+    range: Range<usize>,
+}
+
+impl AsRef<[u8]> for MappedRange {
+    fn as_ref(&self) -> &[u8] {
+        &self.file.as_ref()[self.range.clone()]
+    }
+}
+
+impl EvtxParser<Cursor<MappedFile>> {
+    /// Attempts to load an evtx file from a memory mapping, will fail if the evtx header is invalid.
+    ///
+    /// Chunks are borrowed directly from the mapping instead of being copied into a buffer,
+    /// which avoids an allocation and a copy for every chunk of large files.
+    ///
+    /// Mapping the file is left to the caller (it is `unsafe`, since the file must not be modified
+    /// while it is mapped):
+    ///
+    /// ```rust
+    /// # use evtx::{EvtxParser, Mmap};
+    /// # use std::fs::File;
+    /// # let fp = std::path::PathBuf::from(format!("{}/samples/security.evtx", std::env::var("CARGO_MANIFEST_DIR").unwrap()));
+    /// let file = File::open(fp).unwrap();
+    /// let mmap = unsafe { Mmap::map(&file).unwrap() };
+    ///
+    /// let mut parser = EvtxParser::from_mmap(mmap).unwrap();
+    ///
+    /// for record in parser.records() {
+    ///     match record {
+    ///         Ok(r) => println!("Record {}\n{}", r.event_record_id, r.data),
+    ///         Err(e) => eprintln!("{}", e),
+    ///     }
+    /// }
+    /// ```
+    pub fn from_mmap(mmap: Mmap) -> Result<Self> {
+        let file = MappedFile(Arc::new(mmap));
+
+        let mut parser = Self::from_read_seek(Cursor::new(file.clone()))?;
+        parser.mapped_file = Some(file);
+
+        Ok(parser)
+    }
+}
@@ -0,0 +1,177 @@
+use crate::err::Result;
+use crate::evtx_record::RecordId;
+use crate::EvtxRecord;
+
+use chrono::{DateTime, Utc};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+/// The order in which the parser returns records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RecordOrder {
+    /// The order in which the records are stored in the file (chunk after chunk).
+    /// Note that after the file wraps around, this is not the order in which they were written.
+    #[default]
+    File,
+    /// Sorted by `EventRecordID`.
+    RecordId,
+    /// Sorted by the timestamp of the record, records with the same timestamp are sorted
+    /// by `EventRecordID`.
+    Timestamp,
+}
+
+/// The key records are sorted by.
+/// The timestamp is only set when sorting by timestamp.
+pub(crate) type OrderKey = (Option<DateTime<Utc>>, RecordId);
+
+/// A serialized record along with its key (if records are sorted).
+pub(crate) type KeyedRecord<U> = (Option<OrderKey>, Result<U>);
+
+impl RecordOrder {
+    /// Returns the key of `record`, or `None` if records are not sorted.
+    pub(crate) fn key(&self, record: &EvtxRecord) -> Option<OrderKey> {
+        match self {
+            RecordOrder::File => None,
+            RecordOrder::RecordId => Some((None, record.event_record_id)),
+            RecordOrder::Timestamp => Some((Some(record.timestamp), record.event_record_id)),
+        }
+    }
+
+    /// The smallest key a chunk may contain, given its first record id and earliest timestamp
+    /// (`None` if the timestamps of the chunk are unknown).
+    pub(crate) fn chunk_lower_bound(
+        &self,
+        first_event_record_id: RecordId,
+        earliest_timestamp: Option<DateTime<Utc>>,
+    ) -> OrderKey {
+        match self {
+            RecordOrder::File | RecordOrder::RecordId => (None, first_event_record_id),
+            // A chunk with unknown timestamps must be parsed before any record can be released.
+            RecordOrder::Timestamp => (earliest_timestamp, 0),
+        }
+    }
+}
+
+struct BufferedRecord<U> {
+    key: OrderKey,
+    /// Insertion order, used to keep the output stable for records with the same key.
+    sequence: u64,
+    record: Result<U>,
+}
+
+impl<U> PartialEq for BufferedRecord<U> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<U> Eq for BufferedRecord<U> {}
+
+impl<U> PartialOrd for BufferedRecord<U> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<U> Ord for BufferedRecord<U> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (&self.key, self.sequence).cmp(&(&other.key, other.sequence))
+    }
+}
+
+/// Holds records until it is known that no smaller record is yet to come.
+///
+/// The buffer holds at most `capacity` records, once it is full the smallest record is released
+/// even if it is not known to be in order. Since records are pushed in a fixed order,
+/// the output is the same regardless of the number of threads used for parsing.
+pub(crate) struct ReorderBuffer<U> {
+    heap: BinaryHeap<Reverse<BufferedRecord<U>>>,
+    capacity: usize,
+    next_sequence: u64,
+}
+
+impl<U> ReorderBuffer<U> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        ReorderBuffer {
+            heap: BinaryHeap::new(),
+            capacity: capacity.max(1),
+            next_sequence: 0,
+        }
+    }
+
+    /// Adds a record to the buffer, returning a record if one has to be released.
+    /// Records without a key (errors) are released immediately.
+    pub(crate) fn push(&mut self, key: Option<OrderKey>, record: Result<U>) -> Option<Result<U>> {
+        let key = match key {
+            Some(key) => key,
+            None => return Some(record),
+        };
+
+        self.heap.push(Reverse(BufferedRecord {
+            key,
+            sequence: self.next_sequence,
+            record,
+        }));
+        self.next_sequence += 1;
+
+        if self.heap.len() > self.capacity {
+            self.heap.pop().map(|Reverse(buffered)| buffered.record)
+        } else {
+            None
+        }
+    }
+
+    /// Releases all the records whose key is smaller than `bound`, in order.
+    pub(crate) fn release_below(&mut self, bound: &OrderKey) -> Vec<Result<U>> {
+        let mut released = vec![];
+
+        while matches!(self.heap.peek(), Some(Reverse(buffered)) if buffered.key < *bound) {
+            if let Some(Reverse(buffered)) = self.heap.pop() {
+                released.push(buffered.record);
+            }
+        }
+
+        released
+    }
+
+    /// Releases all the records in the buffer, in order.
+    pub(crate) fn release_all(self) -> Vec<Result<U>> {
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .rev()
+            .map(|Reverse(buffered)| buffered.record)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(records: Vec<Result<RecordId>>) -> Vec<RecordId> {
+        records.into_iter().map(|r| r.unwrap()).collect()
+    }
+
+    #[test]
+    fn test_reorder_buffer_releases_in_order() {
+        let mut buffer = ReorderBuffer::new(10);
+
+        for id in [3, 1, 2, 6, 5] {
+            assert!(buffer.push(Some((None, id)), Ok(id)).is_none());
+        }
+
+        assert_eq!(ids(buffer.release_below(&(None, 4))), vec![1, 2, 3]);
+        assert_eq!(ids(buffer.release_all()), vec![5, 6]);
+    }
+
+    #[test]
+    fn test_full_reorder_buffer_releases_smallest_record() {
+        let mut buffer = ReorderBuffer::new(2);
+
+        assert!(buffer.push(Some((None, 3)), Ok(3)).is_none());
+        assert!(buffer.push(Some((None, 2)), Ok(2)).is_none());
+        assert_eq!(buffer.push(Some((None, 4)), Ok(4)).unwrap().unwrap(), 2);
+        assert_eq!(ids(buffer.release_all()), vec![3, 4]);
+    }
+}
@@ -1,8 +1,17 @@
 use crate::err::{ChunkError, EvtxError, InputError, Result};
 
-use crate::evtx_chunk::EvtxChunkData;
+use crate::evtx_checkpoint::Checkpoint;
+use crate::evtx_chunk::{
+    ChunkBytes, EvtxChunk, EvtxChunkData, EvtxChunkHeader, EVTX_CHUNK_HEADER_SIZE,
+};
 use crate::evtx_file_header::EvtxFileHeader;
-use crate::evtx_record::SerializedEvtxRecord;
+use crate::evtx_follow::EvtxFollower;
+use crate::evtx_index::{ChunkIndex, ChunkIndexEntry};
+#[cfg(feature = "mmap")]
+use crate::evtx_mmap::MappedFile;
+use crate::evtx_ordering::{KeyedRecord, OrderKey, RecordOrder, ReorderBuffer};
+use crate::evtx_record::{RecordId, SerializedEvtxRecord};
+use crate::json_output::JsonFormat;
 #[cfg(feature = "multithreading")]
 use rayon::prelude::*;
 
@@ -14,18 +23,26 @@ use log::{debug, info};
 use std::fs::File;
 use std::io::{self, Cursor, Read, Seek, SeekFrom};
 
-use crate::EvtxRecord;
+use crate::{checksum_ieee, EvtxRecord};
+use chrono::{DateTime, Utc};
 use encoding::all::WINDOWS_1252;
 use encoding::EncodingRef;
+use hashbrown::HashMap;
 use std::cmp::max;
 use std::fmt;
 use std::fmt::Debug;
 use std::iter::{IntoIterator, Iterator};
+use std::ops::RangeInclusive;
 use std::path::Path;
 use std::sync::Arc;
 
+/// A predicate deciding whether a record should be serialized, see `ParserSettings::record_filter`.
+pub type RecordFilter = Arc<dyn Fn(&EvtxRecord) -> bool + Send + Sync>;
+
 pub const EVTX_CHUNK_SIZE: usize = 65536;
 pub const EVTX_FILE_HEADER_SIZE: usize = 4096;
+/// Enough to hold a few hundred full chunks.
+const DEFAULT_REORDER_BUFFER_SIZE: usize = 100_000;
 
 // Stable shim until https://github.com/rust-lang/rust/issues/59359 is merged.
 // Taken from proposed std code.
@@ -90,13 +107,20 @@ impl<T: Read + Seek> ReadSeek for T {}
 /// ```
 ///
 pub struct EvtxParser<T: ReadSeek> {
-    data: T,
-    header: EvtxFileHeader,
-    config: Arc<ParserSettings>,
+    pub(crate) data: T,
+    pub(crate) header: EvtxFileHeader,
+    pub(crate) config: Arc<ParserSettings>,
     /// The calculated_chunk_count is the: (<file size> - <header size>) / <chunk size>
     /// This is needed because the chunk count of an EVTX file can be larger than the u16
     /// value stored in the file header.
-    calculated_chunk_count: u64,
+    pub(crate) calculated_chunk_count: u64,
+    /// Built on demand by `chunk_index`.
+    chunk_index: Option<ChunkIndex>,
+    /// When set, iteration starts right after the record of the checkpoint.
+    resume_from: Option<Checkpoint>,
+    /// Set by `from_mmap`, chunks are then borrowed from the mapping instead of being read from `data`.
+    #[cfg(feature = "mmap")]
+    pub(crate) mapped_file: Option<MappedFile>,
 }
 impl<T: ReadSeek> Debug for EvtxParser<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> ::std::fmt::Result {
@@ -132,10 +156,24 @@ pub struct ParserSettings {
     ///   }
     /// }
     separate_json_attributes: bool,
+    /// The shape of the JSON output.
+    json_format: JsonFormat,
     /// If true, output will be indented.
     indent: bool,
     /// Controls the ansi codec used to deserialize ansi strings inside the xml document.
     ansi_codec: EncodingRef,
+    /// If enabled, the parser will skip to the next valid record after encountering a corrupt
+    /// record, instead of skipping the rest of the chunk.
+    resync_after_corrupt_records: bool,
+    /// If enabled, the slack space of every chunk (after its `free_space_offset`) will be
+    /// scanned for discarded records.
+    recover_slack_records: bool,
+    /// Controls the order in which records are returned.
+    record_order: RecordOrder,
+    /// The maximal number of records held back while sorting records.
+    reorder_buffer_size: usize,
+    /// If set, only records accepted by the filter are serialized.
+    record_filter: Option<RecordFilter>,
 }
 
 impl Debug for ParserSettings {
@@ -144,8 +182,17 @@ impl Debug for ParserSettings {
             .field("num_threads", &self.num_threads)
             .field("validate_checksums", &self.validate_checksums)
             .field("separate_json_attributes", &self.separate_json_attributes)
+            .field("json_format", &self.json_format)
             .field("indent", &self.indent)
             .field("ansi_codec", &self.ansi_codec.name())
+            .field(
+                "resync_after_corrupt_records",
+                &self.resync_after_corrupt_records,
+            )
+            .field("recover_slack_records", &self.recover_slack_records)
+            .field("record_order", &self.record_order)
+            .field("reorder_buffer_size", &self.reorder_buffer_size)
+            .field("record_filter", &self.record_filter.is_some())
             .finish()
     }
 }
@@ -156,7 +203,19 @@ impl PartialEq for ParserSettings {
             && self.num_threads == other.num_threads
             && self.validate_checksums == other.validate_checksums
             && self.separate_json_attributes == other.separate_json_attributes
+            && self.json_format == other.json_format
             && self.indent == other.indent
+            && self.resync_after_corrupt_records == other.resync_after_corrupt_records
+            && self.recover_slack_records == other.recover_slack_records
+            && self.record_order == other.record_order
+            && self.reorder_buffer_size == other.reorder_buffer_size
+            && match (&self.record_filter, &other.record_filter) {
+                (None, None) => true,
+                (Some(filter), Some(other_filter)) => {
+                    Arc::as_ptr(filter) as *const () == Arc::as_ptr(other_filter) as *const ()
+                }
+                _ => false,
+            }
     }
 }
 
@@ -166,8 +225,14 @@ impl Default for ParserSettings {
             num_threads: 0,
             validate_checksums: false,
             separate_json_attributes: false,
+            json_format: JsonFormat::Nested,
             indent: true,
             ansi_codec: WINDOWS_1252,
+            resync_after_corrupt_records: false,
+            recover_slack_records: false,
+            record_order: RecordOrder::File,
+            reorder_buffer_size: DEFAULT_REORDER_BUFFER_SIZE,
+            record_filter: None,
         }
     }
 }
@@ -218,12 +283,87 @@ impl ParserSettings {
         self
     }
 
+    /// Sets the shape of the JSON output, see `JsonFormat`.
+    pub fn json_format(mut self, format: JsonFormat) -> Self {
+        self.json_format = format;
+
+        self
+    }
+
     pub fn indent(mut self, pretty: bool) -> Self {
         self.indent = pretty;
 
         self
     }
 
+    /// When set, a corrupt record will not end the iteration over its chunk.
+    /// Instead, the parser will scan for the next valid record, and report the skipped data
+    /// using `EvtxError::SkippedCorruptRecordData`.
+    pub fn resync_after_corrupt_records(mut self, resync: bool) -> Self {
+        self.resync_after_corrupt_records = resync;
+
+        self
+    }
+
+    /// When set, records which still exist in the slack space of a chunk will be recovered
+    /// after the regular records of the chunk.
+    /// These records will have `recovered_from_slack` set.
+    pub fn recover_slack_records(mut self, recover: bool) -> Self {
+        self.recover_slack_records = recover;
+
+        self
+    }
+
+    /// Sets the order in which records are returned (by every iterator over records of
+    /// `EvtxParser`, including `records_by_id_range` and `records_between`).
+    ///
+    /// When sorting, chunks are still parsed in parallel, but are read in the order of the
+    /// smallest record they may contain (according to their headers, or to `chunk_index` when
+    /// sorting by timestamp), and records are held back until no smaller record may follow.
+    /// Errors are returned as soon as they are encountered.
+    pub fn record_order(mut self, order: RecordOrder) -> Self {
+        self.record_order = order;
+
+        self
+    }
+
+    /// Sets the maximal number of records held back while sorting records.
+    /// Once the buffer is full, the smallest record is returned, even if it may be out of order.
+    /// The output does not depend on the number of threads either way.
+    pub fn reorder_buffer_size(mut self, size: usize) -> Self {
+        self.reorder_buffer_size = size;
+
+        self
+    }
+
+    /// Sets a filter which is called with every record before it is serialized.
+    /// Records for which the filter returns `false` are skipped without being serialized,
+    /// which is much cheaper than filtering the serialized records.
+    ///
+    /// The filter receives the record before serialization, so it can use the record id,
+    /// the timestamp, and the `System` fields of the record (see `EvtxRecord::system`).
+    /// Since the filter is called for every record, read the `System` fields once when
+    /// more than one of them is needed.
+    /// Errors are never filtered out.
+    ///
+    /// ```rust
+    /// # use evtx::ParserSettings;
+    /// // Only serialize successful logons of the Security channel.
+    /// let settings = ParserSettings::new().record_filter(|record| {
+    ///     record.system().is_ok_and(|system| {
+    ///         system.event_id == Some(4624) && system.channel.as_deref() == Some("Security")
+    ///     })
+    /// });
+    /// ```
+    pub fn record_filter(
+        mut self,
+        filter: impl Fn(&EvtxRecord) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.record_filter = Some(Arc::new(filter));
+
+        self
+    }
+
     /// Gets the current ansi codec
     pub fn get_ansi_codec(&self) -> EncodingRef {
         self.ansi_codec
@@ -233,6 +373,10 @@ impl ParserSettings {
         self.separate_json_attributes
     }
 
+    pub fn get_json_format(&self) -> &JsonFormat {
+        &self.json_format
+    }
+
     pub fn should_indent(&self) -> bool {
         self.indent
     }
@@ -241,9 +385,37 @@ impl ParserSettings {
         self.validate_checksums
     }
 
+    pub fn should_resync_after_corrupt_records(&self) -> bool {
+        self.resync_after_corrupt_records
+    }
+
+    pub fn should_recover_slack_records(&self) -> bool {
+        self.recover_slack_records
+    }
+
+    pub fn get_record_order(&self) -> RecordOrder {
+        self.record_order
+    }
+
+    pub fn get_reorder_buffer_size(&self) -> usize {
+        self.reorder_buffer_size
+    }
+
     pub fn get_num_threads(&self) -> &usize {
         &self.num_threads
     }
+
+    pub fn get_record_filter(&self) -> Option<&RecordFilter> {
+        self.record_filter.as_ref()
+    }
+
+    /// Returns true if `record` should be serialized (errors are always passed on).
+    pub(crate) fn should_serialize(&self, record: &Result<EvtxRecord>) -> bool {
+        match (record, &self.record_filter) {
+            (Ok(record), Some(filter)) => filter(record),
+            _ => true,
+        }
+    }
 }
 
 impl EvtxParser<File> {
@@ -273,12 +445,27 @@ impl EvtxParser<Cursor<Vec<u8>>> {
 impl<T: ReadSeek> EvtxParser<T> {
     pub fn from_read_seek(mut read_seek: T) -> Result<Self> {
         let evtx_header = EvtxFileHeader::from_stream(&mut read_seek)?;
+        let chunk_count = Self::calculate_chunk_count(&mut read_seek, &evtx_header)?;
+
+        debug!("EVTX Header: {:#?}", evtx_header);
+        Ok(EvtxParser {
+            data: read_seek,
+            header: evtx_header,
+            config: Arc::new(ParserSettings::default()),
+            calculated_chunk_count: chunk_count,
+            chunk_index: None,
+            resume_from: None,
+            #[cfg(feature = "mmap")]
+            mapped_file: None,
+        })
+    }
 
+    fn calculate_chunk_count(read_seek: &mut T, evtx_header: &EvtxFileHeader) -> Result<u64> {
         // Because an event log can be larger than u16 MAX * EVTX_CHUNK_SIZE,
         // We need to calculate the chunk count instead of using the header value
         // this allows us to continue parsing events past the 4294901760 bytes of
         // chunk data
-        let stream_size = ReadSeek::stream_len(&mut read_seek)?;
+        let stream_size = ReadSeek::stream_len(read_seek)?;
         let chunk_data_size: u64 =
             match stream_size.checked_sub(evtx_header.header_block_size.into()) {
                 Some(c) => c,
@@ -290,15 +477,25 @@ impl<T: ReadSeek> EvtxParser<T> {
                     )));
                 }
             };
-        let chunk_count = chunk_data_size / EVTX_CHUNK_SIZE as u64;
 
-        debug!("EVTX Header: {:#?}", evtx_header);
-        Ok(EvtxParser {
-            data: read_seek,
-            header: evtx_header,
-            config: Arc::new(ParserSettings::default()),
-            calculated_chunk_count: chunk_count,
-        })
+        Ok(chunk_data_size / EVTX_CHUNK_SIZE as u64)
+    }
+
+    /// Re-reads the file header and recalculates the chunk count, for files which are still
+    /// being written to.
+    pub(crate) fn reload_file_header(&mut self) -> Result<()> {
+        self.data.seek(SeekFrom::Start(0))?;
+        self.header = EvtxFileHeader::from_stream(&mut self.data)?;
+        self.calculated_chunk_count = Self::calculate_chunk_count(&mut self.data, &self.header)?;
+        self.chunk_index = None;
+
+        Ok(())
+    }
+
+    /// Consumes the parser, returning an `EvtxFollower` which yields records as they are
+    /// appended to the file.
+    pub fn follow(self) -> EvtxFollower<T> {
+        EvtxFollower::new(self)
     }
 
     pub fn with_configuration(mut self, configuration: ParserSettings) -> Self {
@@ -311,12 +508,11 @@ impl<T: ReadSeek> EvtxParser<T> {
     /// If the read chunk contains invalid data (bad magic, bad checksum when `validate_checksum` is set to true),
     /// of if not enough data can be read (e.g. because we reached EOF), an `Err` is returned.
     /// If the read chunk is empty, `Ok(None)` will be returned.
-    fn allocate_chunk(
-        data: &mut T,
+    pub(crate) fn allocate_chunk(
+        &mut self,
         chunk_number: u64,
         validate_checksum: bool,
     ) -> Result<Option<EvtxChunkData>> {
-        let mut chunk_data = Vec::with_capacity(EVTX_CHUNK_SIZE);
         let chunk_offset = EVTX_FILE_HEADER_SIZE + chunk_number as usize * EVTX_CHUNK_SIZE;
 
         trace!(
@@ -326,6 +522,35 @@ impl<T: ReadSeek> EvtxParser<T> {
             chunk_number
         );
 
+        #[cfg(feature = "mmap")]
+        let chunk_data: ChunkBytes = match &self.mapped_file {
+            Some(mapped_file) => mapped_file
+                .range(chunk_offset..chunk_offset + EVTX_CHUNK_SIZE)
+                .ok_or_else(|| EvtxError::incomplete_chunk(chunk_number))?
+                .into(),
+            None => Self::read_chunk_data(&mut self.data, chunk_number, chunk_offset)?.into(),
+        };
+        #[cfg(not(feature = "mmap"))]
+        let chunk_data: ChunkBytes =
+            Self::read_chunk_data(&mut self.data, chunk_number, chunk_offset)?.into();
+
+        // There might be empty chunks in the middle of a dirty file.
+        if chunk_data.iter().all(|x| *x == 0) {
+            return Ok(None);
+        }
+
+        EvtxChunkData::new(chunk_data, validate_checksum)
+            .map(Some)
+            .map_err(|e| EvtxError::FailedToParseChunk {
+                chunk_id: chunk_number,
+                source: e,
+            })
+    }
+
+    /// Copies the data of the chunk at `chunk_offset` into a new buffer.
+    fn read_chunk_data(data: &mut T, chunk_number: u64, chunk_offset: usize) -> Result<Vec<u8>> {
+        let mut chunk_data = Vec::with_capacity(EVTX_CHUNK_SIZE);
+
         data.seek(SeekFrom::Start(chunk_offset as u64))
             .map_err(|e| EvtxError::FailedToParseChunk {
                 chunk_id: chunk_number,
@@ -341,16 +566,42 @@ impl<T: ReadSeek> EvtxParser<T> {
             return Err(EvtxError::incomplete_chunk(chunk_number));
         }
 
-        // There might be empty chunks in the middle of a dirty file.
-        if chunk_data.iter().all(|x| *x == 0) {
+        Ok(chunk_data)
+    }
+
+    /// Reads only the header of the chunk at `chunk_number`, without reading the rest of the chunk.
+    /// If the header is empty, `Ok(None)` will be returned.
+    pub(crate) fn read_chunk_header(
+        data: &mut T,
+        chunk_number: u64,
+    ) -> Result<Option<EvtxChunkHeader>> {
+        let mut header_data = Vec::with_capacity(EVTX_CHUNK_HEADER_SIZE);
+        let chunk_offset = EVTX_FILE_HEADER_SIZE as u64 + chunk_number * EVTX_CHUNK_SIZE as u64;
+
+        data.seek(SeekFrom::Start(chunk_offset))
+            .map_err(|e| EvtxError::FailedToParseChunk {
+                chunk_id: chunk_number,
+                source: ChunkError::FailedToSeekToChunk(e),
+            })?;
+
+        let amount_read = data
+            .take(EVTX_CHUNK_HEADER_SIZE as u64)
+            .read_to_end(&mut header_data)
+            .map_err(|_| EvtxError::incomplete_chunk(chunk_number))?;
+
+        if amount_read != EVTX_CHUNK_HEADER_SIZE {
+            return Err(EvtxError::incomplete_chunk(chunk_number));
+        }
+
+        if header_data.iter().all(|x| *x == 0) {
             return Ok(None);
         }
 
-        EvtxChunkData::new(chunk_data, validate_checksum)
+        EvtxChunkHeader::from_reader(&mut Cursor::new(header_data.as_slice()))
             .map(Some)
             .map_err(|e| EvtxError::FailedToParseChunk {
                 chunk_id: chunk_number,
-                source: e,
+                source: ChunkError::FailedToParseChunkHeader(e),
             })
     }
 
@@ -362,11 +613,7 @@ impl<T: ReadSeek> EvtxParser<T> {
         mut chunk_number: u64,
     ) -> Option<(Result<EvtxChunkData>, u64)> {
         loop {
-            match EvtxParser::allocate_chunk(
-                &mut self.data,
-                chunk_number,
-                self.config.validate_checksums,
-            ) {
+            match self.allocate_chunk(chunk_number, self.config.validate_checksums) {
                 Err(err) => {
                     // We try to read past the `chunk_count` to allow for dirty files.
                     // But if we failed, it means we really are at the end of the file.
@@ -397,8 +644,8 @@ impl<T: ReadSeek> EvtxParser<T> {
     /// (before they are converted to XML or JSON).
     pub fn chunks(&mut self) -> IterChunks<T> {
         IterChunks {
+            current_chunk_number: self.first_chunk_number(),
             parser: self,
-            current_chunk_number: 0,
         }
     }
 
@@ -407,69 +654,508 @@ impl<T: ReadSeek> EvtxParser<T> {
     /// (before they are converted to XML or JSON).
     pub fn into_chunks(self) -> IntoIterChunks<T> {
         IntoIterChunks {
+            current_chunk_number: self.first_chunk_number(),
             parser: self,
-            current_chunk_number: 0,
         }
     }
+
+    /// The number of the chunk iteration starts from.
+    fn first_chunk_number(&self) -> u64 {
+        self.resume_from
+            .as_ref()
+            .map_or(0, |checkpoint| checkpoint.chunk_number)
+    }
+
+    /// A checksum of the header of the first record in the file, see `Checkpoint::file_identity`.
+    /// If the first record cannot be read, `0` is returned.
+    fn file_identity(&mut self) -> u32 {
+        let mut record_header = [0_u8; 24];
+        let record_offset = (EVTX_FILE_HEADER_SIZE + EVTX_CHUNK_HEADER_SIZE) as u64;
+
+        match self
+            .data
+            .seek(SeekFrom::Start(record_offset))
+            .and_then(|_| self.data.read_exact(&mut record_header))
+        {
+            Ok(_) => checksum_ieee(&record_header),
+            Err(_) => 0,
+        }
+    }
+
+    /// Makes the record iterators start right after the record at which `checkpoint` was taken.
+    /// Will fail if the checkpoint was taken from a different file.
+    pub fn resume_from(&mut self, checkpoint: &Checkpoint) -> Result<()> {
+        let file_identity = self.file_identity();
+
+        if checkpoint.file_identity != file_identity {
+            return Err(EvtxError::InvalidCheckpoint {
+                expected: checkpoint.file_identity,
+                found: file_identity,
+            });
+        }
+
+        self.resume_from = Some(checkpoint.clone());
+
+        Ok(())
+    }
+
     /// Return an iterator over all the records.
     /// Records will be mapped `f`, which must produce owned data from the records.
-    pub fn serialized_records<'a, U: Send>(
+    ///
+    /// Records rejected by `ParserSettings::record_filter` are skipped before `f` is called
+    /// (this also applies to the other iterators over records).
+    pub fn serialized_records<'a, U: Send + 'a>(
         &'a mut self,
-        f: impl FnMut(Result<EvtxRecord<'_>>) -> Result<U> + Send + Sync + Clone + 'a,
+        mut f: impl FnMut(Result<EvtxRecord<'_>>) -> Result<U> + Send + Sync + Clone + 'a,
     ) -> impl Iterator<Item = Result<U>> + '_ {
+        self.serialized_records_with_chunk_number(move |record, _| f(record))
+    }
+
+    /// Return an iterator over all the records, along with a `Checkpoint` for each of them.
+    /// Records will be mapped `f`, which must produce owned data from the records.
+    ///
+    /// Passing a checkpoint to `resume_from` allows parsing to continue after its record.
+    pub fn serialized_records_with_checkpoints<'a, U: Send + 'a>(
+        &'a mut self,
+        mut f: impl FnMut(Result<EvtxRecord<'_>>) -> Result<U> + Send + Sync + Clone + 'a,
+    ) -> impl Iterator<Item = Result<(U, Checkpoint)>> + 'a {
+        let file_identity = self.file_identity();
+
+        self.serialized_records_with_chunk_number(move |record, chunk_number| {
+            let record = record?;
+            let checkpoint = Checkpoint {
+                file_identity,
+                chunk_number,
+                record_offset: record.record_offset,
+                last_record_id: record.event_record_id,
+            };
+
+            f(Ok(record)).map(|serialized| (serialized, checkpoint))
+        })
+    }
+
+    /// Return an iterator over all the records, along with a `Checkpoint` for each of them.
+    /// Records will be XML-formatted.
+    pub fn records_with_checkpoints(
+        &mut self,
+    ) -> impl Iterator<Item = Result<(SerializedEvtxRecord<String>, Checkpoint)>> + '_ {
+        self.serialized_records_with_checkpoints(|record| {
+            record.and_then(|record| record.into_xml())
+        })
+    }
+
+    /// Return an iterator over all the records, along with a `Checkpoint` for each of them.
+    /// Records will be JSON-formatted.
+    pub fn records_json_with_checkpoints(
+        &mut self,
+    ) -> impl Iterator<Item = Result<(SerializedEvtxRecord<String>, Checkpoint)>> + '_ {
+        self.serialized_records_with_checkpoints(|record| {
+            record.and_then(|record| record.into_json())
+        })
+    }
+
+    /// Returns `chunk_numbers` along with the smallest key of a record each of these chunks
+    /// may contain, sorted by that key.
+    fn chunks_in_order(
+        &mut self,
+        order: RecordOrder,
+        chunk_numbers: impl IntoIterator<Item = u64>,
+    ) -> Vec<(OrderKey, u64)> {
+        let earliest_timestamps: HashMap<u64, DateTime<Utc>> = match order {
+            RecordOrder::Timestamp => self
+                .chunk_index()
+                .entries
+                .iter()
+                .filter_map(|entry| {
+                    let timestamps = entry.timestamps.as_ref()?;
+                    Some((entry.chunk_number, *timestamps.start()))
+                })
+                .collect(),
+            _ => HashMap::new(),
+        };
+
+        let mut chunks = vec![];
+
+        for chunk_number in chunk_numbers {
+            let lower_bound = match EvtxParser::read_chunk_header(&mut self.data, chunk_number) {
+                Ok(None) => continue,
+                Ok(Some(header)) => order.chunk_lower_bound(
+                    header.first_event_record_id,
+                    earliest_timestamps.get(&chunk_number).copied(),
+                ),
+                // The error will be reported when the chunk is read.
+                Err(_) => order.chunk_lower_bound(0, None),
+            };
+
+            chunks.push((lower_bound, chunk_number));
+        }
+
+        chunks.sort_unstable();
+        chunks
+    }
+
+    /// Like `serialized_records`, but `f` also receives the number of the chunk of each record.
+    pub(crate) fn serialized_records_with_chunk_number<'a, U: Send + 'a>(
+        &'a mut self,
+        f: impl FnMut(Result<EvtxRecord<'_>>, u64) -> Result<U> + Send + Sync + Clone + 'a,
+    ) -> impl Iterator<Item = Result<U>> + 'a {
+        let resume_from = self.resume_from.clone();
+        let order = self.config.get_record_order();
+
+        self.serialized_records_in_chunks(None, order, resume_from, |_| true, f)
+    }
+
+    /// Serializes the records accepted by `keep` in the chunks `chunk_numbers` (in every chunk
+    /// starting at `first_chunk_number` when `None`), in `order`.
+    /// The chunks are parsed in batches, see `parse_chunks_in_batches`.
+    ///
+    /// The records of the chunk of `resume_from` which precede its checkpoint are skipped.
+    fn serialized_records_in_chunks<'a, U: Send + 'a>(
+        &'a mut self,
+        chunk_numbers: Option<Vec<u64>>,
+        order: RecordOrder,
+        resume_from: Option<Checkpoint>,
+        keep: impl Fn(&Result<EvtxRecord<'_>>) -> bool + Send + Sync + 'a,
+        f: impl FnMut(Result<EvtxRecord<'_>>, u64) -> Result<U> + Send + Sync + Clone + 'a,
+    ) -> impl Iterator<Item = Result<U>> + 'a {
         // Retrieve parser settings here, while `self` is immutably borrowed.
         let num_threads = max(self.config.num_threads, 1);
         let chunk_settings = Arc::clone(&self.config);
 
+        // When sorting, chunks are read in the order of the smallest record they may contain.
+        // After the records of a chunk are buffered, the records smaller than anything the next chunk
+        // may contain are released.
+        let (mut chunk_numbers, next_lower_bounds, mut buffer) = match (order, chunk_numbers) {
+            (RecordOrder::File, chunk_numbers) => {
+                (chunk_numbers.map(Vec::into_iter), HashMap::new(), None)
+            }
+            (order, chunk_numbers) => {
+                let chunk_numbers = chunk_numbers.unwrap_or_else(|| {
+                    (self.first_chunk_number()..self.calculated_chunk_count).collect()
+                });
+                let chunks_in_order = self.chunks_in_order(order, chunk_numbers);
+                let next_lower_bounds: HashMap<u64, OrderKey> = chunks_in_order
+                    .windows(2)
+                    .map(|pair| (pair[0].1, pair[1].0))
+                    .collect();
+
+                (
+                    Some(
+                        chunks_in_order
+                            .into_iter()
+                            .map(|(_, chunk_number)| chunk_number)
+                            .collect::<Vec<_>>()
+                            .into_iter(),
+                    ),
+                    next_lower_bounds,
+                    Some(ReorderBuffer::new(self.config.get_reorder_buffer_size())),
+                )
+            }
+        };
+
         // `self` is mutably borrowed from here on.
         let mut chunks = self.chunks();
 
-        let records_per_chunk = std::iter::from_fn(move || {
-            // Allocate some chunks in advance, so they can be parsed in parallel.
-            let mut chunk_of_chunks = Vec::with_capacity(num_threads);
+        let validate_checksums = chunk_settings.should_validate_checksums();
+        let next_chunks = std::iter::from_fn(move || match &mut chunk_numbers {
+            None => chunks.next_with_chunk_number(),
+            Some(chunk_numbers) => chunk_numbers.find_map(|chunk_number| {
+                match chunks
+                    .parser
+                    .allocate_chunk(chunk_number, validate_checksums)
+                {
+                    Ok(None) => None,
+                    Ok(Some(chunk)) => Some((chunk_number, Ok(chunk))),
+                    Err(err) => Some((chunk_number, Err(err))),
+                }
+            }),
+        });
 
-            for _ in 0..num_threads {
-                if let Some(chunk) = chunks.next() {
-                    chunk_of_chunks.push(chunk);
+        // Serialize the records in each chunk, along with their sort keys.
+        let mut batches = parse_chunks_in_batches(
+            next_chunks,
+            num_threads,
+            Arc::clone(&chunk_settings),
+            |err| (None, Err(err)),
+            move |chunk_number, mut chunk_records| {
+                let mut f = f.clone();
+                let mut serialize = |record: Result<EvtxRecord<'_>>| -> KeyedRecord<U> {
+                    let key = record.as_ref().ok().and_then(|r| order.key(r));
+                    (key, f(record, chunk_number))
                 };
-            }
+                match &resume_from {
+                    // Skip the records which were processed before the checkpoint was taken.
+                    Some(checkpoint) if checkpoint.chunk_number == chunk_number => chunk_records
+                        .iter_from_offset(u64::from(checkpoint.record_offset))
+                        .filter(|record| {
+                            !matches!(record, Ok(record)
+                                if record.record_offset == checkpoint.record_offset
+                                && record.event_record_id == checkpoint.last_record_id)
+                        })
+                        .filter(|record| keep(record) && chunk_settings.should_serialize(record))
+                        .map(&mut serialize)
+                        .collect(),
+                    _ => chunk_records
+                        .iter()
+                        .filter(|record| keep(record) && chunk_settings.should_serialize(record))
+                        .map(&mut serialize)
+                        .collect(),
+                }
+            },
+        );
 
-            // We only stop once no chunks can be allocated.
-            if chunk_of_chunks.is_empty() {
-                None
-            } else {
-                #[cfg(feature = "multithreading")]
-                let chunk_iter = chunk_of_chunks.into_par_iter();
-
-                #[cfg(not(feature = "multithreading"))]
-                let chunk_iter = chunk_of_chunks.into_iter();
-
-                // Serialize the records in each chunk.
-                let iterators: Vec<Vec<Result<U>>> = chunk_iter
-                    .enumerate()
-                    .map(|(i, chunk_res)| match chunk_res {
-                        Err(err) => vec![Err(err)],
-                        Ok(mut chunk) => {
-                            let chunk_records_res = chunk.parse(chunk_settings.clone());
-
-                            match chunk_records_res {
-                                Err(err) => vec![Err(EvtxError::FailedToParseChunk {
-                                    chunk_id: i as u64,
-                                    source: err,
-                                })],
-                                Ok(mut chunk_records) => {
-                                    chunk_records.iter().map(f.clone()).collect()
-                                }
-                            }
-                        }
-                    })
-                    .collect();
+        let records_per_batch = std::iter::from_fn(move || {
+            // We only stop once no chunks are left (and all the buffered records were released).
+            let batch = match batches.next() {
+                Some(batch) => batch,
+                None => return buffer.take().map(|buffer| buffer.release_all()),
+            };
 
-                Some(iterators.into_iter().flatten())
+            let buffer = match &mut buffer {
+                None => {
+                    return Some(
+                        batch
+                            .into_iter()
+                            .flat_map(|(_, records)| records)
+                            .map(|(_, record)| record)
+                            .collect(),
+                    );
+                }
+                Some(buffer) => buffer,
+            };
+
+            // Buffer the records one chunk at a time, so the output does not depend on `num_threads`.
+            let mut released = vec![];
+            for (chunk_number, records) in batch {
+                for (key, record) in records {
+                    released.extend(buffer.push(key, record));
+                }
+
+                if let Some(lower_bound) = next_lower_bounds.get(&chunk_number) {
+                    released.extend(buffer.release_below(lower_bound));
+                }
             }
+
+            Some(released)
         });
 
-        records_per_chunk.flatten()
+        records_per_batch.flatten()
+    }
+
+    /// Return an iterator over the records whose `EventRecordID` is in `range`.
+    /// Records will be mapped `f`, which must produce owned data from the records.
+    ///
+    /// Only chunks whose header claims to contain records in `range` are read and parsed,
+    /// (unless slack space recovery is enabled, since records in slack space are not accounted for
+    /// in the chunk header).
+    /// Errors which cannot be attributed to a specific record are returned as well,
+    /// since they may hide a record in `range`.
+    ///
+    /// Like `serialized_records`, the selected chunks are parsed in parallel.
+    pub fn serialized_records_by_id_range<'a, U: Send + 'a>(
+        &'a mut self,
+        range: RangeInclusive<RecordId>,
+        mut f: impl FnMut(Result<EvtxRecord<'_>>) -> Result<U> + Send + Sync + Clone + 'a,
+    ) -> impl Iterator<Item = Result<U>> + 'a {
+        let recover_slack_records = self.config.should_recover_slack_records();
+        let mut chunk_numbers = vec![];
+
+        for chunk_number in 0..u64::MAX {
+            match EvtxParser::read_chunk_header(&mut self.data, chunk_number) {
+                // Like `find_next_chunk`, we try to read past `chunk_count` to allow for dirty files.
+                Err(_) if chunk_number >= self.calculated_chunk_count => break,
+                Ok(None) => {}
+                Ok(Some(header))
+                    if !recover_slack_records
+                        && (header.last_event_record_id < *range.start()
+                            || header.first_event_record_id > *range.end()) => {}
+                // Chunks with an invalid header are allocated anyway, so that the error is reported.
+                _ => chunk_numbers.push(chunk_number),
+            }
+        }
+
+        let keep = move |record: &Result<EvtxRecord<'_>>| match record {
+            Ok(record) => range.contains(&record.event_record_id),
+            Err(EvtxError::FailedToParseRecord { record_id, .. }) => range.contains(record_id),
+            Err(_) => true,
+        };
+
+        let order = self.config.get_record_order();
+
+        self.serialized_records_in_chunks(
+            Some(chunk_numbers),
+            order,
+            None,
+            keep,
+            move |record, _| f(record),
+        )
+    }
+
+    /// Returns an index of the record ids and timestamps in every chunk.
+    /// The index is built on the first call (which reads every chunk, but only parses the record
+    /// headers), and is reused afterwards.
+    ///
+    /// Chunks which could not be read are listed in `unreadable_chunks`.
+    pub fn chunk_index(&mut self) -> &ChunkIndex {
+        if self.chunk_index.is_none() {
+            let mut index = ChunkIndex::default();
+            let mut chunk_number = 0;
+
+            while let Some((chunk, current_chunk_number)) = self.find_next_chunk(chunk_number) {
+                match chunk {
+                    Ok(chunk) => index.entries.push(ChunkIndexEntry::from_chunk_data(
+                        current_chunk_number,
+                        &chunk,
+                    )),
+                    Err(err) => {
+                        debug!(
+                            "Chunk {} will not be indexed: {}",
+                            current_chunk_number, err
+                        );
+                        index.unreadable_chunks.push(current_chunk_number);
+                    }
+                }
+
+                chunk_number = match current_chunk_number.checked_add(1) {
+                    None => break,
+                    Some(n) => n,
+                };
+            }
+
+            self.chunk_index = Some(index);
+        }
+
+        self.chunk_index.as_ref().expect("index was built")
+    }
+
+    /// Return an iterator over the records written between `start` and `end` (inclusive).
+    /// Records will be mapped `f`, which must produce owned data from the records.
+    ///
+    /// Only chunks which overlap the time window according to `chunk_index` are parsed.
+    /// Errors which cannot be attributed to a specific record (such as chunks which could not be
+    /// read or parsed) are returned as well.
+    ///
+    /// Like `serialized_records`, the selected chunks are parsed in parallel.
+    pub fn serialized_records_between<'a, U: Send + 'a>(
+        &'a mut self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        mut f: impl FnMut(Result<EvtxRecord<'_>>) -> Result<U> + Send + Sync + Clone + 'a,
+    ) -> impl Iterator<Item = Result<U>> + 'a {
+        let order = self.config.get_record_order();
+
+        // Records in slack space are not accounted for in the index.
+        let chunk_numbers = if self.config.should_recover_slack_records() {
+            let index = self.chunk_index();
+            let mut chunk_numbers: Vec<u64> = index
+                .entries
+                .iter()
+                .map(|entry| entry.chunk_number)
+                .chain(index.unreadable_chunks.iter().copied())
+                .collect();
+            chunk_numbers.sort_unstable();
+            chunk_numbers
+        } else {
+            self.chunk_index().chunks_between(&start, &end)
+        };
+
+        let keep = move |record: &Result<EvtxRecord<'_>>| match record {
+            Ok(record) => start <= record.timestamp && record.timestamp <= end,
+            Err(_) => true,
+        };
+
+        self.serialized_records_in_chunks(
+            Some(chunk_numbers),
+            order,
+            None,
+            keep,
+            move |record, _| f(record),
+        )
+    }
+
+    /// Return an iterator over the records written between `start` and `end` (inclusive).
+    /// Records will be XML-formatted.
+    pub fn records_between(
+        &mut self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> impl Iterator<Item = Result<SerializedEvtxRecord<String>>> + '_ {
+        self.serialized_records_between(start, end, |record| {
+            record.and_then(|record| record.into_xml())
+        })
+    }
+
+    /// Return an iterator over the records written between `start` and `end` (inclusive).
+    /// Records will be JSON-formatted.
+    pub fn records_json_between(
+        &mut self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> impl Iterator<Item = Result<SerializedEvtxRecord<String>>> + '_ {
+        self.serialized_records_between(start, end, |record| {
+            record.and_then(|record| record.into_json())
+        })
+    }
+
+    /// Return the record with the given `EventRecordID`, mapped with `f`.
+    /// See `serialized_records_by_id_range` for the details of the lookup.
+    ///
+    /// If the record was not found, but errors were encountered while looking for it,
+    /// the first error is returned.
+    pub fn serialized_record_by_id<U: Send>(
+        &mut self,
+        id: RecordId,
+        f: impl FnMut(Result<EvtxRecord<'_>>) -> Result<U> + Send + Sync + Clone,
+    ) -> Option<Result<U>> {
+        let mut first_error = None;
+
+        for record in self.serialized_records_by_id_range(id..=id, f) {
+            match record {
+                Ok(record) => return Some(Ok(record)),
+                Err(err) => {
+                    first_error.get_or_insert(err);
+                }
+            }
+        }
+
+        first_error.map(Err)
+    }
+
+    /// Return the record with the given `EventRecordID`.
+    /// Record will be XML-formatted.
+    pub fn record_by_id(&mut self, id: RecordId) -> Option<Result<SerializedEvtxRecord<String>>> {
+        self.serialized_record_by_id(id, |record| record.and_then(|record| record.into_xml()))
+    }
+
+    /// Return the record with the given `EventRecordID`.
+    /// Record will be JSON-formatted.
+    pub fn record_json_by_id(
+        &mut self,
+        id: RecordId,
+    ) -> Option<Result<SerializedEvtxRecord<String>>> {
+        self.serialized_record_by_id(id, |record| record.and_then(|record| record.into_json()))
+    }
+
+    /// Return an iterator over the records whose `EventRecordID` is in `range`.
+    /// Records will be XML-formatted.
+    pub fn records_by_id_range(
+        &mut self,
+        range: RangeInclusive<RecordId>,
+    ) -> impl Iterator<Item = Result<SerializedEvtxRecord<String>>> + '_ {
+        self.serialized_records_by_id_range(range, |record| {
+            record.and_then(|record| record.into_xml())
+        })
+    }
+
+    /// Return an iterator over the records whose `EventRecordID` is in `range`.
+    /// Records will be JSON-formatted.
+    pub fn records_json_by_id_range(
+        &mut self,
+        range: RangeInclusive<RecordId>,
+    ) -> impl Iterator<Item = Result<SerializedEvtxRecord<String>>> + '_ {
+        self.serialized_records_by_id_range(range, |record| {
+            record.and_then(|record| record.into_json())
+        })
     }
 
     /// Return an iterator over all the records.
@@ -501,9 +1187,9 @@ pub struct IterChunks<'c, T: ReadSeek> {
     current_chunk_number: u64,
 }
 
-impl<'c, T: ReadSeek> Iterator for IterChunks<'c, T> {
-    type Item = Result<EvtxChunkData>;
-    fn next(&mut self) -> Option<<Self as Iterator>::Item> {
+impl<'c, T: ReadSeek> IterChunks<'c, T> {
+    /// Like `next`, but also returns the number of the chunk.
+    fn next_with_chunk_number(&mut self) -> Option<(u64, Result<EvtxChunkData>)> {
         match self.parser.find_next_chunk(self.current_chunk_number) {
             None => None,
             Some((chunk, chunk_number)) => {
@@ -512,12 +1198,67 @@ impl<'c, T: ReadSeek> Iterator for IterChunks<'c, T> {
                     Some(n) => n,
                 };
 
-                Some(chunk)
+                Some((chunk_number, chunk))
             }
         }
     }
 }
 
+/// Parses the chunks yielded by `chunks` in batches of `num_threads` chunks (in parallel when the
+/// `multithreading` feature is enabled), yielding the results of every chunk of a batch in order.
+///
+/// The records of a chunk are mapped by `chunk_records`, which receives the number of the chunk,
+/// and chunks which could not be read or parsed produce a single error, mapped by `on_error`.
+pub(crate) fn parse_chunks_in_batches<T: Send>(
+    mut chunks: impl Iterator<Item = (u64, Result<EvtxChunkData>)>,
+    num_threads: usize,
+    settings: Arc<ParserSettings>,
+    on_error: impl Fn(EvtxError) -> T + Send + Sync,
+    chunk_records: impl Fn(u64, EvtxChunk<'_>) -> Vec<T> + Send + Sync,
+) -> impl Iterator<Item = Vec<(u64, Vec<T>)>> {
+    std::iter::from_fn(move || {
+        // Allocate some chunks in advance, so they can be parsed in parallel.
+        let chunk_of_chunks: Vec<(u64, Result<EvtxChunkData>)> =
+            chunks.by_ref().take(num_threads).collect();
+
+        if chunk_of_chunks.is_empty() {
+            return None;
+        }
+
+        #[cfg(feature = "multithreading")]
+        let chunk_iter = chunk_of_chunks.into_par_iter();
+
+        #[cfg(not(feature = "multithreading"))]
+        let chunk_iter = chunk_of_chunks.into_iter();
+
+        let batch = chunk_iter
+            .map(|(chunk_number, chunk_res)| {
+                let records = match chunk_res {
+                    Err(err) => vec![on_error(err)],
+                    Ok(mut chunk) => match chunk.parse(Arc::clone(&settings)) {
+                        Err(err) => vec![on_error(EvtxError::FailedToParseChunk {
+                            chunk_id: chunk_number,
+                            source: err,
+                        })],
+                        Ok(records) => chunk_records(chunk_number, records),
+                    },
+                };
+
+                (chunk_number, records)
+            })
+            .collect();
+
+        Some(batch)
+    })
+}
+
+impl<'c, T: ReadSeek> Iterator for IterChunks<'c, T> {
+    type Item = Result<EvtxChunkData>;
+    fn next(&mut self) -> Option<<Self as Iterator>::Item> {
+        self.next_with_chunk_number().map(|(_, chunk)| chunk)
+    }
+}
+
 pub struct IntoIterChunks<T: ReadSeek> {
     parser: EvtxParser<T>,
     current_chunk_number: u64,
@@ -548,6 +1289,7 @@ mod tests {
     use super::*;
     use crate::ensure_env_logger_initialized;
     use anyhow::anyhow;
+    use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
 
     fn process_90_records(buffer: &'static [u8]) -> anyhow::Result<()> {
         let mut parser = EvtxParser::from_buffer(buffer.to_vec())?;
@@ -722,4 +1464,261 @@ mod tests {
             assert!(record.data.as_object().unwrap().contains_key("Event"));
         }
     }
+
+    #[test]
+    fn test_record_by_id() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+        let mut parser = EvtxParser::from_buffer(evtx_file.to_vec()).unwrap();
+
+        // Record 1500 is in the middle of the file, well past the first chunk.
+        let expected = parser
+            .records()
+            .map(|r| r.unwrap())
+            .find(|r| r.event_record_id == 1500)
+            .unwrap();
+
+        let record = parser.record_by_id(1500).unwrap().unwrap();
+        assert_eq!(record, expected);
+
+        assert!(parser.record_by_id(u64::MAX).is_none());
+    }
+
+    #[test]
+    fn test_records_between() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+        let mut parser = EvtxParser::from_buffer(evtx_file.to_vec()).unwrap();
+
+        let all: Vec<_> = parser.records().map(|r| r.unwrap()).collect();
+        let start = all[1000].timestamp;
+        let end = all[1100].timestamp;
+
+        let expected: Vec<_> = all
+            .iter()
+            .filter(|r| start <= r.timestamp && r.timestamp <= end)
+            .map(|r| r.event_record_id)
+            .collect();
+
+        let index = parser.chunk_index();
+        assert!(index.chunks_between(&start, &end).len() < index.entries.len());
+
+        let ids: Vec<_> = parser
+            .records_between(start, end)
+            .map(|r| r.unwrap().event_record_id)
+            .collect();
+
+        assert!(!ids.is_empty());
+        assert_eq!(ids, expected);
+    }
+
+    #[test]
+    fn test_records_between_returns_unreadable_chunks_as_errors() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+        let mut corrupted = evtx_file.to_vec();
+        let second_chunk = EVTX_FILE_HEADER_SIZE + EVTX_CHUNK_SIZE;
+        corrupted[second_chunk..second_chunk + 8].copy_from_slice(b"NotChunk");
+
+        let mut parser = EvtxParser::from_buffer(corrupted).unwrap();
+        assert_eq!(parser.chunk_index().unreadable_chunks, vec![1]);
+
+        let start = DateTime::<Utc>::MIN_UTC;
+        let end = DateTime::<Utc>::MAX_UTC;
+        let errors: Vec<_> = parser
+            .records_between(start, end)
+            .filter_map(|r| r.err())
+            .collect();
+
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0],
+            EvtxError::FailedToParseChunk { chunk_id: 1, .. }
+        ));
+    }
+
+    #[test]
+    fn test_records_between_sorted_after_wrap_around() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+
+        // Simulate a log which wrapped around, by moving the first chunk to the end of the file.
+        let chunk_data = &evtx_file[EVTX_FILE_HEADER_SIZE..];
+        let mut wrapped = evtx_file[..EVTX_FILE_HEADER_SIZE].to_vec();
+        wrapped.extend_from_slice(&chunk_data[EVTX_CHUNK_SIZE..]);
+        wrapped.extend_from_slice(&chunk_data[..EVTX_CHUNK_SIZE]);
+
+        let settings = ParserSettings::new().record_order(RecordOrder::Timestamp);
+        let mut parser = EvtxParser::from_buffer(wrapped)
+            .unwrap()
+            .with_configuration(settings);
+
+        let timestamps: Vec<_> = parser
+            .records_between(DateTime::<Utc>::MIN_UTC, DateTime::<Utc>::MAX_UTC)
+            .map(|r| r.unwrap().timestamp)
+            .collect();
+        let mut sorted = timestamps.clone();
+        sorted.sort_unstable();
+
+        assert!(!timestamps.is_empty());
+        assert_eq!(timestamps, sorted);
+    }
+
+    #[test]
+    fn test_records_by_id_range() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+        let mut parser = EvtxParser::from_buffer(evtx_file.to_vec()).unwrap();
+
+        // This range crosses a chunk boundary.
+        let ids: Vec<_> = parser
+            .records_json_by_id_range(80..=100)
+            .map(|r| r.unwrap().event_record_id)
+            .collect();
+
+        assert_eq!(ids, (80..=100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_record_filter_skips_serialization() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+
+        let expected: Vec<_> = EvtxParser::from_buffer(evtx_file.to_vec())
+            .unwrap()
+            .records_json_value()
+            .map(|r| r.unwrap())
+            .filter(|r| r.data["Event"]["System"]["EventID"] == 4624)
+            .map(|r| r.event_record_id)
+            .collect();
+        assert!(!expected.is_empty());
+
+        let settings = ParserSettings::new()
+            .num_threads(4)
+            .record_filter(|record| matches!(record.event_id(), Ok(Some(4624))));
+        let mut parser = EvtxParser::from_buffer(evtx_file.to_vec())
+            .unwrap()
+            .with_configuration(settings);
+
+        let serialized = AtomicUsize::new(0);
+        let ids: Vec<_> = parser
+            .serialized_records(|record| {
+                serialized.fetch_add(1, AtomicOrdering::SeqCst);
+                record.and_then(|record| record.into_json())
+            })
+            .map(|r| r.unwrap().event_record_id)
+            .collect();
+
+        assert_eq!(ids, expected);
+        assert_eq!(serialized.load(AtomicOrdering::SeqCst), expected.len());
+    }
+
+    #[test]
+    fn test_records_sorted_by_id_after_wrap_around() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+
+        // Simulate a log which wrapped around, by moving the first chunk to the end of the file.
+        let chunk_data = &evtx_file[EVTX_FILE_HEADER_SIZE..];
+        let mut wrapped = evtx_file[..EVTX_FILE_HEADER_SIZE].to_vec();
+        wrapped.extend_from_slice(&chunk_data[EVTX_CHUNK_SIZE..]);
+        wrapped.extend_from_slice(&chunk_data[..EVTX_CHUNK_SIZE]);
+
+        let ids_with_threads = |num_threads: usize| -> Vec<u64> {
+            let settings = ParserSettings::new()
+                .num_threads(num_threads)
+                .record_order(RecordOrder::RecordId)
+                .reorder_buffer_size(500);
+
+            EvtxParser::from_buffer(wrapped.clone())
+                .unwrap()
+                .with_configuration(settings)
+                .records_json()
+                .map(|r| r.unwrap().event_record_id)
+                .collect()
+        };
+
+        let ids = ids_with_threads(4);
+        let mut sorted = ids.clone();
+        sorted.sort_unstable();
+
+        assert_eq!(ids.first(), Some(&1));
+        assert_eq!(ids, sorted);
+        assert_eq!(ids, ids_with_threads(1));
+    }
+
+    #[test]
+    fn test_records_sorted_by_timestamp() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+
+        let settings = ParserSettings::new().record_order(RecordOrder::Timestamp);
+        let records: Vec<_> = EvtxParser::from_buffer(evtx_file.to_vec())
+            .unwrap()
+            .with_configuration(settings)
+            .records()
+            .map(|r| r.unwrap())
+            .collect();
+
+        assert_eq!(
+            records.len(),
+            EvtxParser::from_buffer(evtx_file.to_vec())
+                .unwrap()
+                .records()
+                .count()
+        );
+        assert!(records.windows(2).all(|pair| {
+            (pair[0].timestamp, pair[0].event_record_id)
+                < (pair[1].timestamp, pair[1].event_record_id)
+        }));
+    }
+
+    #[test]
+    fn test_resume_from_checkpoint() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+        let mut parser = EvtxParser::from_buffer(evtx_file.to_vec()).unwrap();
+
+        let (record, checkpoint) = parser
+            .records_json_with_checkpoints()
+            .map(|r| r.unwrap())
+            .find(|(record, _)| record.event_record_id == 1000)
+            .unwrap();
+        assert_eq!(record.event_record_id, checkpoint.last_record_id);
+
+        let serialized = serde_json::to_string(&checkpoint).unwrap();
+        let checkpoint: Checkpoint = serde_json::from_str(&serialized).unwrap();
+
+        let mut parser = EvtxParser::from_buffer(evtx_file.to_vec()).unwrap();
+        parser.resume_from(&checkpoint).unwrap();
+
+        let ids: Vec<_> = parser
+            .records_json()
+            .map(|r| r.unwrap().event_record_id)
+            .collect();
+
+        let all_ids: Vec<_> = EvtxParser::from_buffer(evtx_file.to_vec())
+            .unwrap()
+            .records_json()
+            .map(|r| r.unwrap().event_record_id)
+            .collect();
+
+        assert_eq!(ids.first(), Some(&1001));
+        assert_eq!(ids.last(), all_ids.last());
+    }
+
+    #[test]
+    fn test_resume_from_checkpoint_of_other_file() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+        let mut parser = EvtxParser::from_buffer(evtx_file.to_vec()).unwrap();
+
+        let (_, mut checkpoint) = parser.records_with_checkpoints().next().unwrap().unwrap();
+        checkpoint.file_identity ^= 1;
+
+        assert!(matches!(
+            parser.resume_from(&checkpoint),
+            Err(EvtxError::InvalidCheckpoint { .. })
+        ));
+    }
 }
@@ -0,0 +1,1109 @@
+use crate::binxml::assemble::{create_record_model, expand_templates};
+use crate::binxml::value_variant::BinXmlValue;
+use crate::err::{EvtxError, Result};
+use crate::model::xml::{char_from_reference, XmlModel};
+use crate::EvtxRecord;
+
+use chrono::{DateTime, Utc};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// A structured query over records, using the subset of XPath 1.0 supported by the Windows
+/// event log (the queries used by Event Viewer filters, subscriptions and `wevtutil qe /q:`).
+///
+/// Both plain XPath expressions and `<QueryList>` documents are accepted:
+///
+/// ```rust
+/// # use evtx::EvtxQuery;
+/// let query: EvtxQuery =
+///     "*[System[(EventID=4624 or EventID=4625) and TimeCreated[timediff(@SystemTime) <= 86400000]]]"
+///         .parse()
+///         .unwrap();
+///
+/// let query = EvtxQuery::parse(r#"
+///     <QueryList>
+///       <Query Id="0">
+///         <Select Path="Security">*[EventData[Data[@Name='TargetUserName']='admin']]</Select>
+///         <Suppress Path="Security">*[System[EventID=4634]]</Suppress>
+///       </Query>
+///     </QueryList>"#).unwrap();
+/// ```
+///
+/// Supported are element and `*` steps (separated by `/`), attributes (`@Name`), nested
+/// predicates, `and`, `or`, parentheses, the comparison operators (`=`, `!=`, `<`, `<=`, `>`, `>=`),
+/// string and number literals, and the functions `timediff`, `band`, and `not`.
+///
+/// In a `<QueryList>`, a record matches if it matches any `Select` and no `Suppress` element.
+/// The `Path` attributes are ignored, since records are read from a single file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvtxQuery {
+    select: Vec<Expr>,
+    suppress: Vec<Expr>,
+    reference_time: Option<DateTime<Utc>>,
+}
+
+impl EvtxQuery {
+    /// Parses an XPath expression, or a `<QueryList>` document.
+    pub fn parse(query: &str) -> Result<Self> {
+        let (select, suppress) = if query.trim_start().starts_with('<') {
+            parse_query_list(query)?
+        } else {
+            (vec![parse_expression(query)?], vec![])
+        };
+
+        Ok(EvtxQuery {
+            select,
+            suppress,
+            reference_time: None,
+        })
+    }
+
+    /// Sets the time `timediff` is computed against.
+    /// By default, the current time (at the time of evaluation) is used.
+    pub fn with_reference_time(mut self, reference_time: DateTime<Utc>) -> Self {
+        self.reference_time = Some(reference_time);
+        self
+    }
+
+    /// Returns true if `record` matches the query.
+    /// The record is not consumed, so it can still be serialized afterwards.
+    pub fn matches(&self, record: &EvtxRecord) -> Result<bool> {
+        let tokens = expand_templates(record.tokens.clone(), record.chunk)?;
+        let model = create_record_model(tokens, record.chunk)?;
+
+        Ok(self.matches_model(&model))
+    }
+
+    /// Returns true if the record described by `model` (see `create_record_model`) matches the query.
+    pub fn matches_model(&self, model: &[XmlModel]) -> bool {
+        let document = Node::from_model(model, &Scalar::from_value);
+        let evaluator = Evaluator {
+            now: self.reference_time.unwrap_or_else(Utc::now),
+            root: &document,
+        };
+
+        self.select
+            .iter()
+            .any(|expr| evaluator.eval(expr, &document).to_bool())
+            && !self
+                .suppress
+                .iter()
+                .any(|expr| evaluator.eval(expr, &document).to_bool())
+    }
+}
+
+impl FromStr for EvtxQuery {
+    type Err = EvtxError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        EvtxQuery::parse(s)
+    }
+}
+
+/// An expression (usually a path such as `System/EventID`) whose values are read from records.
+/// Relative paths start at the root element of the record.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Selector(Expr);
+
+impl Selector {
+    pub(crate) fn parse(selector: &str) -> Result<Self> {
+        Ok(Selector(parse_expression(selector)?))
+    }
+}
+
+/// A record, prepared for evaluating selectors against it.
+pub(crate) struct Document {
+    node: Node,
+    now: DateTime<Utc>,
+}
+
+impl Document {
+    /// Values for which `as_written` returns a string are selected as that string,
+    /// instead of being converted like they are in queries.
+    pub(crate) fn from_model(
+        model: &[XmlModel],
+        as_written: impl Fn(&BinXmlValue) -> Option<String>,
+    ) -> Self {
+        let scalar_from_value = |value: &BinXmlValue| {
+            as_written(value).map_or_else(|| Scalar::from_value(value), Scalar::String)
+        };
+
+        Document {
+            node: Node::from_model(model, &scalar_from_value),
+            now: Utc::now(),
+        }
+    }
+
+    /// Returns the values selected by `selector`, as strings.
+    pub(crate) fn select(&self, selector: &Selector) -> Vec<String> {
+        let evaluator = Evaluator {
+            now: self.now,
+            root: &self.node,
+        };
+        let context = self.node.children.first().unwrap_or(&self.node);
+
+        match evaluator.eval(&selector.0, context) {
+            Value::Bool(b) => vec![b.to_string()],
+            value => value.scalars().iter().map(Scalar::to_string).collect(),
+        }
+    }
+}
+
+fn invalid_query(position: usize, message: impl Into<String>) -> EvtxError {
+    EvtxError::InvalidQuery {
+        position,
+        message: message.into(),
+    }
+}
+
+/// Reads the `Select` and `Suppress` expressions of a `<QueryList>` document.
+fn parse_query_list(query: &str) -> Result<(Vec<Expr>, Vec<Expr>)> {
+    let mut reader = Reader::from_str(query);
+    let mut select = vec![];
+    let mut suppress = vec![];
+    // Set while reading the text of a `Select` (true) or `Suppress` (false) element.
+    let mut current: Option<bool> = None;
+    let mut text = String::new();
+
+    loop {
+        let position = reader.buffer_position();
+
+        match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                current = match e.name().as_ref() {
+                    b"Select" => Some(true),
+                    b"Suppress" => Some(false),
+                    _ => None,
+                };
+                text.clear();
+            }
+            Ok(Event::Text(t)) if current.is_some() => {
+                let unescaped = t
+                    .unescape()
+                    .map_err(|e| invalid_query(position, e.to_string()))?;
+                text.push_str(&unescaped);
+            }
+            Ok(Event::End(_)) => {
+                match current.take() {
+                    Some(true) => select.push(parse_expression(&text)?),
+                    Some(false) => suppress.push(parse_expression(&text)?),
+                    None => {}
+                }
+                text.clear();
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(invalid_query(position, e.to_string())),
+            _ => {}
+        }
+    }
+
+    if select.is_empty() {
+        return Err(invalid_query(0, "expected at least one `Select` element"));
+    }
+
+    Ok((select, suppress))
+}
+
+/// A value read from a record, or a literal in a query.
+#[derive(Debug, Clone, PartialEq)]
+enum Scalar {
+    Integer(i128),
+    Float(f64),
+    String(String),
+    DateTime(DateTime<Utc>),
+}
+
+impl Scalar {
+    fn from_value(value: &BinXmlValue) -> Scalar {
+        match value {
+            BinXmlValue::Int8Type(n) => Scalar::Integer(i128::from(*n)),
+            BinXmlValue::UInt8Type(n) => Scalar::Integer(i128::from(*n)),
+            BinXmlValue::Int16Type(n) => Scalar::Integer(i128::from(*n)),
+            BinXmlValue::UInt16Type(n) => Scalar::Integer(i128::from(*n)),
+            BinXmlValue::Int32Type(n) => Scalar::Integer(i128::from(*n)),
+            BinXmlValue::UInt32Type(n) => Scalar::Integer(i128::from(*n)),
+            BinXmlValue::Int64Type(n) => Scalar::Integer(i128::from(*n)),
+            BinXmlValue::UInt64Type(n) => Scalar::Integer(i128::from(*n)),
+            BinXmlValue::SizeTType(n) => Scalar::Integer(*n as i128),
+            BinXmlValue::Real32Type(n) => Scalar::Float(f64::from(*n)),
+            BinXmlValue::Real64Type(n) => Scalar::Float(*n),
+            BinXmlValue::FileTimeType(tm) | BinXmlValue::SysTimeType(tm) => Scalar::DateTime(*tm),
+            BinXmlValue::HexInt32Type(s) | BinXmlValue::HexInt64Type(s) => match parse_integer(s) {
+                Some(n) => Scalar::Integer(n),
+                None => Scalar::String(s.to_string()),
+            },
+            // Values without a textual representation (such as handles) match nothing but an empty string.
+            _ => Scalar::String(value.as_cow_str().map(Cow::into_owned).unwrap_or_default()),
+        }
+    }
+
+    fn as_integer(&self) -> Option<i128> {
+        match self {
+            Scalar::Integer(n) => Some(*n),
+            Scalar::Float(n) if n.fract() == 0.0 => Some(*n as i128),
+            Scalar::String(s) => parse_integer(s),
+            _ => None,
+        }
+    }
+
+    fn as_float(&self) -> Option<f64> {
+        match self {
+            Scalar::Integer(n) => Some(*n as f64),
+            Scalar::Float(n) => Some(*n),
+            Scalar::String(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    fn as_datetime(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Scalar::DateTime(tm) => Some(*tm),
+            Scalar::String(s) => DateTime::parse_from_rfc3339(s.trim())
+                .ok()
+                .map(|tm| tm.with_timezone(&Utc)),
+            _ => None,
+        }
+    }
+
+    fn to_bool(&self) -> bool {
+        match self {
+            Scalar::Integer(n) => *n != 0,
+            Scalar::Float(n) => *n != 0.0 && !n.is_nan(),
+            Scalar::String(s) => !s.is_empty(),
+            Scalar::DateTime(_) => true,
+        }
+    }
+
+    /// Compares two values, as numbers if one of them is a number, as timestamps if one of them
+    /// is a timestamp, and as strings otherwise.
+    fn compare(&self, other: &Scalar) -> Option<Ordering> {
+        match (self, other) {
+            (Scalar::DateTime(_), _) | (_, Scalar::DateTime(_)) => {
+                Some(self.as_datetime()?.cmp(&other.as_datetime()?))
+            }
+            (Scalar::String(left), Scalar::String(right)) => Some(left.cmp(right)),
+            _ => match (self.as_integer(), other.as_integer()) {
+                (Some(left), Some(right)) => Some(left.cmp(&right)),
+                _ => self.as_float()?.partial_cmp(&other.as_float()?),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Scalar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Scalar::Integer(n) => write!(f, "{}", n),
+            Scalar::Float(n) => write!(f, "{}", n),
+            Scalar::String(s) => write!(f, "{}", s),
+            Scalar::DateTime(tm) => write!(f, "{}", tm.format("%Y-%m-%dT%H:%M:%S%.6fZ")),
+        }
+    }
+}
+
+/// Parses a decimal integer, or an hexadecimal integer prefixed by `0x`.
+fn parse_integer(s: &str) -> Option<i128> {
+    let s = s.trim();
+
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => i128::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    fn matches(self, left: &Scalar, right: &Scalar) -> bool {
+        match (self, left.compare(right)) {
+            (CompareOp::Eq, Some(ordering)) => ordering == Ordering::Equal,
+            (CompareOp::Ne, Some(ordering)) => ordering != Ordering::Equal,
+            // Values which cannot be compared are compared by their text.
+            (CompareOp::Eq, None) => left.to_string() == right.to_string(),
+            (CompareOp::Ne, None) => left.to_string() != right.to_string(),
+            (CompareOp::Lt, Some(ordering)) => ordering == Ordering::Less,
+            (CompareOp::Le, Some(ordering)) => ordering != Ordering::Greater,
+            (CompareOp::Gt, Some(ordering)) => ordering == Ordering::Greater,
+            (CompareOp::Ge, Some(ordering)) => ordering != Ordering::Less,
+            (_, None) => false,
+        }
+    }
+
+    fn matches_bool(self, left: bool, right: bool) -> bool {
+        match self {
+            CompareOp::Eq => left == right,
+            CompareOp::Ne => left != right,
+            CompareOp::Lt => !left & right,
+            CompareOp::Le => left <= right,
+            CompareOp::Gt => left & !right,
+            CompareOp::Ge => left >= right,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Function {
+    /// `timediff(t)`: the number of milliseconds between `t` and now.
+    TimeDiff,
+    /// `band(a, b)`: bitwise and.
+    Band,
+    Not,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<(Function, usize)> {
+        match name {
+            "timediff" => Some((Function::TimeDiff, 1)),
+            "band" => Some((Function::Band, 2)),
+            "not" => Some((Function::Not, 1)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Compare(Box<Expr>, CompareOp, Box<Expr>),
+    Path(Path),
+    Literal(Scalar),
+    Function(Function, Vec<Expr>),
+}
+
+/// A location path, such as `System/Provider/@Name` or `Data[@Name='TargetUserName']`.
+#[derive(Debug, Clone, PartialEq)]
+struct Path {
+    absolute: bool,
+    steps: Vec<Step>,
+    /// The attribute selected at the end of the path (`None` in `@*`).
+    attribute: Option<Option<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Step {
+    /// The name of the elements selected by the step (`None` in `*`).
+    name: Option<String>,
+    predicates: Vec<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Star,
+    Slash,
+    LBracket,
+    RBracket,
+    LParen,
+    RParen,
+    At,
+    Comma,
+    Op(CompareOp),
+    Name(String),
+    Literal(String),
+    Number(Scalar),
+}
+
+fn tokenize(query: &str) -> Result<Vec<(usize, Token)>> {
+    let chars: Vec<(usize, char)> = query.char_indices().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    let is_name_char = |c: char| c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':');
+
+    while i < chars.len() {
+        let (position, c) = chars[i];
+        let next = chars.get(i + 1).map(|(_, c)| *c);
+
+        let token = match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '*' => Token::Star,
+            '/' => Token::Slash,
+            '[' => Token::LBracket,
+            ']' => Token::RBracket,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '@' => Token::At,
+            ',' => Token::Comma,
+            '=' => Token::Op(CompareOp::Eq),
+            '!' if next == Some('=') => {
+                i += 1;
+                Token::Op(CompareOp::Ne)
+            }
+            '<' if next == Some('=') => {
+                i += 1;
+                Token::Op(CompareOp::Le)
+            }
+            '>' if next == Some('=') => {
+                i += 1;
+                Token::Op(CompareOp::Ge)
+            }
+            '<' => Token::Op(CompareOp::Lt),
+            '>' => Token::Op(CompareOp::Gt),
+            '\'' | '"' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|(_, q)| *q == c)
+                    .map(|end| i + 1 + end)
+                    .ok_or_else(|| invalid_query(position, "unterminated string literal"))?;
+                let literal = chars[i + 1..end].iter().map(|(_, c)| c).collect();
+                i = end;
+                Token::Literal(literal)
+            }
+            c if c.is_ascii_digit() || (c == '-' && next.is_some_and(|n| n.is_ascii_digit())) => {
+                let start = i;
+                i += 1;
+                while i < chars.len() && (chars[i].1.is_ascii_alphanumeric() || chars[i].1 == '.') {
+                    i += 1;
+                }
+                let literal: String = chars[start..i].iter().map(|(_, c)| c).collect();
+                let number = match parse_integer(&literal) {
+                    Some(n) => Scalar::Integer(n),
+                    None => Scalar::Float(literal.parse().map_err(|_| {
+                        invalid_query(position, format!("invalid number `{}`", literal))
+                    })?),
+                };
+                tokens.push((position, Token::Number(number)));
+                continue;
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && is_name_char(chars[i].1) {
+                    i += 1;
+                }
+                let name = chars[start..i].iter().map(|(_, c)| c).collect();
+                tokens.push((position, Token::Name(name)));
+                continue;
+            }
+            c => return Err(invalid_query(position, format!("unexpected `{}`", c))),
+        };
+
+        tokens.push((position, token));
+        i += 1;
+    }
+
+    Ok(tokens)
+}
+
+fn parse_expression(query: &str) -> Result<Expr> {
+    let mut parser = Parser {
+        tokens: tokenize(query)?,
+        index: 0,
+        end: query.len(),
+    };
+
+    let expr = parser.parse_or()?;
+
+    match parser.tokens.get(parser.index) {
+        None => Ok(expr),
+        Some((position, token)) => Err(invalid_query(
+            *position,
+            format!("unexpected {:?} after the end of the expression", token),
+        )),
+    }
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    index: usize,
+    /// The length of the query, reported as the position of errors at its end.
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index).map(|(_, token)| token)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.index)
+            .map_or(self.end, |(position, _)| *position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.index).map(|(_, token)| token.clone());
+        self.index += 1;
+        token
+    }
+
+    fn consume(&mut self, expected: &Token) -> bool {
+        if self.peek() == Some(expected) {
+            self.index += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        if self.consume(&expected) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("{:?}", expected)))
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> EvtxError {
+        let found = match self.peek() {
+            Some(token) => format!("{:?}", token),
+            None => "the end of the query".to_string(),
+        };
+
+        invalid_query(
+            self.position(),
+            format!("expected {}, found {}", expected, found),
+        )
+    }
+
+    fn consume_keyword(&mut self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Name(name)) if name == keyword) && {
+            self.index += 1;
+            true
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_and()?;
+
+        while self.consume_keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_comparison()?;
+
+        while self.consume_keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_comparison()?));
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr> {
+        let left = self.parse_primary()?;
+
+        match self.peek() {
+            Some(Token::Op(op)) => {
+                let op = *op;
+                self.index += 1;
+                let right = self.parse_primary()?;
+                Ok(Expr::Compare(Box::new(left), op, Box::new(right)))
+            }
+            _ => Ok(left),
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr> {
+        match self.peek() {
+            Some(Token::LParen) => {
+                self.index += 1;
+                let expr = self.parse_or()?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            Some(Token::Literal(literal)) => {
+                let literal = Scalar::String(literal.clone());
+                self.index += 1;
+                Ok(Expr::Literal(literal))
+            }
+            Some(Token::Number(number)) => {
+                let number = number.clone();
+                self.index += 1;
+                Ok(Expr::Literal(number))
+            }
+            Some(Token::Name(name))
+                if matches!(self.tokens.get(self.index + 1), Some((_, Token::LParen))) =>
+            {
+                let position = self.position();
+                let (function, arity) = Function::from_name(name).ok_or_else(|| {
+                    invalid_query(position, format!("unsupported function `{}`", name))
+                })?;
+                self.index += 2;
+
+                let mut arguments = vec![];
+                if !self.consume(&Token::RParen) {
+                    loop {
+                        arguments.push(self.parse_or()?);
+                        if !self.consume(&Token::Comma) {
+                            break;
+                        }
+                    }
+                    self.expect(Token::RParen)?;
+                }
+
+                if arguments.len() != arity {
+                    return Err(invalid_query(
+                        position,
+                        format!("expected {} argument(s), found {}", arity, arguments.len()),
+                    ));
+                }
+
+                Ok(Expr::Function(function, arguments))
+            }
+            Some(Token::Star | Token::Name(_) | Token::At | Token::Slash) => {
+                Ok(Expr::Path(self.parse_path()?))
+            }
+            _ => Err(self.unexpected("an expression")),
+        }
+    }
+
+    fn parse_path(&mut self) -> Result<Path> {
+        let absolute = self.consume(&Token::Slash);
+        let mut steps = vec![];
+        let mut attribute = None;
+
+        loop {
+            if self.consume(&Token::At) {
+                attribute = Some(match self.next() {
+                    Some(Token::Star) => None,
+                    Some(Token::Name(name)) => Some(name),
+                    _ => {
+                        self.index -= 1;
+                        return Err(self.unexpected("an attribute name"));
+                    }
+                });
+                break;
+            }
+
+            let name = match self.next() {
+                Some(Token::Star) => None,
+                Some(Token::Name(name)) => Some(name),
+                _ => {
+                    self.index -= 1;
+                    return Err(self.unexpected("an element name"));
+                }
+            };
+
+            let mut predicates = vec![];
+            while self.consume(&Token::LBracket) {
+                predicates.push(self.parse_or()?);
+                self.expect(Token::RBracket)?;
+            }
+
+            steps.push(Step { name, predicates });
+
+            if !self.consume(&Token::Slash) {
+                break;
+            }
+        }
+
+        Ok(Path {
+            absolute,
+            steps,
+            attribute,
+        })
+    }
+}
+
+/// An element of a record, with its attributes and text.
+#[derive(Debug, Default)]
+struct Node {
+    name: String,
+    attributes: Vec<(String, Scalar)>,
+    text: Vec<Scalar>,
+    children: Vec<Node>,
+}
+
+impl Node {
+    /// Builds a document whose children are the top level elements of the record,
+    /// converting values with `scalar_from_value`.
+    fn from_model(model: &[XmlModel], scalar_from_value: &dyn Fn(&BinXmlValue) -> Scalar) -> Node {
+        let mut stack = vec![Node::default()];
+
+        for token in model {
+            match token {
+                XmlModel::OpenElement(element) => stack.push(Node {
+                    name: element.name.as_str().to_string(),
+                    attributes: element
+                        .attributes
+                        .iter()
+                        .map(|attr| {
+                            (
+                                attr.name.as_str().to_string(),
+                                scalar_from_value(&attr.value),
+                            )
+                        })
+                        .collect(),
+                    ..Node::default()
+                }),
+                XmlModel::CloseElement if stack.len() > 1 => {
+                    let node = stack.pop().expect("stack has more than one element");
+                    if let Some(parent) = stack.last_mut() {
+                        parent.children.push(node);
+                    }
+                }
+                XmlModel::Value(value) => {
+                    if let Some(node) = stack.last_mut() {
+                        node.text.push(scalar_from_value(value));
+                    }
+                }
+                XmlModel::CDATA(data) => {
+                    if let Some(node) = stack.last_mut() {
+                        node.text.push(Scalar::String(data.to_string()));
+                    }
+                }
+                XmlModel::CharRef(value) => {
+                    if let Some(node) = stack.last_mut() {
+                        node.text
+                            .push(Scalar::String(char_from_reference(*value).to_string()));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Close the elements which were left open.
+        while stack.len() > 1 {
+            let node = stack.pop().expect("stack has more than one element");
+            if let Some(parent) = stack.last_mut() {
+                parent.children.push(node);
+            }
+        }
+
+        stack.pop().unwrap_or_default()
+    }
+
+    /// The text of the element.
+    fn value(&self) -> Scalar {
+        match self.text.as_slice() {
+            [value] => value.clone(),
+            values => Scalar::String(values.iter().map(Scalar::to_string).collect()),
+        }
+    }
+}
+
+/// The result of evaluating an expression.
+enum Value<'n> {
+    Nodes(Vec<&'n Node>),
+    Attributes(Vec<&'n Scalar>),
+    Scalar(Scalar),
+    Bool(bool),
+}
+
+impl<'n> Value<'n> {
+    fn to_bool(&self) -> bool {
+        match self {
+            Value::Nodes(nodes) => !nodes.is_empty(),
+            Value::Attributes(attributes) => !attributes.is_empty(),
+            Value::Scalar(scalar) => scalar.to_bool(),
+            Value::Bool(b) => *b,
+        }
+    }
+
+    fn scalars(&self) -> Vec<Scalar> {
+        match self {
+            Value::Nodes(nodes) => nodes.iter().map(|node| node.value()).collect(),
+            Value::Attributes(attributes) => attributes.iter().map(|a| (*a).clone()).collect(),
+            Value::Scalar(scalar) => vec![scalar.clone()],
+            Value::Bool(b) => vec![Scalar::Integer(i128::from(*b))],
+        }
+    }
+}
+
+struct Evaluator<'n> {
+    now: DateTime<Utc>,
+    root: &'n Node,
+}
+
+impl<'n> Evaluator<'n> {
+    fn eval(&self, expr: &Expr, context: &'n Node) -> Value<'n> {
+        match expr {
+            Expr::Or(left, right) => Value::Bool(
+                self.eval(left, context).to_bool() || self.eval(right, context).to_bool(),
+            ),
+            Expr::And(left, right) => Value::Bool(
+                self.eval(left, context).to_bool() && self.eval(right, context).to_bool(),
+            ),
+            Expr::Compare(left, op, right) => {
+                let left = self.eval(left, context);
+                let right = self.eval(right, context);
+
+                // Node sets match if any of their values matches.
+                let matches = match (&left, &right) {
+                    (Value::Bool(_), _) | (_, Value::Bool(_)) => {
+                        op.matches_bool(left.to_bool(), right.to_bool())
+                    }
+                    _ => {
+                        let right = right.scalars();
+                        left.scalars()
+                            .iter()
+                            .any(|l| right.iter().any(|r| op.matches(l, r)))
+                    }
+                };
+
+                Value::Bool(matches)
+            }
+            Expr::Path(path) => self.eval_path(path, context),
+            Expr::Literal(scalar) => Value::Scalar(scalar.clone()),
+            Expr::Function(function, arguments) => {
+                let mut arguments = arguments.iter().map(|arg| self.eval(arg, context));
+
+                match function {
+                    Function::TimeDiff => {
+                        let time = arguments
+                            .next()
+                            .and_then(|arg| arg.scalars().iter().find_map(Scalar::as_datetime));
+
+                        match time {
+                            Some(time) => Value::Scalar(Scalar::Integer(i128::from(
+                                (self.now - time).num_milliseconds(),
+                            ))),
+                            None => Value::Nodes(vec![]),
+                        }
+                    }
+                    Function::Band => {
+                        let mut integer = || {
+                            arguments
+                                .next()
+                                .and_then(|arg| arg.scalars().iter().find_map(Scalar::as_integer))
+                        };
+
+                        match (integer(), integer()) {
+                            (Some(left), Some(right)) => {
+                                Value::Scalar(Scalar::Integer(left & right))
+                            }
+                            _ => Value::Nodes(vec![]),
+                        }
+                    }
+                    Function::Not => {
+                        Value::Bool(!arguments.next().is_some_and(|arg| arg.to_bool()))
+                    }
+                }
+            }
+        }
+    }
+
+    fn eval_path(&self, path: &Path, context: &'n Node) -> Value<'n> {
+        let mut nodes = vec![if path.absolute { self.root } else { context }];
+
+        for step in &path.steps {
+            let mut selected = vec![];
+
+            for node in nodes {
+                let mut candidates: Vec<&Node> = node
+                    .children
+                    .iter()
+                    .filter(|child| step.name.as_ref().is_none_or(|name| child.name == *name))
+                    .collect();
+
+                for predicate in &step.predicates {
+                    candidates = candidates
+                        .into_iter()
+                        .enumerate()
+                        .filter(|(index, candidate)| {
+                            match self.eval(predicate, candidate) {
+                                // A number selects the candidate at that (1-based) position.
+                                Value::Scalar(Scalar::Integer(position)) => {
+                                    position == *index as i128 + 1
+                                }
+                                value => value.to_bool(),
+                            }
+                        })
+                        .map(|(_, candidate)| candidate)
+                        .collect();
+                }
+
+                selected.extend(candidates);
+            }
+
+            nodes = selected;
+        }
+
+        match &path.attribute {
+            None => Value::Nodes(nodes),
+            Some(attribute) => Value::Attributes(
+                nodes
+                    .iter()
+                    .flat_map(|node| node.attributes.iter())
+                    .filter(|(name, _)| attribute.as_ref().is_none_or(|a| name == a))
+                    .map(|(_, value)| value)
+                    .collect(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ensure_env_logger_initialized;
+    use crate::{EvtxParser, ParserSettings};
+    use std::sync::Arc;
+
+    fn matching_ids(query: &EvtxQuery) -> Vec<u64> {
+        let evtx_file = include_bytes!("../samples/security.evtx");
+        let mut parser = EvtxParser::from_buffer(evtx_file.to_vec()).unwrap();
+        let mut ids = vec![];
+
+        for chunk in parser.chunks() {
+            let mut chunk = chunk.unwrap();
+            let mut chunk = chunk.parse(Arc::new(ParserSettings::new())).unwrap();
+
+            for record in chunk.iter() {
+                let record = record.unwrap();
+                if query.matches(&record).unwrap() {
+                    ids.push(record.event_record_id);
+                }
+            }
+        }
+
+        ids
+    }
+
+    fn matching_ids_json(f: impl Fn(&serde_json::Value) -> bool) -> Vec<u64> {
+        let evtx_file = include_bytes!("../samples/security.evtx");
+        let settings = ParserSettings::new().separate_json_attributes(true);
+        let mut parser = EvtxParser::from_buffer(evtx_file.to_vec())
+            .unwrap()
+            .with_configuration(settings);
+
+        parser
+            .records_json_value()
+            .map(|r| r.unwrap())
+            .filter(|r| f(&r.data["Event"]))
+            .map(|r| r.event_record_id)
+            .collect()
+    }
+
+    #[test]
+    fn test_query_by_event_id() {
+        ensure_env_logger_initialized();
+        let query = EvtxQuery::parse("*[System[(EventID=4624 or EventID=4672)]]").unwrap();
+
+        let expected = matching_ids_json(|event| {
+            event["System"]["EventID"] == 4624 || event["System"]["EventID"] == 4672
+        });
+
+        assert!(!expected.is_empty());
+        assert_eq!(matching_ids(&query), expected);
+    }
+
+    #[test]
+    fn test_query_by_event_data() {
+        ensure_env_logger_initialized();
+        let query = EvtxQuery::parse(
+            "*[System[EventID=4624] and EventData[Data[@Name='TargetUserName']='SYSTEM']]",
+        )
+        .unwrap();
+
+        let expected = matching_ids_json(|event| {
+            event["System"]["EventID"] == 4624 && event["EventData"]["TargetUserName"] == "SYSTEM"
+        });
+
+        assert!(!expected.is_empty());
+        assert_eq!(matching_ids(&query), expected);
+    }
+
+    #[test]
+    fn test_query_by_time() {
+        ensure_env_logger_initialized();
+        let reference_time = DateTime::parse_from_rfc3339("2016-07-08T18:13:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        // Records written in the 10 seconds before the reference time.
+        let query = EvtxQuery::parse(
+            "*[System[TimeCreated[timediff(@SystemTime) >= 0 and timediff(@SystemTime) <= 10000]]]",
+        )
+        .unwrap()
+        .with_reference_time(reference_time);
+
+        let expected = matching_ids_json(|event| {
+            let time = event["System"]["TimeCreated_attributes"]["SystemTime"]
+                .as_str()
+                .unwrap();
+            let time = DateTime::parse_from_rfc3339(time).unwrap();
+            let diff = (reference_time - time.with_timezone(&Utc)).num_milliseconds();
+            (0..=10000).contains(&diff)
+        });
+
+        assert!(!expected.is_empty());
+        assert_eq!(matching_ids(&query), expected);
+
+        let query = EvtxQuery::parse(
+            "*[System[TimeCreated[@SystemTime>='2016-07-08T18:12:51Z' and @SystemTime<'2016-07-08T18:13:00Z']]]",
+        )
+        .unwrap();
+        assert_eq!(matching_ids(&query), expected);
+    }
+
+    #[test]
+    fn test_query_list_with_suppress() {
+        ensure_env_logger_initialized();
+        let query = EvtxQuery::parse(
+            r#"<QueryList>
+                 <Query Id="0" Path="Security">
+                   <Select Path="Security">*[System[band(Keywords,9007199254740992) and Level=0]]</Select>
+                   <Suppress Path="Security">*[System[EventID=4624]]</Suppress>
+                 </Query>
+               </QueryList>"#,
+        )
+        .unwrap();
+
+        // Audit success (0x20000000000000) events with level 0, other than logons.
+        let expected = matching_ids_json(|event| {
+            let keywords = event["System"]["Keywords"].as_str().unwrap();
+            let keywords = u64::from_str_radix(keywords.trim_start_matches("0x"), 16).unwrap();
+            keywords & 0x20000000000000 != 0
+                && event["System"]["Level"] == 0
+                && event["System"]["EventID"] != 4624
+        });
+
+        assert!(!expected.is_empty());
+        assert_eq!(matching_ids(&query), expected);
+    }
+
+    #[test]
+    fn test_query_by_hexadecimal_value() {
+        ensure_env_logger_initialized();
+        let expected =
+            matching_ids_json(|event| event["System"]["Keywords"] == "0x8020000000000000");
+        assert!(!expected.is_empty());
+
+        // Hexadecimal values are compared as numbers.
+        for query in [
+            "*[System[Keywords=9232379236109516800]]",
+            "*[System[Keywords='0x8020000000000000']]",
+            "*[System[Keywords='0X8020000000000000']]",
+        ] {
+            let query = EvtxQuery::parse(query).unwrap();
+            assert_eq!(matching_ids(&query), expected);
+        }
+    }
+
+    #[test]
+    fn test_invalid_queries() {
+        for (query, position) in [
+            ("*[System[EventID=4624]", 22),
+            ("*[System[EventID=]]", 17),
+            ("*[System[unknown(EventID)]]", 9),
+            ("*[EventData[Data='admin]]", 17),
+            ("<QueryList></QueryList>", 0),
+        ] {
+            match EvtxQuery::parse(query) {
+                Err(EvtxError::InvalidQuery { position: p, .. }) => {
+                    assert_eq!(p, position, "{}", query)
+                }
+                other => panic!("{}: expected an invalid query, got {:?}", query, other),
+            }
+        }
+    }
+}
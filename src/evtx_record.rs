@@ -1,11 +1,13 @@
-use crate::binxml::assemble::parse_tokens;
+use crate::binxml::assemble::{create_record_model, expand_templates, parse_tokens};
+use crate::csv_output::CsvOutput;
 use crate::err::{
     DeserializationError, DeserializationResult, EvtxError, Result, SerializationError,
 };
-use crate::json_output::JsonOutput;
+use crate::json_output::{JsonFormat, JsonOutput};
+use crate::json_stream_output::JsonStreamOutput;
 use crate::model::deserialized::BinXMLDeserializedTokens;
 use crate::xml_output::{BinXmlOutput, XmlOutput};
-use crate::{EvtxChunk, ParserSettings};
+use crate::{ChunkOffset, EvtxChunk, ParserSettings};
 
 use byteorder::ReadBytesExt;
 use chrono::prelude::*;
@@ -19,6 +21,12 @@ pub struct EvtxRecord<'a> {
     pub chunk: &'a EvtxChunk<'a>,
     pub event_record_id: RecordId,
     pub timestamp: DateTime<Utc>,
+    /// Offset of the record header, relative to the start of the chunk.
+    pub record_offset: ChunkOffset,
+    /// Set when the record was recovered from the slack space of its chunk.
+    pub recovered_from_slack: bool,
+    /// Set when only part of the data of a record recovered from slack space could be read.
+    pub truncated: bool,
     pub tokens: Vec<BinXMLDeserializedTokens<'a>>,
     pub settings: Arc<ParserSettings>,
 }
@@ -34,6 +42,10 @@ pub struct EvtxRecordHeader {
 pub struct SerializedEvtxRecord<T> {
     pub event_record_id: RecordId,
     pub timestamp: DateTime<Utc>,
+    /// Set when the record was recovered from the slack space of its chunk.
+    pub recovered_from_slack: bool,
+    /// Set when only part of the data of a record recovered from slack space could be read.
+    pub truncated: bool,
     pub data: T,
 }
 
@@ -47,6 +59,12 @@ impl EvtxRecordHeader {
         }
 
         let size = try_read!(input, u32)?;
+
+        // The header and the copy of the size at the end of the record.
+        if size < 24 + 4 {
+            return Err(DeserializationError::EvtxRecordTooSmall { size });
+        }
+
         let record_id = try_read!(input, u64)?;
         let timestamp = try_read!(input, filetime)?;
 
@@ -60,7 +78,7 @@ impl EvtxRecordHeader {
     pub fn record_data_size(&self) -> u32 {
         // 24 - record header size
         // 4 - copy of size record size
-        self.data_size - 24 - 4
+        self.data_size.saturating_sub(24 + 4)
     }
 }
 
@@ -84,17 +102,49 @@ impl<'a> EvtxRecord<'a> {
 
         let event_record_id = self.event_record_id;
         let timestamp = self.timestamp;
+        let recovered_from_slack = self.recovered_from_slack;
+        let truncated = self.truncated;
         self.into_output(&mut output_builder)?;
 
         Ok(SerializedEvtxRecord {
             event_record_id,
             timestamp,
+            recovered_from_slack,
+            truncated,
             data: output_builder.into_value()?,
         })
     }
 
     /// Consumes the record and parse it, producing a JSON serialized record.
+    ///
+    /// Nested JSON is written directly (see `JsonStreamOutput`), flat JSON is serialized
+    /// from the value built by `into_json_value`.
     pub fn into_json(self) -> Result<SerializedEvtxRecord<String>> {
+        if *self.settings.get_json_format() != JsonFormat::Nested {
+            return self.into_json_from_value();
+        }
+
+        let mut output_builder = JsonStreamOutput::with_writer(Vec::new(), &self.settings);
+
+        let event_record_id = self.event_record_id;
+        let timestamp = self.timestamp;
+        let recovered_from_slack = self.recovered_from_slack;
+        let truncated = self.truncated;
+        self.into_output(&mut output_builder)?;
+
+        let data =
+            String::from_utf8(output_builder.into_writer()).map_err(SerializationError::from)?;
+
+        Ok(SerializedEvtxRecord {
+            event_record_id,
+            timestamp,
+            recovered_from_slack,
+            truncated,
+            data,
+        })
+    }
+
+    fn into_json_from_value(self) -> Result<SerializedEvtxRecord<String>> {
         let indent = self.settings.should_indent();
         let record_with_json_value = self.into_json_value()?;
 
@@ -108,6 +158,8 @@ impl<'a> EvtxRecord<'a> {
         Ok(SerializedEvtxRecord {
             event_record_id: record_with_json_value.event_record_id,
             timestamp: record_with_json_value.timestamp,
+            recovered_from_slack: record_with_json_value.recovered_from_slack,
+            truncated: record_with_json_value.truncated,
             data,
         })
     }
@@ -118,6 +170,8 @@ impl<'a> EvtxRecord<'a> {
 
         let event_record_id = self.event_record_id;
         let timestamp = self.timestamp;
+        let recovered_from_slack = self.recovered_from_slack;
+        let truncated = self.truncated;
         self.into_output(&mut output_builder)?;
 
         let data =
@@ -126,7 +180,23 @@ impl<'a> EvtxRecord<'a> {
         Ok(SerializedEvtxRecord {
             event_record_id,
             timestamp,
+            recovered_from_slack,
+            truncated,
             data,
         })
     }
+
+    /// Consumes the record, producing a delimited row with the columns of `output`.
+    pub fn into_csv(self, output: &CsvOutput) -> Result<SerializedEvtxRecord<String>> {
+        let tokens = expand_templates(self.tokens, self.chunk)?;
+        let model = create_record_model(tokens, self.chunk)?;
+
+        Ok(SerializedEvtxRecord {
+            event_record_id: self.event_record_id,
+            timestamp: self.timestamp,
+            recovered_from_slack: self.recovered_from_slack,
+            truncated: self.truncated,
+            data: output.row(self.event_record_id, self.timestamp, &model),
+        })
+    }
 }
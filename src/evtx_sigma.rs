@@ -0,0 +1,1117 @@
+use crate::binxml::assemble::{expand_string_ref, walk_expanded_tokens};
+use crate::binxml::name::BinXmlName;
+use crate::binxml::value_variant::BinXmlValue;
+use crate::err::{EvtxError, InputError, Result};
+use crate::evtx_record::RecordId;
+use crate::model::deserialized::BinXMLDeserializedTokens;
+use crate::{EvtxChunk, EvtxRecord};
+
+use chrono::{DateTime, Utc};
+use hashbrown::HashMap;
+use log::warn;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value as YamlValue;
+use std::borrow::Cow;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The flattened fields of a record, by name.
+///
+/// `System` fields are named after their element (`EventID`, `Channel`, ...), and attributes
+/// after their element and attribute (`Provider_Name`, `Execution_ProcessID`, ...).
+/// `EventData` fields are named after the `Name` attribute of their `Data` element, other fields
+/// (in `UserData` for example) are named after their element.
+pub type SigmaFields = HashMap<String, Vec<String>>;
+
+/// A match of a rule against a record.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SigmaMatch {
+    pub rule_id: Option<String>,
+    pub title: String,
+    pub level: Option<String>,
+    pub event_record_id: RecordId,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// A compiled Sigma rule.
+///
+/// Supported are:
+/// - Selections made of maps (fields are and-ed) or lists of maps (or-ed), and keyword lists.
+/// - The `contains`, `startswith`, `endswith`, `all`, `re`, `exists`, `gt`, `gte`, `lt`, `lte`,
+///   `windash` and `cased` modifiers, as well as wildcards (`*` and `?`).
+/// - Conditions using `and`, `or`, `not`, parentheses, `1 of`/`any of`/`all of` a pattern or `them`.
+///
+/// Aggregations (`| count() ...`) are not supported.
+#[derive(Debug, Clone)]
+pub struct SigmaRule {
+    pub id: Option<String>,
+    pub title: String,
+    pub level: Option<String>,
+    pub tags: Vec<String>,
+    logsource: LogSource,
+    selections: Vec<(String, Selection)>,
+    conditions: Vec<Condition>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawRule {
+    title: String,
+    id: Option<String>,
+    level: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    logsource: RawLogSource,
+    detection: serde_yaml::Mapping,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RawLogSource {
+    category: Option<String>,
+    service: Option<String>,
+}
+
+impl SigmaRule {
+    /// Parses and compiles a single rule.
+    pub fn from_yaml(yaml: &str) -> Result<SigmaRule> {
+        let raw: RawRule = serde_yaml::from_str(yaml).map_err(|e| invalid_rule(e.to_string()))?;
+
+        let mut selections = vec![];
+        let mut conditions = vec![];
+
+        for (name, definition) in raw.detection.iter() {
+            let name = name
+                .as_str()
+                .ok_or_else(|| invalid_rule("detection keys must be strings"))?;
+
+            match name {
+                "condition" => match definition {
+                    YamlValue::String(condition) => conditions.push(condition.as_str()),
+                    YamlValue::Sequence(items) => {
+                        for item in items {
+                            conditions.push(
+                                item.as_str()
+                                    .ok_or_else(|| invalid_rule("conditions must be strings"))?,
+                            );
+                        }
+                    }
+                    _ => return Err(invalid_rule("conditions must be strings")),
+                },
+                // Only used by aggregations.
+                "timeframe" => {}
+                _ => selections.push((name.to_string(), Selection::compile(definition)?)),
+            }
+        }
+
+        let selection_names: Vec<&str> = selections.iter().map(|(name, _)| name.as_str()).collect();
+        let conditions = conditions
+            .into_iter()
+            .map(|condition| Condition::parse(condition, &selection_names))
+            .collect::<Result<Vec<_>>>()?;
+
+        if conditions.is_empty() {
+            return Err(invalid_rule("missing condition"));
+        }
+
+        Ok(SigmaRule {
+            id: raw.id,
+            title: raw.title,
+            level: raw.level,
+            tags: raw.tags,
+            logsource: LogSource::new(&raw.logsource),
+            selections,
+            conditions,
+        })
+    }
+
+    /// Returns true if the rule matches the fields of a record.
+    pub fn matches(&self, fields: &SigmaFields) -> bool {
+        self.logsource.matches(fields)
+            && self
+                .conditions
+                .iter()
+                .any(|condition| condition.eval(&self.selections, fields))
+    }
+}
+
+/// A set of rules, evaluated together against records.
+///
+/// ```rust,no_run
+/// # use evtx::{EvtxParser, SigmaRuleSet};
+/// let rules = SigmaRuleSet::load_dir("rules/windows").unwrap();
+/// let mut parser = EvtxParser::from_path("Security.evtx").unwrap();
+///
+/// for matches in parser.serialized_records(|record| rules.evaluate(&record?)) {
+///     for m in matches.unwrap() {
+///         println!("{} - {}", m.event_record_id, m.title);
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SigmaRuleSet {
+    rules: Vec<SigmaRule>,
+    skipped: Vec<(PathBuf, String)>,
+}
+
+impl SigmaRuleSet {
+    pub fn from_rules(rules: Vec<SigmaRule>) -> Self {
+        SigmaRuleSet {
+            rules,
+            skipped: vec![],
+        }
+    }
+
+    /// Loads all the rules (`.yml` and `.yaml` files) in `path` and its subdirectories.
+    ///
+    /// Rules which cannot be compiled (for example, because they use unsupported modifiers)
+    /// are skipped, and can be listed using `skipped_rules`.
+    pub fn load_dir(path: impl AsRef<Path>) -> Result<Self> {
+        let mut rule_set = SigmaRuleSet::default();
+        let mut directories = vec![path.as_ref().to_path_buf()];
+
+        while let Some(directory) = directories.pop() {
+            let mut entries = fs::read_dir(&directory)
+                .and_then(|entries| {
+                    entries
+                        .map(|entry| entry.map(|entry| entry.path()))
+                        .collect::<std::io::Result<Vec<_>>>()
+                })
+                .map_err(|e| InputError::failed_to_open_file(e, &directory))?;
+            // Keep the order of the rules stable.
+            entries.sort();
+
+            for path in entries {
+                if path.is_dir() {
+                    directories.push(path);
+                    continue;
+                }
+
+                let is_rule = matches!(
+                    path.extension().and_then(|ext| ext.to_str()),
+                    Some("yml" | "yaml")
+                );
+                if !is_rule {
+                    continue;
+                }
+
+                let yaml = fs::read_to_string(&path)
+                    .map_err(|e| InputError::failed_to_open_file(e, &path))?;
+
+                match SigmaRule::from_yaml(&yaml) {
+                    Ok(rule) => rule_set.rules.push(rule),
+                    Err(err) => {
+                        warn!("Skipping sigma rule {}: {}", path.display(), err);
+                        rule_set.skipped.push((path, err.to_string()));
+                    }
+                }
+            }
+        }
+
+        Ok(rule_set)
+    }
+
+    pub fn rules(&self) -> &[SigmaRule] {
+        &self.rules
+    }
+
+    /// The rules which were skipped by `load_dir`, along with the reason.
+    pub fn skipped_rules(&self) -> &[(PathBuf, String)] {
+        &self.skipped
+    }
+
+    /// Returns the rules matching `record`.
+    /// The record is not consumed, so it can still be serialized afterwards.
+    pub fn evaluate(&self, record: &EvtxRecord) -> Result<Vec<SigmaMatch>> {
+        let fields = record.sigma_fields()?;
+
+        Ok(self
+            .rules
+            .iter()
+            .filter(|rule| rule.matches(&fields))
+            .map(|rule| SigmaMatch {
+                rule_id: rule.id.clone(),
+                title: rule.title.clone(),
+                level: rule.level.clone(),
+                event_record_id: record.event_record_id,
+                timestamp: record.timestamp,
+            })
+            .collect())
+    }
+}
+
+fn invalid_rule(message: impl Into<String>) -> EvtxError {
+    EvtxError::InvalidSigmaRule {
+        message: message.into(),
+    }
+}
+
+const SYSMON_CHANNEL: &str = "Microsoft-Windows-Sysmon/Operational";
+
+/// The records a rule applies to, according to its `logsource`.
+/// Unknown categories and services are not restricted.
+#[derive(Debug, Clone, Default)]
+struct LogSource {
+    /// Pairs of channel and event ids (`None` for any event id), any of which may match.
+    sources: Vec<(&'static str, Option<&'static [u16]>)>,
+}
+
+impl LogSource {
+    fn new(raw: &RawLogSource) -> Self {
+        let by_category: &[(&str, Option<&[u16]>)] = match raw.category.as_deref() {
+            Some("process_creation") => {
+                &[(SYSMON_CHANNEL, Some(&[1])), ("Security", Some(&[4688]))]
+            }
+            Some("network_connection") => &[(SYSMON_CHANNEL, Some(&[3]))],
+            Some("process_termination") => {
+                &[(SYSMON_CHANNEL, Some(&[5])), ("Security", Some(&[4689]))]
+            }
+            Some("driver_load") => &[(SYSMON_CHANNEL, Some(&[6]))],
+            Some("image_load") => &[(SYSMON_CHANNEL, Some(&[7]))],
+            Some("create_remote_thread") => &[(SYSMON_CHANNEL, Some(&[8]))],
+            Some("raw_access_thread") => &[(SYSMON_CHANNEL, Some(&[9]))],
+            Some("process_access") => &[(SYSMON_CHANNEL, Some(&[10]))],
+            Some("file_event") => &[(SYSMON_CHANNEL, Some(&[11]))],
+            Some("registry_event") => &[(SYSMON_CHANNEL, Some(&[12, 13, 14]))],
+            Some("registry_add" | "registry_delete") => &[(SYSMON_CHANNEL, Some(&[12]))],
+            Some("registry_set") => &[(SYSMON_CHANNEL, Some(&[13]))],
+            Some("registry_rename") => &[(SYSMON_CHANNEL, Some(&[14]))],
+            Some("create_stream_hash") => &[(SYSMON_CHANNEL, Some(&[15]))],
+            Some("pipe_created") => &[(SYSMON_CHANNEL, Some(&[17, 18]))],
+            Some("wmi_event") => &[(SYSMON_CHANNEL, Some(&[19, 20, 21]))],
+            Some("dns_query") => &[(SYSMON_CHANNEL, Some(&[22]))],
+            Some("file_delete") => &[(SYSMON_CHANNEL, Some(&[23, 26]))],
+            _ => &[],
+        };
+
+        let by_service: &[(&str, Option<&[u16]>)] = match raw.service.as_deref() {
+            Some("security") => &[("Security", None)],
+            Some("system") => &[("System", None)],
+            Some("application") => &[("Application", None)],
+            Some("sysmon") => &[(SYSMON_CHANNEL, None)],
+            Some("powershell") => &[("Microsoft-Windows-PowerShell/Operational", None)],
+            Some("powershell-classic") => &[("Windows PowerShell", None)],
+            Some("taskscheduler") => &[("Microsoft-Windows-TaskScheduler/Operational", None)],
+            Some("wmi") => &[("Microsoft-Windows-WMI-Activity/Operational", None)],
+            Some("windefend") => &[("Microsoft-Windows-Windows Defender/Operational", None)],
+            Some("bits-client") => &[("Microsoft-Windows-Bits-Client/Operational", None)],
+            Some("firewall-as") => &[(
+                "Microsoft-Windows-Windows Firewall With Advanced Security/Firewall",
+                None,
+            )],
+            _ => &[],
+        };
+
+        LogSource {
+            sources: if by_category.is_empty() {
+                by_service.to_vec()
+            } else {
+                by_category.to_vec()
+            },
+        }
+    }
+
+    fn matches(&self, fields: &SigmaFields) -> bool {
+        if self.sources.is_empty() {
+            return true;
+        }
+
+        let channel = fields.get("Channel").and_then(|values| values.first());
+        let event_id = fields
+            .get("EventID")
+            .and_then(|values| values.first())
+            .and_then(|id| id.parse::<u16>().ok());
+
+        self.sources.iter().any(|(source_channel, event_ids)| {
+            channel.is_some_and(|channel| channel.eq_ignore_ascii_case(source_channel))
+                && event_ids.is_none_or(|ids| event_id.is_some_and(|id| ids.contains(&id)))
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Selection {
+    /// All the field conditions must match.
+    Fields(Vec<FieldCondition>),
+    /// Any of the selections must match.
+    Any(Vec<Selection>),
+    /// Any field must match any of the values.
+    Keywords(Vec<ValueMatcher>),
+}
+
+impl Selection {
+    fn compile(definition: &YamlValue) -> Result<Selection> {
+        match definition {
+            YamlValue::Mapping(mapping) => {
+                let mut conditions = vec![];
+
+                for (field, values) in mapping.iter() {
+                    let field = field
+                        .as_str()
+                        .ok_or_else(|| invalid_rule("field names must be strings"))?;
+                    conditions.push(FieldCondition::compile(field, values)?);
+                }
+
+                Ok(Selection::Fields(conditions))
+            }
+            YamlValue::Sequence(items) if items.iter().all(YamlValue::is_mapping) => {
+                Ok(Selection::Any(
+                    items
+                        .iter()
+                        .map(Selection::compile)
+                        .collect::<Result<_>>()?,
+                ))
+            }
+            YamlValue::Sequence(items) => Ok(Selection::Keywords(
+                items
+                    .iter()
+                    .map(|item| ValueMatcher::compile(item, &[Modifier::Contains]))
+                    .collect::<Result<_>>()?,
+            )),
+            value => Ok(Selection::Keywords(vec![ValueMatcher::compile(
+                value,
+                &[Modifier::Contains],
+            )?])),
+        }
+    }
+
+    fn matches(&self, fields: &SigmaFields) -> bool {
+        match self {
+            Selection::Fields(conditions) => conditions.iter().all(|c| c.matches(fields)),
+            Selection::Any(selections) => selections.iter().any(|s| s.matches(fields)),
+            Selection::Keywords(keywords) => fields
+                .values()
+                .flatten()
+                .any(|value| keywords.iter().any(|keyword| keyword.matches(Some(value)))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Modifier {
+    Contains,
+    StartsWith,
+    EndsWith,
+    All,
+    Re,
+    Exists,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    Windash,
+    Cased,
+}
+
+impl Modifier {
+    fn parse(name: &str) -> Result<Modifier> {
+        Ok(match name {
+            "contains" => Modifier::Contains,
+            "startswith" => Modifier::StartsWith,
+            "endswith" => Modifier::EndsWith,
+            "all" => Modifier::All,
+            "re" => Modifier::Re,
+            "exists" => Modifier::Exists,
+            "gt" => Modifier::Gt,
+            "gte" => Modifier::Gte,
+            "lt" => Modifier::Lt,
+            "lte" => Modifier::Lte,
+            "windash" => Modifier::Windash,
+            "cased" => Modifier::Cased,
+            _ => return Err(invalid_rule(format!("unsupported modifier `{}`", name))),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct FieldCondition {
+    field: String,
+    /// Set by the `all` modifier, in which case all the values must match.
+    all: bool,
+    values: Vec<ValueMatcher>,
+}
+
+impl FieldCondition {
+    fn compile(spec: &str, values: &YamlValue) -> Result<FieldCondition> {
+        let mut parts = spec.split('|');
+        let field = parts.next().unwrap_or_default().to_string();
+        let modifiers = parts.map(Modifier::parse).collect::<Result<Vec<_>>>()?;
+
+        let values = match values {
+            YamlValue::Sequence(items) => items
+                .iter()
+                .map(|item| ValueMatcher::compile(item, &modifiers))
+                .collect::<Result<_>>()?,
+            value => vec![ValueMatcher::compile(value, &modifiers)?],
+        };
+
+        Ok(FieldCondition {
+            field,
+            all: modifiers.contains(&Modifier::All),
+            values,
+        })
+    }
+
+    fn matches(&self, fields: &SigmaFields) -> bool {
+        let field_values = fields.get(&self.field);
+
+        let value_matches = |matcher: &ValueMatcher| match field_values {
+            Some(values) => values.iter().any(|value| matcher.matches(Some(value))),
+            None => matcher.matches(None),
+        };
+
+        if self.all {
+            self.values.iter().all(value_matches)
+        } else {
+            self.values.iter().any(value_matches)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum ValueMatcher {
+    /// Matches missing or empty fields.
+    Null,
+    Exists(bool),
+    Pattern(Regex),
+    Compare(Modifier, f64),
+}
+
+impl ValueMatcher {
+    fn compile(value: &YamlValue, modifiers: &[Modifier]) -> Result<ValueMatcher> {
+        let value = match value {
+            YamlValue::Null => return Ok(ValueMatcher::Null),
+            YamlValue::Bool(b) if modifiers.contains(&Modifier::Exists) => {
+                return Ok(ValueMatcher::Exists(*b))
+            }
+            YamlValue::String(s) => s.clone(),
+            YamlValue::Number(n) => n.to_string(),
+            YamlValue::Bool(b) => b.to_string(),
+            _ => return Err(invalid_rule("values must be strings, numbers or null")),
+        };
+
+        for modifier in [Modifier::Gt, Modifier::Gte, Modifier::Lt, Modifier::Lte] {
+            if modifiers.contains(&modifier) {
+                let number = value
+                    .parse()
+                    .map_err(|_| invalid_rule(format!("expected a number, found `{}`", value)))?;
+                return Ok(ValueMatcher::Compare(modifier, number));
+            }
+        }
+
+        let pattern = if modifiers.contains(&Modifier::Re) {
+            value
+        } else {
+            let mut pattern = wildcard_to_regex(&value);
+
+            if modifiers.contains(&Modifier::Windash) {
+                // Command line flags may start with either a dash or a slash.
+                pattern = pattern.replace(r"\-", "[-/]");
+            }
+
+            let prefix = if modifiers.contains(&Modifier::Contains)
+                || modifiers.contains(&Modifier::EndsWith)
+            {
+                ".*"
+            } else {
+                ""
+            };
+            let suffix = if modifiers.contains(&Modifier::Contains)
+                || modifiers.contains(&Modifier::StartsWith)
+            {
+                ".*"
+            } else {
+                ""
+            };
+
+            format!("^{}{}{}$", prefix, pattern, suffix)
+        };
+
+        let regex = RegexBuilder::new(&pattern)
+            // Regular expressions are case sensitive, other values are not (unless `cased` is used).
+            .case_insensitive(
+                !modifiers.contains(&Modifier::Re) && !modifiers.contains(&Modifier::Cased),
+            )
+            .dot_matches_new_line(true)
+            .build()
+            .map_err(|e| invalid_rule(e.to_string()))?;
+
+        Ok(ValueMatcher::Pattern(regex))
+    }
+
+    fn matches(&self, value: Option<&String>) -> bool {
+        match (self, value) {
+            (ValueMatcher::Null, value) => value.is_none_or(|v| v.is_empty()),
+            (ValueMatcher::Exists(exists), value) => value.is_some() == *exists,
+            (_, None) => false,
+            (ValueMatcher::Pattern(regex), Some(value)) => regex.is_match(value),
+            (ValueMatcher::Compare(modifier, expected), Some(value)) => {
+                match value.trim().parse::<f64>() {
+                    Ok(value) => match modifier {
+                        Modifier::Gt => value > *expected,
+                        Modifier::Gte => value >= *expected,
+                        Modifier::Lt => value < *expected,
+                        _ => value <= *expected,
+                    },
+                    Err(_) => false,
+                }
+            }
+        }
+    }
+}
+
+/// Converts a Sigma value to a regular expression.
+/// `*` and `?` are wildcards, unless they are escaped with a backslash.
+fn wildcard_to_regex(value: &str) -> String {
+    let mut pattern = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if matches!(chars.peek(), Some('*' | '?' | '\\')) => {
+                let escaped = chars.next().expect("peeked");
+                pattern.push_str(&regex::escape(&escaped.to_string()));
+            }
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    pattern
+}
+
+#[derive(Debug, Clone)]
+enum Condition {
+    Or(Box<Condition>, Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Not(Box<Condition>),
+    Selection(usize),
+    /// Any (`1 of`) or all (`all of`) of the given selections.
+    Of {
+        all: bool,
+        selections: Vec<usize>,
+    },
+}
+
+impl Condition {
+    fn parse(condition: &str, selection_names: &[&str]) -> Result<Condition> {
+        if condition.contains('|') {
+            return Err(invalid_rule("aggregations are not supported"));
+        }
+
+        let spaced = condition.replace('(', " ( ").replace(')', " ) ");
+        let tokens: Vec<&str> = spaced.split_whitespace().collect();
+        let mut parser = ConditionParser {
+            tokens,
+            index: 0,
+            selection_names,
+        };
+
+        let condition = parser.parse_or()?;
+
+        match parser.tokens.get(parser.index) {
+            None => Ok(condition),
+            Some(token) => Err(invalid_rule(format!("unexpected `{}` in condition", token))),
+        }
+    }
+
+    fn eval(&self, selections: &[(String, Selection)], fields: &SigmaFields) -> bool {
+        match self {
+            Condition::Or(left, right) => {
+                left.eval(selections, fields) || right.eval(selections, fields)
+            }
+            Condition::And(left, right) => {
+                left.eval(selections, fields) && right.eval(selections, fields)
+            }
+            Condition::Not(condition) => !condition.eval(selections, fields),
+            Condition::Selection(index) => selections[*index].1.matches(fields),
+            Condition::Of {
+                all: true,
+                selections: indices,
+            } => indices
+                .iter()
+                .all(|index| selections[*index].1.matches(fields)),
+            Condition::Of {
+                all: false,
+                selections: indices,
+            } => indices
+                .iter()
+                .any(|index| selections[*index].1.matches(fields)),
+        }
+    }
+}
+
+struct ConditionParser<'c> {
+    tokens: Vec<&'c str>,
+    index: usize,
+    selection_names: &'c [&'c str],
+}
+
+impl<'c> ConditionParser<'c> {
+    fn peek(&self) -> Option<&'c str> {
+        self.tokens.get(self.index).copied()
+    }
+
+    fn next(&mut self) -> Result<&'c str> {
+        let token = self
+            .peek()
+            .ok_or_else(|| invalid_rule("unexpected end of condition"))?;
+        self.index += 1;
+        Ok(token)
+    }
+
+    fn parse_or(&mut self) -> Result<Condition> {
+        let mut condition = self.parse_and()?;
+
+        while self.peek() == Some("or") {
+            self.index += 1;
+            condition = Condition::Or(Box::new(condition), Box::new(self.parse_and()?));
+        }
+
+        Ok(condition)
+    }
+
+    fn parse_and(&mut self) -> Result<Condition> {
+        let mut condition = self.parse_not()?;
+
+        while self.peek() == Some("and") {
+            self.index += 1;
+            condition = Condition::And(Box::new(condition), Box::new(self.parse_not()?));
+        }
+
+        Ok(condition)
+    }
+
+    fn parse_not(&mut self) -> Result<Condition> {
+        if self.peek() == Some("not") {
+            self.index += 1;
+            return Ok(Condition::Not(Box::new(self.parse_not()?)));
+        }
+
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Condition> {
+        let token = self.next()?;
+
+        match token {
+            "(" => {
+                let condition = self.parse_or()?;
+                match self.next()? {
+                    ")" => Ok(condition),
+                    token => Err(invalid_rule(format!("expected `)`, found `{}`", token))),
+                }
+            }
+            "1" | "any" | "all" if self.peek() == Some("of") => {
+                self.index += 1;
+                let pattern = self.next()?;
+                let selections: Vec<usize> = self
+                    .selection_names
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, name)| match pattern {
+                        // Selections starting with an underscore are not included in `them`.
+                        "them" => !name.starts_with('_'),
+                        pattern => matches_wildcard(pattern, name),
+                    })
+                    .map(|(index, _)| index)
+                    .collect();
+
+                if selections.is_empty() {
+                    return Err(invalid_rule(format!("no selection matches `{}`", pattern)));
+                }
+
+                Ok(Condition::Of {
+                    all: token == "all",
+                    selections,
+                })
+            }
+            name => self
+                .selection_names
+                .iter()
+                .position(|selection| *selection == name)
+                .map(Condition::Selection)
+                .ok_or_else(|| invalid_rule(format!("unknown selection `{}`", name))),
+        }
+    }
+}
+
+/// Matches selection names against patterns such as `selection*`.
+fn matches_wildcard(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => name.strip_prefix(prefix).is_some_and(|name| {
+            (0..=name.len())
+                .filter(|i| name.is_char_boundary(*i))
+                .any(|i| matches_wildcard(rest, &name[i..]))
+        }),
+    }
+}
+
+struct OpenElement {
+    /// The name of the field the text of the element is stored in.
+    field: String,
+    text: String,
+    has_text: bool,
+}
+
+/// Flattens the fields of a record while walking its tokens.
+struct FieldsCollector<'a> {
+    chunk: &'a EvtxChunk<'a>,
+    elements: Vec<OpenElement>,
+    start_element: Option<Cow<'a, BinXmlName>>,
+    attribute: Option<Cow<'a, BinXmlName>>,
+    attributes: Vec<(Cow<'a, BinXmlName>, String)>,
+    fields: SigmaFields,
+}
+
+impl<'a> FieldsCollector<'a> {
+    fn visit(&mut self, token: &BinXMLDeserializedTokens) -> Result<bool> {
+        match token {
+            BinXMLDeserializedTokens::OpenStartElement(elem) => {
+                self.start_element = Some(expand_string_ref(&elem.name, self.chunk)?);
+                self.attribute = None;
+                self.attributes.clear();
+            }
+            BinXMLDeserializedTokens::Attribute(attr) => {
+                self.attribute = Some(expand_string_ref(&attr.name, self.chunk)?);
+            }
+            BinXMLDeserializedTokens::Value(value) => match self.start_element {
+                Some(_) => {
+                    if let (Some(attribute), Some(value)) =
+                        (self.attribute.take(), value_as_string(value))
+                    {
+                        self.attributes.push((attribute, value));
+                    }
+                }
+                None => {
+                    if let (Some(element), Some(value)) =
+                        (self.elements.last_mut(), value_as_string(value))
+                    {
+                        element.text.push_str(&value);
+                        element.has_text = true;
+                    }
+                }
+            },
+            BinXMLDeserializedTokens::CloseStartElement => {
+                let element = self.close_start_element();
+                self.elements.push(element);
+            }
+            BinXMLDeserializedTokens::CloseEmptyElement => {
+                let element = self.close_start_element();
+                self.insert_text(element);
+            }
+            BinXMLDeserializedTokens::CloseElement => {
+                if let Some(element) = self.elements.pop() {
+                    self.insert_text(element);
+                }
+            }
+            _ => {}
+        }
+
+        Ok(true)
+    }
+
+    /// Stores the attributes of the element whose start tag was read.
+    fn close_start_element(&mut self) -> OpenElement {
+        let name = self
+            .start_element
+            .take()
+            .map(|name| name.as_str().to_string())
+            .unwrap_or_default();
+
+        let mut field = name.clone();
+        let is_named_data = name == "Data";
+
+        for (attribute, value) in self.attributes.drain(..) {
+            if is_named_data && attribute.as_str() == "Name" {
+                field = value;
+            } else {
+                self.fields
+                    .entry(format!("{}_{}", name, attribute.as_str()))
+                    .or_default()
+                    .push(value);
+            }
+        }
+
+        OpenElement {
+            // Named `Data` elements are always stored, even if they are empty.
+            has_text: is_named_data && field != name,
+            field,
+            text: String::new(),
+        }
+    }
+
+    fn insert_text(&mut self, element: OpenElement) {
+        if element.has_text {
+            self.fields
+                .entry(element.field)
+                .or_default()
+                .push(element.text);
+        }
+    }
+}
+
+fn value_as_string(value: &BinXmlValue) -> Option<String> {
+    match value {
+        BinXmlValue::NullType
+        | BinXmlValue::EvtHandle
+        | BinXmlValue::BinXmlType(_)
+        | BinXmlValue::BinXmlArrayType(_) => None,
+        _ => value.as_cow_str().ok().map(Cow::into_owned),
+    }
+}
+
+impl<'a> EvtxRecord<'a> {
+    /// Flattens the fields of the record, as used by Sigma rules (see `SigmaFields`).
+    pub fn sigma_fields(&self) -> Result<SigmaFields> {
+        let mut collector = FieldsCollector {
+            chunk: self.chunk,
+            elements: vec![],
+            start_element: None,
+            attribute: None,
+            attributes: vec![],
+            fields: SigmaFields::new(),
+        };
+
+        walk_expanded_tokens(&self.tokens, &[], self.chunk, &mut |token| {
+            collector.visit(token)
+        })?;
+
+        Ok(collector.fields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ensure_env_logger_initialized;
+    use crate::EvtxParser;
+
+    fn json_str(value: &serde_json::Value) -> String {
+        match value {
+            serde_json::Value::String(s) => s.clone(),
+            value => value.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_sigma_fields_of_record() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+        let mut parser = EvtxParser::from_buffer(evtx_file.to_vec()).unwrap();
+
+        let mut chunk = parser.chunks().next().unwrap().unwrap();
+        let mut chunk = chunk
+            .parse(std::sync::Arc::new(crate::ParserSettings::new()))
+            .unwrap();
+        let record = chunk.iter().nth(1).unwrap().unwrap();
+        let fields = record.sigma_fields().unwrap();
+
+        let field = |name: &str| fields.get(name).map(|values| values[0].as_str());
+
+        assert_eq!(field("EventID"), Some("4624"));
+        assert_eq!(field("Channel"), Some("Security"));
+        assert_eq!(
+            field("Provider_Name"),
+            Some("Microsoft-Windows-Security-Auditing")
+        );
+        assert_eq!(field("Execution_ProcessID"), Some("456"));
+        assert_eq!(field("TargetUserName"), Some("SYSTEM"));
+        assert_eq!(field("LogonType"), Some("0"));
+        // Empty `Data` elements are still present.
+        assert_eq!(field("ProcessName"), Some(""));
+        assert_eq!(field("Data"), None);
+    }
+
+    #[test]
+    fn test_rules_match_records() {
+        ensure_env_logger_initialized();
+        let rules = SigmaRuleSet::from_rules(vec![
+            SigmaRule::from_yaml(
+                r#"
+title: Ping Execution
+id: 1d3b7d9c-5b5e-4d55-9b3e-000000000001
+level: low
+logsource:
+    category: process_creation
+    product: windows
+detection:
+    selection:
+        Image|endswith: '\ping.exe'
+        CommandLine|contains|all:
+            - 'ping'
+            - ' -n '
+    filter:
+        User: null
+    condition: selection and not filter
+"#,
+            )
+            .unwrap(),
+            SigmaRule::from_yaml(
+                r#"
+title: System Logon
+level: informational
+logsource:
+    service: security
+detection:
+    selection_logon:
+        EventID: 4624
+    selection_user:
+        TargetUserName|re: '^SYS'
+    condition: all of selection_*
+"#,
+            )
+            .unwrap(),
+        ]);
+
+        let mut all_matches = vec![];
+        for file in [
+            &include_bytes!("../samples/sysmon.evtx")[..],
+            &include_bytes!("../samples/security.evtx")[..],
+        ] {
+            let mut parser = EvtxParser::from_buffer(file.to_vec()).unwrap();
+            let actual: Vec<_> = parser
+                .serialized_records(|record| {
+                    let record = record?;
+                    Ok((record.event_record_id, rules.evaluate(&record)?))
+                })
+                // Some samples end with empty chunks.
+                .filter_map(|result| result.ok())
+                .collect();
+
+            let mut parser = EvtxParser::from_buffer(file.to_vec()).unwrap();
+            for (record, (id, matches)) in parser
+                .records_json_value()
+                .filter_map(|record| record.ok())
+                .zip(actual)
+            {
+                assert_eq!(record.event_record_id, id);
+
+                let event = &record.data["Event"];
+                let system = &event["System"];
+                let data = &event["EventData"];
+
+                let is_ping = system["Channel"] == "Microsoft-Windows-Sysmon/Operational"
+                    && system["EventID"] == 1
+                    && json_str(&data["Image"])
+                        .to_lowercase()
+                        .ends_with("\\ping.exe")
+                    && json_str(&data["CommandLine"]).contains(" -n ")
+                    && !json_str(&data["User"]).is_empty()
+                    && !data["User"].is_null();
+                let is_logon = system["Channel"] == "Security"
+                    && system["EventID"] == 4624
+                    && json_str(&data["TargetUserName"]).starts_with("SYS");
+
+                let titles: Vec<&str> = matches.iter().map(|m| m.title.as_str()).collect();
+                let expected_titles: Vec<&str> =
+                    [(is_ping, "Ping Execution"), (is_logon, "System Logon")]
+                        .iter()
+                        .filter(|(matched, _)| *matched)
+                        .map(|(_, title)| *title)
+                        .collect();
+
+                assert_eq!(titles, expected_titles, "record {}", id);
+                all_matches.extend(matches);
+            }
+        }
+
+        assert!(all_matches.iter().any(|m| m.title == "Ping Execution"
+            && m.rule_id.as_deref() == Some("1d3b7d9c-5b5e-4d55-9b3e-000000000001")
+            && m.level.as_deref() == Some("low")));
+        assert!(all_matches.iter().any(|m| m.title == "System Logon"));
+    }
+
+    #[test]
+    fn test_conditions() {
+        let fields: SigmaFields = [
+            ("EventID", "4688"),
+            ("CommandLine", "cmd.exe /c whoami"),
+            ("Image", "C:\\Windows\\System32\\cmd.exe"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), vec![v.to_string()]))
+        .collect();
+
+        let rule = |detection: &str| {
+            SigmaRule::from_yaml(&format!("title: test\ndetection:\n{}", detection)).unwrap()
+        };
+
+        assert!(rule("  keywords:\n    - WHOAMI\n  condition: keywords").matches(&fields));
+        assert!(rule("  sel:\n    CommandLine|windash: '*-c*'\n  condition: sel").matches(&fields));
+        assert!(rule("  sel:\n    Image: '*\\cmd.exe'\n  condition: sel").matches(&fields));
+        assert!(!rule("  sel:\n    Image|cased: '*\\CMD.EXE'\n  condition: sel").matches(&fields));
+        assert!(rule("  sel:\n    EventID|gte: 4688\n  condition: sel").matches(&fields));
+        assert!(rule("  sel:\n    User|exists: false\n  condition: sel").matches(&fields));
+        assert!(rule(
+            "  sel_a:\n    EventID: 1\n  sel_b:\n    - EventID: 4688\n    - EventID: 1\n  condition: 1 of sel_*"
+        )
+        .matches(&fields));
+        assert!(!rule(
+            "  sel_a:\n    EventID: 1\n  sel_b:\n    EventID: 4688\n  condition: all of them"
+        )
+        .matches(&fields));
+        assert!(rule(
+            "  a:\n    EventID: 1\n  b:\n    EventID: 4688\n  condition: not (a or not b)"
+        )
+        .matches(&fields));
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        let rule = |detection: &str| {
+            SigmaRule::from_yaml(&format!("title: test\ndetection:\n{}", detection)).unwrap_err()
+        };
+
+        for (detection, message) in [
+            (
+                "  sel:\n    Image|base64: a\n  condition: sel",
+                "unsupported modifier `base64`",
+            ),
+            (
+                "  sel:\n    Image: a\n  condition: other",
+                "unknown selection `other`",
+            ),
+            (
+                "  sel:\n    Image: a\n  condition: sel | count() > 5",
+                "aggregations",
+            ),
+            (
+                "  sel:\n    Image: a\n  condition: (sel",
+                "unexpected end of condition",
+            ),
+            ("  sel:\n    Image: a", "missing condition"),
+        ] {
+            let err = rule(detection);
+            assert!(
+                matches!(err, EvtxError::InvalidSigmaRule { .. }),
+                "{:?}",
+                err
+            );
+            assert!(err.to_string().contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_load_dir_skips_invalid_rules() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::write(
+            dir.join("nested").join("valid.yml"),
+            "title: valid\ndetection:\n  sel:\n    EventID: 1\n  condition: sel\n",
+        )
+        .unwrap();
+        fs::write(dir.join("invalid.yaml"), "title: invalid\n").unwrap();
+        fs::write(dir.join("README.md"), "not a rule").unwrap();
+
+        let rules = SigmaRuleSet::load_dir(dir).unwrap();
+
+        assert_eq!(rules.rules().len(), 1);
+        assert_eq!(rules.rules()[0].title, "valid");
+        assert_eq!(rules.skipped_rules().len(), 1);
+        assert!(rules.skipped_rules()[0].0.ends_with("invalid.yaml"));
+    }
+}
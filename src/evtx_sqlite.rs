@@ -0,0 +1,288 @@
+use crate::err::Result;
+use crate::evtx_parser::ReadSeek;
+use crate::evtx_record::RecordId;
+use crate::{EvtxParser, EvtxRecord};
+
+use chrono::{DateTime, Utc};
+use log::warn;
+use rusqlite::{params, Connection};
+use std::path::Path;
+use std::time::Duration;
+
+/// The tables (and indexes) written by `SqliteWriter`, created when they do not exist yet.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS records (
+    source_file TEXT NOT NULL,
+    record_id INTEGER NOT NULL,
+    timestamp TEXT NOT NULL,
+    event_id INTEGER,
+    provider TEXT,
+    channel TEXT,
+    computer TEXT,
+    xml TEXT NOT NULL,
+    json TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS event_data (
+    source_file TEXT NOT NULL,
+    record_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    value TEXT
+);
+CREATE INDEX IF NOT EXISTS records_source_file_record_id ON records (source_file, record_id);
+CREATE INDEX IF NOT EXISTS records_timestamp ON records (timestamp);
+CREATE INDEX IF NOT EXISTS records_event_id ON records (event_id);
+CREATE INDEX IF NOT EXISTS event_data_source_file_record_id ON event_data (source_file, record_id);
+CREATE INDEX IF NOT EXISTS event_data_name_value ON event_data (name, value);
+";
+
+/// Rows are committed in transactions of (at most) this many records.
+const RECORDS_PER_TRANSACTION: usize = 10_000;
+
+/// How long to wait for another connection (such as another `evtx_dump`) to release the database.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The values of a record, as stored in the database.
+#[derive(Debug, Clone)]
+struct SqliteRow {
+    event_record_id: RecordId,
+    timestamp: DateTime<Utc>,
+    event_id: Option<u16>,
+    provider: Option<String>,
+    channel: Option<String>,
+    computer: Option<String>,
+    xml: String,
+    json: String,
+    event_data: Vec<(String, Option<String>)>,
+}
+
+impl SqliteRow {
+    fn from_record(record: EvtxRecord) -> Result<Self> {
+        let event_record_id = record.event_record_id;
+        let timestamp = record.timestamp;
+        let system = record.system()?;
+        let event_data = record.event_data()?;
+        let xml = record.clone().into_xml()?.data;
+        let json = record.into_json()?.data;
+
+        Ok(SqliteRow {
+            event_record_id,
+            timestamp,
+            event_id: system.event_id,
+            provider: system.provider_name,
+            channel: system.channel,
+            computer: system.computer,
+            xml,
+            json,
+            event_data,
+        })
+    }
+}
+
+/// Writes records into a SQLite database.
+///
+/// Every record is a row of the `records` table, which has the file it was read from (`source_file`),
+/// its record id, timestamp, event id, provider, channel and computer, along with the record serialized
+/// as XML and as JSON (using the settings of the parser).
+/// The fields of `EventData` (or `UserData`) are rows of the `event_data` table (`source_file`, `record_id`,
+/// `name` and `value`, see `EvtxRecord::event_data`).
+///
+/// Records of several files can be written to the same database, which may already exist.
+///
+/// ```rust
+/// # use evtx::{EvtxParser, SqliteWriter};
+/// # let fp = std::path::PathBuf::from(format!("{}/samples/security.evtx", std::env::var("CARGO_MANIFEST_DIR").unwrap()));
+/// # let db = std::env::temp_dir().join("evtx_sqlite_doctest.db");
+/// let mut writer = SqliteWriter::open(&db).unwrap();
+/// let mut parser = EvtxParser::from_path(&fp).unwrap();
+///
+/// for record_id in writer.insert_records("security.evtx", &mut parser).unwrap() {
+///     if let Err(err) = record_id {
+///         eprintln!("{}", err);
+///     }
+/// }
+/// writer.flush().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct SqliteWriter {
+    connection: Connection,
+    /// The number of records inserted in the current transaction.
+    pending: usize,
+}
+
+impl SqliteWriter {
+    /// Opens (or creates) the database at `path`, creating the tables if needed.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    fn from_connection(connection: Connection) -> Result<Self> {
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+        connection.execute_batch(SCHEMA)?;
+
+        Ok(SqliteWriter {
+            connection,
+            pending: 0,
+        })
+    }
+
+    /// Inserts all the records of `parser` (like `EvtxParser::records` would return them),
+    /// replacing the records previously inserted from `source_file`.
+    ///
+    /// The returned iterator yields the id of every inserted record, or the error which prevented
+    /// a record from being read or inserted.
+    /// Records are committed in batches, call `flush` once done to commit the last ones.
+    pub fn insert_records<'a, T: ReadSeek>(
+        &'a mut self,
+        source_file: &'a str,
+        parser: &'a mut EvtxParser<T>,
+    ) -> Result<impl Iterator<Item = Result<RecordId>> + 'a> {
+        self.begin()?;
+        self.connection.execute(
+            "DELETE FROM records WHERE source_file = ?1",
+            params![source_file],
+        )?;
+        self.connection.execute(
+            "DELETE FROM event_data WHERE source_file = ?1",
+            params![source_file],
+        )?;
+
+        Ok(parser
+            .serialized_records(|record| SqliteRow::from_record(record?))
+            .map(move |row| {
+                let row = row?;
+                self.insert(source_file, &row)?;
+
+                Ok(row.event_record_id)
+            }))
+    }
+
+    fn insert(&mut self, source_file: &str, row: &SqliteRow) -> Result<()> {
+        self.begin()?;
+
+        self.connection
+            .prepare_cached(
+                "INSERT INTO records \
+                 (source_file, record_id, timestamp, event_id, provider, channel, computer, xml, json) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            )?
+            .execute(params![
+                source_file,
+                row.event_record_id,
+                row.timestamp.format("%Y-%m-%dT%H:%M:%S%.6fZ").to_string(),
+                row.event_id,
+                row.provider,
+                row.channel,
+                row.computer,
+                row.xml,
+                row.json,
+            ])?;
+
+        let mut statement = self.connection.prepare_cached(
+            "INSERT INTO event_data (source_file, record_id, name, value) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for (name, value) in &row.event_data {
+            statement.execute(params![source_file, row.event_record_id, name, value])?;
+        }
+        drop(statement);
+
+        self.pending += 1;
+        if self.pending >= RECORDS_PER_TRANSACTION {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn begin(&mut self) -> Result<()> {
+        if self.connection.is_autocommit() {
+            self.connection.execute_batch("BEGIN")?;
+        }
+
+        Ok(())
+    }
+
+    /// Commits the records inserted so far.
+    pub fn flush(&mut self) -> Result<()> {
+        if !self.connection.is_autocommit() {
+            self.connection.execute_batch("COMMIT")?;
+        }
+        self.pending = 0;
+
+        Ok(())
+    }
+}
+
+impl Drop for SqliteWriter {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush() {
+            warn!("Failed to commit records to the database: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ensure_env_logger_initialized;
+
+    #[test]
+    fn test_inserts_records_and_event_data() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+        let mut writer =
+            SqliteWriter::from_connection(Connection::open_in_memory().unwrap()).unwrap();
+
+        // Inserting the same file twice replaces its records, while other files are kept.
+        for source_file in ["a.evtx", "b.evtx", "a.evtx"] {
+            let mut parser = EvtxParser::from_buffer(evtx_file.to_vec()).unwrap();
+            let inserted = writer
+                .insert_records(source_file, &mut parser)
+                .unwrap()
+                .filter(|record_id| record_id.is_ok())
+                .count();
+            assert!(inserted > 0);
+        }
+        writer.flush().unwrap();
+
+        let mut parser = EvtxParser::from_buffer(evtx_file.to_vec()).unwrap();
+        let record_count = parser.records().filter(|record| record.is_ok()).count();
+
+        let count_records = |source_file: &str| -> usize {
+            writer
+                .connection
+                .query_row(
+                    "SELECT COUNT(*) FROM records WHERE source_file = ?1",
+                    params![source_file],
+                    |row| row.get(0),
+                )
+                .unwrap()
+        };
+        assert_eq!(count_records("a.evtx"), record_count);
+        assert_eq!(count_records("b.evtx"), record_count);
+
+        let (timestamp, event_id, channel, xml): (String, u16, String, String) = writer
+            .connection
+            .query_row(
+                "SELECT timestamp, event_id, channel, xml FROM records \
+                 WHERE source_file = 'a.evtx' AND record_id = 2",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(timestamp, "2016-07-08T18:12:51.681640Z");
+        assert_eq!(event_id, 4624);
+        assert_eq!(channel, "Security");
+        assert!(xml.contains("<EventRecordID>2</EventRecordID>"));
+
+        let target_user: String = writer
+            .connection
+            .query_row(
+                "SELECT value FROM event_data \
+                 WHERE source_file = 'a.evtx' AND record_id = 2 AND name = 'TargetUserName'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(target_user, "SYSTEM");
+    }
+}
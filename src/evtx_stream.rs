@@ -0,0 +1,315 @@
+use crate::err::{ChunkError, EvtxError, Result};
+
+use crate::evtx_chunk::EvtxChunkData;
+use crate::evtx_file_header::EvtxFileHeader;
+use crate::evtx_parser::{parse_chunks_in_batches, EVTX_CHUNK_SIZE, EVTX_FILE_HEADER_SIZE};
+use crate::evtx_record::SerializedEvtxRecord;
+use crate::{EvtxRecord, ParserSettings};
+
+use log::{debug, trace, warn};
+use std::cmp::max;
+use std::fmt;
+use std::fmt::Debug;
+use std::io::{Cursor, Read};
+use std::sync::Arc;
+
+/// Parses an EVTX file from a stream which can only be read forward, such as a pipe,
+/// a decompression stream or an entry of an archive.
+///
+/// The file header is read first, and then chunks are consumed sequentially, yielding the same
+/// records as `EvtxParser` (in file order, `ParserSettings::record_order` is ignored).
+///
+/// Example usage (reading a gzip compressed file would only require wrapping the file in a decoder):
+///
+/// ```rust
+/// # use evtx::EvtxStreamParser;
+/// # use std::fs::File;
+/// # let fp = std::path::PathBuf::from(format!("{}/samples/security.evtx", std::env::var("CARGO_MANIFEST_DIR").unwrap()));
+/// let file = File::open(fp).unwrap();
+///
+/// let mut parser = EvtxStreamParser::from_read(file).unwrap();
+///
+/// for record in parser.records() {
+///     match record {
+///         Ok(r) => println!("Record {}\n{}", r.event_record_id, r.data),
+///         Err(e) => eprintln!("{}", e),
+///     }
+/// }
+/// ```
+pub struct EvtxStreamParser<R: Read> {
+    data: R,
+    header: EvtxFileHeader,
+    config: Arc<ParserSettings>,
+    /// The number of the next chunk to be read from `data`.
+    next_chunk_number: u64,
+    /// Set once the end of the stream was reached (or reading from it failed).
+    exhausted: bool,
+}
+
+impl<R: Read> Debug for EvtxStreamParser<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> ::std::fmt::Result {
+        f.debug_struct("EvtxStreamParser")
+            .field("header", &self.header)
+            .field("config", &self.config)
+            .field("next_chunk_number", &self.next_chunk_number)
+            .finish()
+    }
+}
+
+impl<R: Read> EvtxStreamParser<R> {
+    /// Reads the file header from `read`, will fail if the evtx header is invalid.
+    pub fn from_read(mut read: R) -> Result<Self> {
+        let mut header_data = Vec::with_capacity(EVTX_FILE_HEADER_SIZE);
+        (&mut read)
+            .take(EVTX_FILE_HEADER_SIZE as u64)
+            .read_to_end(&mut header_data)?;
+
+        let header = EvtxFileHeader::from_stream(&mut Cursor::new(header_data))?;
+
+        debug!("EVTX Header: {:#?}", header);
+        Ok(EvtxStreamParser {
+            data: read,
+            header,
+            config: Arc::new(ParserSettings::default()),
+            next_chunk_number: 0,
+            exhausted: false,
+        })
+    }
+
+    pub fn with_configuration(mut self, configuration: ParserSettings) -> Self {
+        self.config = Arc::new(configuration);
+        self
+    }
+
+    /// Reads the next chunk which is not empty from the stream, along with its number.
+    ///
+    /// Like `EvtxParser`, a trailing chunk which is cut short marks the end of the data.
+    fn next_chunk(&mut self) -> Option<(u64, Result<EvtxChunkData>)> {
+        while !self.exhausted {
+            let chunk_number = self.next_chunk_number;
+            self.next_chunk_number += 1;
+
+            trace!("Reading chunk number `{}` from stream", chunk_number);
+
+            let mut chunk_data = Vec::with_capacity(EVTX_CHUNK_SIZE);
+            let amount_read = match (&mut self.data)
+                .take(EVTX_CHUNK_SIZE as u64)
+                .read_to_end(&mut chunk_data)
+            {
+                Ok(amount_read) => amount_read,
+                Err(e) => {
+                    self.exhausted = true;
+                    return Some((
+                        chunk_number,
+                        Err(EvtxError::FailedToParseChunk {
+                            chunk_id: chunk_number,
+                            source: ChunkError::FailedToReadChunk(e),
+                        }),
+                    ));
+                }
+            };
+
+            if amount_read != EVTX_CHUNK_SIZE {
+                if amount_read != 0 {
+                    warn!(
+                        "Stream ended in the middle of chunk number `{}` ({} bytes)",
+                        chunk_number, amount_read
+                    );
+                }
+
+                self.exhausted = true;
+                return None;
+            }
+
+            // There might be empty chunks in the middle of a dirty file.
+            if chunk_data.iter().all(|x| *x == 0) {
+                continue;
+            }
+
+            let chunk = EvtxChunkData::new(chunk_data, self.config.should_validate_checksums())
+                .map_err(|e| EvtxError::FailedToParseChunk {
+                    chunk_id: chunk_number,
+                    source: e,
+                });
+
+            return Some((chunk_number, chunk));
+        }
+
+        None
+    }
+
+    /// Return an iterator over all the chunks in the stream.
+    /// Each chunk supports iterating over it's records in their un-serialized state
+    /// (before they are converted to XML or JSON).
+    pub fn chunks(&mut self) -> impl Iterator<Item = Result<EvtxChunkData>> + '_ {
+        std::iter::from_fn(move || self.next_chunk().map(|(_, chunk)| chunk))
+    }
+
+    /// Return an iterator over all the records.
+    /// Records will be mapped `f`, which must produce owned data from the records.
+    ///
+    /// Only the chunks which are being parsed are kept in memory (as many as the configured number of threads).
+    /// Records rejected by `ParserSettings::record_filter` are skipped before `f` is called.
+    pub fn serialized_records<'a, U: Send + 'a>(
+        &'a mut self,
+        f: impl FnMut(Result<EvtxRecord<'_>>) -> Result<U> + Send + Sync + Clone + 'a,
+    ) -> impl Iterator<Item = Result<U>> + 'a {
+        let num_threads = max(*self.config.get_num_threads(), 1);
+        let chunk_settings = Arc::clone(&self.config);
+
+        // Read some chunks in advance, so they can be parsed in parallel.
+        let batches = parse_chunks_in_batches(
+            std::iter::from_fn(move || self.next_chunk()),
+            num_threads,
+            Arc::clone(&chunk_settings),
+            Err,
+            move |_, mut chunk_records| {
+                chunk_records
+                    .iter()
+                    .filter(|record| chunk_settings.should_serialize(record))
+                    .map(f.clone())
+                    .collect()
+            },
+        );
+
+        batches.flatten().flat_map(|(_, records)| records)
+    }
+
+    /// Return an iterator over all the records.
+    /// Records will be XML-formatted.
+    pub fn records(&mut self) -> impl Iterator<Item = Result<SerializedEvtxRecord<String>>> + '_ {
+        self.serialized_records(|record| record.and_then(|record| record.into_xml()))
+    }
+
+    /// Return an iterator over all the records.
+    /// Records will be JSON-formatted.
+    pub fn records_json(
+        &mut self,
+    ) -> impl Iterator<Item = Result<SerializedEvtxRecord<String>>> + '_ {
+        self.serialized_records(|record| record.and_then(|record| record.into_json()))
+    }
+
+    /// Return an iterator over all the records.
+    /// Records will have a `serde_json::Value` data attribute.
+    pub fn records_json_value(
+        &mut self,
+    ) -> impl Iterator<Item = Result<SerializedEvtxRecord<serde_json::Value>>> + '_ {
+        self.serialized_records(|record| record.and_then(|record| record.into_json_value()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ensure_env_logger_initialized;
+    use crate::EvtxParser;
+    use std::io;
+
+    /// A reader which returns at most `max_read` bytes at a time, like a pipe.
+    struct ShortReads<R: Read> {
+        inner: R,
+        max_read: usize,
+    }
+
+    impl<R: Read> Read for ShortReads<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(self.max_read);
+            self.inner.read(&mut buf[..len])
+        }
+    }
+
+    /// A reader which fails after `fail_after` bytes.
+    struct FailingReader<R: Read> {
+        inner: R,
+        fail_after: usize,
+    }
+
+    impl<R: Read> Read for FailingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.fail_after == 0 {
+                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "broken pipe"));
+            }
+
+            let len = buf.len().min(self.fail_after);
+            let amount_read = self.inner.read(&mut buf[..len])?;
+            self.fail_after -= amount_read;
+
+            Ok(amount_read)
+        }
+    }
+
+    fn expected_records(evtx_file: &[u8]) -> Vec<SerializedEvtxRecord<String>> {
+        EvtxParser::from_buffer(evtx_file.to_vec())
+            .unwrap()
+            .records_json()
+            .map(|r| r.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_stream_yields_the_same_records() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+        let expected = expected_records(evtx_file);
+
+        for num_threads in [1, 4] {
+            let reader = ShortReads {
+                inner: &evtx_file[..],
+                max_read: 1000,
+            };
+            let mut parser = EvtxStreamParser::from_read(reader)
+                .unwrap()
+                .with_configuration(ParserSettings::new().num_threads(num_threads));
+
+            let actual: Vec<_> = parser.records_json().map(|r| r.unwrap()).collect();
+
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_stream_skips_empty_chunks_and_stops_at_truncated_chunk() {
+        ensure_env_logger_initialized();
+        let mut evtx_file = include_bytes!("../samples/security.evtx").to_vec();
+        // Zero the second chunk, and cut the last chunk short.
+        evtx_file
+            [EVTX_FILE_HEADER_SIZE + EVTX_CHUNK_SIZE..EVTX_FILE_HEADER_SIZE + 2 * EVTX_CHUNK_SIZE]
+            .fill(0);
+        evtx_file.truncate(evtx_file.len() - 100);
+        let expected = expected_records(&evtx_file);
+
+        let mut parser = EvtxStreamParser::from_read(evtx_file.as_slice()).unwrap();
+        let actual: Vec<_> = parser.records_json().map(|r| r.unwrap()).collect();
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_stream_reports_read_errors() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+        let reader = FailingReader {
+            inner: &evtx_file[..],
+            fail_after: EVTX_FILE_HEADER_SIZE + EVTX_CHUNK_SIZE + 10,
+        };
+
+        let mut parser = EvtxStreamParser::from_read(reader).unwrap();
+        let records: Vec<_> = parser.records().collect();
+
+        let last = records.last().unwrap();
+        assert!(matches!(
+            last,
+            Err(EvtxError::FailedToParseChunk {
+                chunk_id: 1,
+                source: ChunkError::FailedToReadChunk(_)
+            })
+        ));
+        assert!(records[..records.len() - 1].iter().all(|r| r.is_ok()));
+    }
+
+    #[test]
+    fn test_stream_rejects_invalid_header() {
+        ensure_env_logger_initialized();
+        assert!(EvtxStreamParser::from_read(&b"not an evtx file"[..]).is_err());
+    }
+}
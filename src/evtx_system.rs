@@ -0,0 +1,638 @@
+use crate::binxml::assemble::{expand_string_ref, walk_expanded_tokens};
+use crate::binxml::name::BinXmlName;
+use crate::binxml::value_variant::BinXmlValue;
+use crate::err::Result;
+use crate::evtx_record::RecordId;
+use crate::model::deserialized::BinXMLDeserializedTokens;
+use crate::model::xml::char_from_reference;
+use crate::{EvtxChunk, EvtxRecord};
+
+use chrono::{DateTime, Utc};
+use std::borrow::Cow;
+
+/// The fields of the `System` element of a record.
+///
+/// A field is `None` when it is missing from the record, or when its value cannot be converted
+/// to the type of the field.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SystemFields {
+    pub provider_name: Option<String>,
+    pub provider_guid: Option<String>,
+    pub event_id: Option<u16>,
+    pub version: Option<u8>,
+    pub level: Option<u8>,
+    pub task: Option<u16>,
+    pub opcode: Option<u8>,
+    pub keywords: Option<u64>,
+    pub time_created: Option<DateTime<Utc>>,
+    pub event_record_id: Option<RecordId>,
+    pub process_id: Option<u32>,
+    pub thread_id: Option<u32>,
+    pub channel: Option<String>,
+    pub computer: Option<String>,
+    pub user_sid: Option<String>,
+}
+
+/// A single field of `SystemFields`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SystemField {
+    ProviderName,
+    ProviderGuid,
+    EventId,
+    Version,
+    Level,
+    Task,
+    Opcode,
+    Keywords,
+    TimeCreated,
+    EventRecordId,
+    ProcessId,
+    ThreadId,
+    Channel,
+    Computer,
+    UserSid,
+}
+
+impl SystemField {
+    /// The field stored in an attribute of a child element of `System`.
+    fn from_attribute(element: &str, attribute: &str) -> Option<SystemField> {
+        match (element, attribute) {
+            ("Provider", "Name") => Some(SystemField::ProviderName),
+            ("Provider", "Guid") => Some(SystemField::ProviderGuid),
+            ("TimeCreated", "SystemTime") => Some(SystemField::TimeCreated),
+            ("Execution", "ProcessID") => Some(SystemField::ProcessId),
+            ("Execution", "ThreadID") => Some(SystemField::ThreadId),
+            ("Security", "UserID") => Some(SystemField::UserSid),
+            _ => None,
+        }
+    }
+
+    /// The field stored in the text of a child element of `System`.
+    fn from_text(element: &str) -> Option<SystemField> {
+        match element {
+            "EventID" => Some(SystemField::EventId),
+            "Version" => Some(SystemField::Version),
+            "Level" => Some(SystemField::Level),
+            "Task" => Some(SystemField::Task),
+            "Opcode" => Some(SystemField::Opcode),
+            "Keywords" => Some(SystemField::Keywords),
+            "EventRecordID" => Some(SystemField::EventRecordId),
+            "Channel" => Some(SystemField::Channel),
+            "Computer" => Some(SystemField::Computer),
+            _ => None,
+        }
+    }
+}
+
+/// Collects the `System` fields while walking the tokens of a record.
+struct SystemFieldsCollector<'a> {
+    chunk: &'a EvtxChunk<'a>,
+    /// When set, only this field is collected, and the walk stops once it is found.
+    only: Option<SystemField>,
+    /// The names of the open elements.
+    elements: Vec<Cow<'a, BinXmlName>>,
+    /// The element whose start tag is being read.
+    start_element: Option<Cow<'a, BinXmlName>>,
+    /// The attribute whose value is being read.
+    attribute: Option<Cow<'a, BinXmlName>>,
+    fields: SystemFields,
+}
+
+impl<'a> SystemFieldsCollector<'a> {
+    fn new(chunk: &'a EvtxChunk<'a>, only: Option<SystemField>) -> Self {
+        SystemFieldsCollector {
+            chunk,
+            only,
+            elements: vec![],
+            start_element: None,
+            attribute: None,
+            fields: SystemFields::default(),
+        }
+    }
+
+    fn is_in_system(&self) -> bool {
+        matches!(
+            self.elements.as_slice(),
+            [event, system, ..] if event.as_str() == "Event" && system.as_str() == "System"
+        )
+    }
+
+    /// Returns false once the `System` element (or the only field which is collected) was read.
+    fn visit(&mut self, token: &BinXMLDeserializedTokens) -> Result<bool> {
+        match token {
+            BinXMLDeserializedTokens::OpenStartElement(elem) => {
+                self.start_element = Some(expand_string_ref(&elem.name, self.chunk)?);
+                self.attribute = None;
+            }
+            BinXMLDeserializedTokens::Attribute(attr) => {
+                self.attribute = Some(expand_string_ref(&attr.name, self.chunk)?);
+            }
+            BinXMLDeserializedTokens::Value(value) => match &self.start_element {
+                Some(element) => {
+                    if let Some(attribute) = self.attribute.take() {
+                        if self.elements.len() == 2 && self.is_in_system() {
+                            let field =
+                                SystemField::from_attribute(element.as_str(), attribute.as_str());
+                            return Ok(self.set(field, value));
+                        }
+                    }
+                }
+                None => {
+                    if self.elements.len() == 3 && self.is_in_system() {
+                        let field = SystemField::from_text(self.elements[2].as_str());
+                        return Ok(self.set(field, value));
+                    }
+                }
+            },
+            BinXMLDeserializedTokens::CloseStartElement => {
+                if let Some(element) = self.start_element.take() {
+                    self.elements.push(element);
+                }
+                self.attribute = None;
+            }
+            BinXMLDeserializedTokens::CloseEmptyElement => {
+                self.start_element = None;
+                self.attribute = None;
+            }
+            BinXMLDeserializedTokens::CloseElement => {
+                let was_in_system = self.elements.len() == 2 && self.is_in_system();
+                self.elements.pop();
+
+                if was_in_system {
+                    return Ok(false);
+                }
+            }
+            _ => {}
+        }
+
+        Ok(true)
+    }
+
+    /// Sets `field` from `value`, unless another field is collected.
+    /// Returns false once the only field which is collected was set.
+    fn set(&mut self, field: Option<SystemField>, value: &BinXmlValue) -> bool {
+        let field = match field {
+            Some(field) if self.only.is_none_or(|only| only == field) => field,
+            _ => return true,
+        };
+
+        let fields = &mut self.fields;
+
+        match field {
+            SystemField::ProviderName => fields.provider_name = value_as_string(value),
+            SystemField::ProviderGuid => fields.provider_guid = value_as_string(value),
+            SystemField::EventId => fields.event_id = value_as_number(value),
+            SystemField::Version => fields.version = value_as_number(value),
+            SystemField::Level => fields.level = value_as_number(value),
+            SystemField::Task => fields.task = value_as_number(value),
+            SystemField::Opcode => fields.opcode = value_as_number(value),
+            SystemField::Keywords => fields.keywords = value_as_number(value),
+            SystemField::TimeCreated => fields.time_created = value_as_datetime(value),
+            SystemField::EventRecordId => fields.event_record_id = value_as_number(value),
+            SystemField::ProcessId => fields.process_id = value_as_number(value),
+            SystemField::ThreadId => fields.thread_id = value_as_number(value),
+            SystemField::Channel => fields.channel = value_as_string(value),
+            SystemField::Computer => fields.computer = value_as_string(value),
+            SystemField::UserSid => fields.user_sid = value_as_string(value),
+        }
+
+        self.only.is_none()
+    }
+}
+
+fn value_as_string(value: &BinXmlValue) -> Option<String> {
+    match value {
+        BinXmlValue::NullType
+        | BinXmlValue::EvtHandle
+        | BinXmlValue::BinXmlType(_)
+        | BinXmlValue::BinXmlArrayType(_) => None,
+        _ => value.as_cow_str().ok().map(Cow::into_owned),
+    }
+}
+
+fn value_as_number<N: TryFrom<u64>>(value: &BinXmlValue) -> Option<N> {
+    let number = match value {
+        BinXmlValue::UInt8Type(n) => u64::from(*n),
+        BinXmlValue::UInt16Type(n) => u64::from(*n),
+        BinXmlValue::UInt32Type(n) => u64::from(*n),
+        BinXmlValue::UInt64Type(n) => *n,
+        BinXmlValue::Int8Type(n) => u64::try_from(*n).ok()?,
+        BinXmlValue::Int16Type(n) => u64::try_from(*n).ok()?,
+        BinXmlValue::Int32Type(n) => u64::try_from(*n).ok()?,
+        BinXmlValue::Int64Type(n) => u64::try_from(*n).ok()?,
+        BinXmlValue::HexInt32Type(s) | BinXmlValue::HexInt64Type(s) => parse_number(s)?,
+        BinXmlValue::StringType(s) => parse_number(s)?,
+        BinXmlValue::AnsiStringType(s) => parse_number(s)?,
+        _ => return None,
+    };
+
+    N::try_from(number).ok()
+}
+
+/// Parses a decimal number, or an hexadecimal number prefixed by `0x`.
+pub(crate) fn parse_number(s: &str) -> Option<u64> {
+    let s = s.trim();
+
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+fn value_as_datetime(value: &BinXmlValue) -> Option<DateTime<Utc>> {
+    match value {
+        BinXmlValue::FileTimeType(tm) | BinXmlValue::SysTimeType(tm) => Some(*tm),
+        BinXmlValue::StringType(s) => DateTime::parse_from_rfc3339(s)
+            .ok()
+            .map(|tm| tm.with_timezone(&Utc)),
+        _ => None,
+    }
+}
+
+impl<'a> EvtxRecord<'a> {
+    /// Reads the fields of the `System` element of the record.
+    ///
+    /// This walks the tokens of the record directly (expanding templates on the fly),
+    /// which is much cheaper than serializing the record to XML or JSON.
+    /// The record is not consumed, so it can still be serialized afterwards.
+    ///
+    /// The fields are not cached, every call walks the record again.
+    /// The accessors below (`event_id`, `provider_name`, ...) only read their own field,
+    /// and stop as soon as it is found. When more than one field is needed,
+    /// call `system` once and reuse its result.
+    pub fn system(&self) -> Result<SystemFields> {
+        self.collect_system_fields(None)
+    }
+
+    fn collect_system_fields(&self, only: Option<SystemField>) -> Result<SystemFields> {
+        let mut collector = SystemFieldsCollector::new(self.chunk, only);
+
+        walk_expanded_tokens(&self.tokens, &[], self.chunk, &mut |token| {
+            collector.visit(token)
+        })?;
+
+        Ok(collector.fields)
+    }
+
+    /// The `EventID` of the record.
+    pub fn event_id(&self) -> Result<Option<u16>> {
+        Ok(self
+            .collect_system_fields(Some(SystemField::EventId))?
+            .event_id)
+    }
+
+    /// The `Name` attribute of the `Provider` element.
+    pub fn provider_name(&self) -> Result<Option<String>> {
+        Ok(self
+            .collect_system_fields(Some(SystemField::ProviderName))?
+            .provider_name)
+    }
+
+    /// The `Guid` attribute of the `Provider` element.
+    pub fn provider_guid(&self) -> Result<Option<String>> {
+        Ok(self
+            .collect_system_fields(Some(SystemField::ProviderGuid))?
+            .provider_guid)
+    }
+
+    pub fn channel(&self) -> Result<Option<String>> {
+        Ok(self
+            .collect_system_fields(Some(SystemField::Channel))?
+            .channel)
+    }
+
+    pub fn computer(&self) -> Result<Option<String>> {
+        Ok(self
+            .collect_system_fields(Some(SystemField::Computer))?
+            .computer)
+    }
+
+    pub fn level(&self) -> Result<Option<u8>> {
+        Ok(self.collect_system_fields(Some(SystemField::Level))?.level)
+    }
+
+    pub fn keywords(&self) -> Result<Option<u64>> {
+        Ok(self
+            .collect_system_fields(Some(SystemField::Keywords))?
+            .keywords)
+    }
+
+    /// The `ProcessID` attribute of the `Execution` element.
+    pub fn process_id(&self) -> Result<Option<u32>> {
+        Ok(self
+            .collect_system_fields(Some(SystemField::ProcessId))?
+            .process_id)
+    }
+
+    /// The `ThreadID` attribute of the `Execution` element.
+    pub fn thread_id(&self) -> Result<Option<u32>> {
+        Ok(self
+            .collect_system_fields(Some(SystemField::ThreadId))?
+            .thread_id)
+    }
+
+    /// The `UserID` attribute of the `Security` element.
+    pub fn user_sid(&self) -> Result<Option<String>> {
+        Ok(self
+            .collect_system_fields(Some(SystemField::UserSid))?
+            .user_sid)
+    }
+
+    /// The `SystemTime` attribute of the `TimeCreated` element.
+    /// Note that this may differ from `timestamp`, which is read from the record header.
+    pub fn time_created(&self) -> Result<Option<DateTime<Utc>>> {
+        Ok(self
+            .collect_system_fields(Some(SystemField::TimeCreated))?
+            .time_created)
+    }
+}
+
+/// An element which is open while collecting `EventData` fields.
+struct OpenField {
+    /// The name of the field the element is stored in, `None` if it is not a field.
+    name: Option<String>,
+    /// Set for `Data` elements with a `Name` attribute, which are fields even if they are empty.
+    is_named_data: bool,
+    text: Option<String>,
+}
+
+/// Collects the `EventData` (or `UserData`) fields while walking the tokens of a record.
+struct EventDataCollector<'a> {
+    chunk: &'a EvtxChunk<'a>,
+    elements: Vec<OpenField>,
+    /// The depth of the `EventData` or `UserData` element, if it is open.
+    data_depth: Option<usize>,
+    start_element: Option<Cow<'a, BinXmlName>>,
+    attribute: Option<Cow<'a, BinXmlName>>,
+    /// The value of the `Name` attribute of a `Data` element.
+    data_name: Option<String>,
+    fields: Vec<(String, Option<String>)>,
+}
+
+impl<'a> EventDataCollector<'a> {
+    fn visit(&mut self, token: &BinXMLDeserializedTokens) -> Result<bool> {
+        match token {
+            BinXMLDeserializedTokens::OpenStartElement(elem) => {
+                self.start_element = Some(expand_string_ref(&elem.name, self.chunk)?);
+                self.attribute = None;
+                self.data_name = None;
+            }
+            BinXMLDeserializedTokens::Attribute(attr) => {
+                self.attribute = Some(expand_string_ref(&attr.name, self.chunk)?);
+            }
+            BinXMLDeserializedTokens::Value(value) => match &self.start_element {
+                Some(element) => {
+                    let is_data_name = element.as_str() == "Data"
+                        && self
+                            .attribute
+                            .take()
+                            .is_some_and(|attribute| attribute.as_str() == "Name");
+
+                    if is_data_name {
+                        self.data_name = value_as_string(value);
+                    }
+                }
+                None => {
+                    if let Some(value) = value_as_string(value) {
+                        self.push_text(&value);
+                    }
+                }
+            },
+            BinXMLDeserializedTokens::CDATASection(data) => self.push_text(data),
+            BinXMLDeserializedTokens::CharRef(value) => {
+                self.push_text(char_from_reference(*value).encode_utf8(&mut [0; 4]))
+            }
+            BinXMLDeserializedTokens::CloseStartElement => self.open_element(),
+            BinXMLDeserializedTokens::CloseEmptyElement => {
+                self.open_element();
+                self.close_element();
+            }
+            BinXMLDeserializedTokens::CloseElement => self.close_element(),
+            _ => {}
+        }
+
+        Ok(true)
+    }
+
+    fn push_text(&mut self, text: &str) {
+        if let Some(element) = self.elements.last_mut() {
+            if element.name.is_some() {
+                element.text.get_or_insert_with(String::new).push_str(text);
+            }
+        }
+    }
+
+    fn open_element(&mut self) {
+        let name = match self.start_element.take() {
+            Some(name) => name,
+            None => return,
+        };
+        let depth = self.elements.len();
+
+        let data_name = self.data_name.take();
+        let is_named_data = data_name.is_some();
+
+        // Fields are elements nested in `EventData` or `UserData`.
+        let field = match self.data_depth {
+            Some(data_depth) if depth > data_depth => {
+                // Elements containing other elements are not fields.
+                if let Some(parent) = self.elements.last_mut() {
+                    if depth > data_depth + 1 {
+                        parent.name = None;
+                    }
+                }
+
+                Some(data_name.unwrap_or_else(|| name.as_str().to_string()))
+            }
+            _ => {
+                if depth == 1 && matches!(name.as_str(), "EventData" | "UserData") {
+                    self.data_depth = Some(depth);
+                }
+                None
+            }
+        };
+
+        self.elements.push(OpenField {
+            name: field,
+            is_named_data,
+            text: None,
+        });
+    }
+
+    fn close_element(&mut self) {
+        if let Some(OpenField {
+            name: Some(name),
+            is_named_data,
+            text,
+        }) = self.elements.pop()
+        {
+            // Empty elements are omitted, unless they are named `Data` elements.
+            if text.is_some() || is_named_data {
+                self.fields.push((name, text));
+            }
+        }
+
+        if self.data_depth == Some(self.elements.len()) {
+            self.data_depth = None;
+        }
+    }
+}
+
+impl<'a> EvtxRecord<'a> {
+    /// Reads the fields of the `EventData` (or `UserData`) element of the record, without serializing it.
+    ///
+    /// Named `Data` elements are named after their `Name` attribute, other fields after their element.
+    /// Named `Data` elements without a value are `None`, other empty elements are omitted.
+    pub fn event_data(&self) -> Result<Vec<(String, Option<String>)>> {
+        let mut collector = EventDataCollector {
+            chunk: self.chunk,
+            elements: vec![],
+            data_depth: None,
+            start_element: None,
+            attribute: None,
+            data_name: None,
+            fields: vec![],
+        };
+
+        walk_expanded_tokens(&self.tokens, &[], self.chunk, &mut |token| {
+            collector.visit(token)
+        })?;
+
+        Ok(collector.fields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ensure_env_logger_initialized;
+    use crate::{EvtxParser, JsonFormat, ParserSettings};
+    use std::sync::Arc;
+
+    fn json_u64(value: &serde_json::Value) -> Option<u64> {
+        match value {
+            serde_json::Value::Number(n) => n.as_u64(),
+            serde_json::Value::String(s) => super::parse_number(s),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_system_fields_of_record() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+        let mut parser = EvtxParser::from_buffer(evtx_file.to_vec()).unwrap();
+
+        let mut chunk = parser.chunks().next().unwrap().unwrap();
+        let mut chunk = chunk.parse(Arc::new(ParserSettings::new())).unwrap();
+        let record = chunk.iter().next().unwrap().unwrap();
+
+        assert_eq!(record.event_id().unwrap(), Some(4608));
+        assert_eq!(
+            record.provider_name().unwrap().as_deref(),
+            Some("Microsoft-Windows-Security-Auditing")
+        );
+        assert_eq!(
+            record.provider_guid().unwrap().as_deref(),
+            Some("54849625-5478-4994-A5BA-3E3B0328C30D")
+        );
+        assert_eq!(record.level().unwrap(), Some(0));
+        assert_eq!(record.keywords().unwrap(), Some(0x8020000000000000));
+        assert_eq!(record.process_id().unwrap(), Some(456));
+        assert_eq!(record.thread_id().unwrap(), Some(460));
+        assert_eq!(record.channel().unwrap().as_deref(), Some("Security"));
+        assert_eq!(record.computer().unwrap().as_deref(), Some("37L4247F27-25"));
+        assert_eq!(record.user_sid().unwrap(), None);
+        assert_eq!(
+            record.time_created().unwrap().unwrap().to_rfc3339(),
+            "2016-07-08T18:12:51.681640+00:00"
+        );
+
+        // The record can still be serialized.
+        assert!(record.into_xml().is_ok());
+    }
+
+    #[test]
+    fn test_system_fields_match_json() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/system.evtx");
+        let mut parser = EvtxParser::from_buffer(evtx_file.to_vec()).unwrap();
+        let settings = Arc::new(ParserSettings::new().separate_json_attributes(true));
+
+        for chunk in parser.chunks() {
+            let mut chunk = chunk.unwrap();
+            let mut chunk = chunk.parse(Arc::clone(&settings)).unwrap();
+
+            for record in chunk.iter() {
+                let record = record.unwrap();
+                let fields = record.system().unwrap();
+
+                // The accessors only read their own field.
+                assert_eq!(record.event_id().unwrap(), fields.event_id);
+                assert_eq!(record.channel().unwrap(), fields.channel);
+                assert_eq!(record.user_sid().unwrap(), fields.user_sid);
+                assert_eq!(record.time_created().unwrap(), fields.time_created);
+
+                let json = record.into_json_value().unwrap().data;
+                let system = &json["Event"]["System"];
+                assert!(fields.event_id.is_some());
+
+                let event_id = match &system["EventID"] {
+                    serde_json::Value::Object(event_id) => json_u64(&event_id["#text"]),
+                    event_id => json_u64(event_id),
+                };
+                assert_eq!(fields.event_id.map(u64::from), event_id);
+                assert_eq!(
+                    fields.provider_name.as_deref(),
+                    system["Provider_attributes"]["Name"].as_str()
+                );
+                assert_eq!(fields.channel.as_deref(), system["Channel"].as_str());
+                assert_eq!(fields.computer.as_deref(), system["Computer"].as_str());
+                assert_eq!(fields.keywords, json_u64(&system["Keywords"]));
+                assert_eq!(
+                    fields.process_id.map(u64::from),
+                    json_u64(&system["Execution_attributes"]["ProcessID"])
+                );
+                assert_eq!(
+                    fields.user_sid.as_deref(),
+                    system["Security_attributes"]["UserID"].as_str()
+                );
+                assert_eq!(fields.event_record_id, json_u64(&system["EventRecordID"]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_event_data_matches_flat_json() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+        let mut parser = EvtxParser::from_buffer(evtx_file.to_vec()).unwrap();
+        let settings = Arc::new(ParserSettings::new().json_format(JsonFormat::Flat));
+
+        let mut chunk = parser.chunks().next().unwrap().unwrap();
+        let mut chunk = chunk.parse(settings).unwrap();
+
+        for record in chunk.iter() {
+            let record = record.unwrap();
+            let event_data = record.event_data().unwrap();
+            let json = record.into_json_value().unwrap().data;
+
+            let expected: Vec<(String, Option<String>)> = json
+                .as_object()
+                .unwrap()
+                .iter()
+                .filter(|(key, _)| key.starts_with("EventData.") || key.starts_with("UserData."))
+                .map(|(key, value)| {
+                    let name = key.rsplit('.').next().unwrap().to_string();
+                    let value = match value {
+                        serde_json::Value::Null => None,
+                        serde_json::Value::String(s) => Some(s.clone()),
+                        value => Some(value.to_string()),
+                    };
+                    (name, value)
+                })
+                .collect();
+
+            assert_eq!(event_data, expected);
+        }
+    }
+}
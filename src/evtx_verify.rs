@@ -0,0 +1,291 @@
+use crate::err::Result;
+use crate::evtx_chunk::{ChunkFlags, EvtxChunkData};
+use crate::evtx_file_header::{EvtxFileHeader, HeaderFlags};
+use crate::evtx_parser::{ReadSeek, EVTX_CHUNK_SIZE, EVTX_FILE_HEADER_SIZE};
+use crate::evtx_record::RecordId;
+use crate::{checksum_ieee, EvtxParser};
+
+use serde::Serialize;
+use std::io::SeekFrom;
+
+/// The file header checksum covers the first 120 bytes of the header.
+const EVTX_FILE_HEADER_CHECKSUM_SIZE: usize = 120;
+
+/// A checksum stored in the file, and the checksum computed from the data it covers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChecksumReport {
+    pub expected: u32,
+    pub computed: u32,
+    pub valid: bool,
+}
+
+impl ChecksumReport {
+    fn new(expected: u32, computed: u32) -> Self {
+        ChecksumReport {
+            expected,
+            computed,
+            valid: expected == computed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileHeaderReport {
+    pub checksum: ChecksumReport,
+    pub first_chunk_number: u64,
+    pub last_chunk_number: u64,
+    pub next_record_id: RecordId,
+    /// The chunk count stored in the header.
+    pub chunk_count: u16,
+    /// Set when the file was not closed properly.
+    pub dirty: bool,
+    /// Set when the log is full.
+    pub full: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChunkReport {
+    pub chunk_number: u64,
+    /// Offset of the chunk in the file.
+    pub offset: u64,
+    /// `None` when the chunk could not be read, in which case `error` is set.
+    pub first_event_record_id: Option<RecordId>,
+    pub last_event_record_id: Option<RecordId>,
+    pub header_checksum: Option<ChecksumReport>,
+    pub data_checksum: Option<ChecksumReport>,
+    /// Set when the chunk has the `NO_CRC32` flag, in which case its checksums are not used.
+    pub checksums_disabled: bool,
+    pub dirty: bool,
+    /// Number of records whose header could be read.
+    pub record_count: u64,
+    pub error: Option<String>,
+}
+
+/// A record whose id does not follow the id of the record before it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RecordIdGap {
+    /// The chunk in which the record was found.
+    pub chunk_number: u64,
+    pub expected: RecordId,
+    pub found: RecordId,
+}
+
+/// The result of `EvtxParser::verify`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VerificationReport {
+    pub file_header: FileHeaderReport,
+    /// The number of chunks according to the size of the file (including empty chunks).
+    pub calculated_chunk_count: u64,
+    /// Set when the chunk count in the file header does not match the number of chunks
+    /// which are not empty.
+    pub chunk_count_mismatch: bool,
+    /// Reports of all the chunks which are not empty.
+    pub chunks: Vec<ChunkReport>,
+    /// Gaps in record ids, both inside chunks and between chunks
+    /// (ordered by their first record id).
+    pub record_id_gaps: Vec<RecordIdGap>,
+}
+
+impl VerificationReport {
+    /// Returns true if no integrity issues were found.
+    /// The dirty and full flags are not considered issues.
+    pub fn is_valid(&self) -> bool {
+        self.file_header.checksum.valid
+            && !self.chunk_count_mismatch
+            && self.chunks.iter().all(ChunkReport::is_valid)
+            && self.record_id_gaps.is_empty()
+    }
+}
+
+impl FileHeaderReport {
+    fn new(header: &EvtxFileHeader, computed_checksum: u32) -> Self {
+        FileHeaderReport {
+            checksum: ChecksumReport::new(header.checksum, computed_checksum),
+            first_chunk_number: header.first_chunk_number,
+            last_chunk_number: header.last_chunk_number,
+            next_record_id: header.next_record_id,
+            chunk_count: header.chunk_count,
+            dirty: header.flags.contains(HeaderFlags::DIRTY),
+            full: header.flags.contains(HeaderFlags::FULL),
+        }
+    }
+}
+
+impl ChunkReport {
+    fn new(
+        chunk_number: u64,
+        chunk: &EvtxChunkData,
+        record_id_gaps: &mut Vec<RecordIdGap>,
+    ) -> Self {
+        let mut record_count = 0;
+        let mut expected_record_id = chunk.header.first_event_record_id;
+
+        for (_, header) in chunk.record_headers() {
+            let header = match header {
+                Ok(header) => header,
+                Err(_) => break,
+            };
+
+            if header.event_record_id != expected_record_id {
+                record_id_gaps.push(RecordIdGap {
+                    chunk_number,
+                    expected: expected_record_id,
+                    found: header.event_record_id,
+                });
+            }
+
+            record_count += 1;
+            expected_record_id = header.event_record_id.saturating_add(1);
+        }
+
+        ChunkReport {
+            chunk_number,
+            offset: chunk_offset(chunk_number),
+            first_event_record_id: Some(chunk.header.first_event_record_id),
+            last_event_record_id: Some(chunk.header.last_event_record_id),
+            header_checksum: Some(ChecksumReport::new(
+                chunk.header.header_chunk_checksum,
+                chunk.computed_header_checksum(),
+            )),
+            data_checksum: Some(ChecksumReport::new(
+                chunk.header.events_checksum,
+                chunk.computed_data_checksum(),
+            )),
+            checksums_disabled: chunk.header.flags.contains(ChunkFlags::NO_CRC32),
+            dirty: chunk.header.flags.contains(ChunkFlags::DIRTY),
+            record_count,
+            error: None,
+        }
+    }
+
+    fn unreadable(chunk_number: u64, error: String) -> Self {
+        ChunkReport {
+            chunk_number,
+            offset: chunk_offset(chunk_number),
+            first_event_record_id: None,
+            last_event_record_id: None,
+            header_checksum: None,
+            data_checksum: None,
+            checksums_disabled: false,
+            dirty: false,
+            record_count: 0,
+            error: Some(error),
+        }
+    }
+
+    /// Returns true if the chunk was read, and its checksums are valid (or disabled).
+    pub fn is_valid(&self) -> bool {
+        let checksum_is_valid =
+            |checksum: &Option<ChecksumReport>| matches!(checksum, Some(c) if c.valid);
+
+        self.error.is_none()
+            && (self.checksums_disabled
+                || (checksum_is_valid(&self.header_checksum)
+                    && checksum_is_valid(&self.data_checksum)))
+    }
+}
+
+fn chunk_offset(chunk_number: u64) -> u64 {
+    EVTX_FILE_HEADER_SIZE as u64 + chunk_number * EVTX_CHUNK_SIZE as u64
+}
+
+impl<T: ReadSeek> EvtxParser<T> {
+    /// Checks the integrity of the whole file: the file header checksum, the checksums of every
+    /// chunk, the continuity of record ids and the chunk count.
+    ///
+    /// Only record headers are read, records are not parsed.
+    /// Problems with the file are reported in the returned report, an error is only returned
+    /// if the file header cannot be read.
+    pub fn verify(&mut self) -> Result<VerificationReport> {
+        let mut header_data = [0_u8; EVTX_FILE_HEADER_CHECKSUM_SIZE];
+        self.data.seek(SeekFrom::Start(0))?;
+        self.data.read_exact(&mut header_data)?;
+
+        let mut chunks = vec![];
+        let mut record_id_gaps = vec![];
+
+        for chunk_number in 0..self.calculated_chunk_count {
+            match self.allocate_chunk(chunk_number, false) {
+                Ok(Some(chunk)) => {
+                    chunks.push(ChunkReport::new(chunk_number, &chunk, &mut record_id_gaps))
+                }
+                Ok(None) => {}
+                Err(err) => chunks.push(ChunkReport::unreadable(chunk_number, err.to_string())),
+            }
+        }
+
+        // Chunks are not necessarily stored in order after the file wraps around.
+        let mut chunk_id_ranges: Vec<_> = chunks
+            .iter()
+            .filter_map(|chunk| {
+                Some((
+                    chunk.first_event_record_id?,
+                    chunk.last_event_record_id?,
+                    chunk.chunk_number,
+                ))
+            })
+            .collect();
+        chunk_id_ranges.sort_unstable();
+
+        for pair in chunk_id_ranges.windows(2) {
+            let (_, previous_last_record_id, _) = pair[0];
+            let (first_record_id, _, chunk_number) = pair[1];
+            let expected = previous_last_record_id.saturating_add(1);
+
+            if first_record_id != expected {
+                record_id_gaps.push(RecordIdGap {
+                    chunk_number,
+                    expected,
+                    found: first_record_id,
+                });
+            }
+        }
+
+        Ok(VerificationReport {
+            file_header: FileHeaderReport::new(&self.header, checksum_ieee(&header_data)),
+            calculated_chunk_count: self.calculated_chunk_count,
+            chunk_count_mismatch: usize::from(self.header.chunk_count) != chunks.len(),
+            chunks,
+            record_id_gaps,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ensure_env_logger_initialized;
+
+    #[test]
+    fn test_verify_clean_file() {
+        ensure_env_logger_initialized();
+        let evtx_file = include_bytes!("../samples/security.evtx");
+        let mut parser = EvtxParser::from_buffer(evtx_file.to_vec()).unwrap();
+
+        let report = parser.verify().unwrap();
+
+        assert!(report.is_valid(), "{:#?}", report);
+        assert!(report.file_header.dirty);
+        assert_eq!(report.chunks.len(), 26);
+    }
+
+    #[test]
+    fn test_verify_reports_tampered_chunk() {
+        ensure_env_logger_initialized();
+        let mut evtx_file = include_bytes!("../samples/security.evtx").to_vec();
+
+        // Change a byte inside the records of the second chunk.
+        evtx_file[EVTX_FILE_HEADER_SIZE + EVTX_CHUNK_SIZE + 1000] ^= 0xff;
+
+        let mut parser = EvtxParser::from_buffer(evtx_file).unwrap();
+        let report = parser.verify().unwrap();
+
+        assert!(!report.is_valid());
+        let invalid: Vec<_> = report.chunks.iter().filter(|c| !c.is_valid()).collect();
+        assert_eq!(invalid.len(), 1);
+        assert_eq!(invalid[0].chunk_number, 1);
+
+        let data_checksum = invalid[0].data_checksum.as_ref().unwrap();
+        assert_ne!(data_checksum.expected, data_checksum.computed);
+    }
+}
@@ -1,23 +1,142 @@
 use crate::err::{SerializationError, SerializationResult};
 
 use crate::binxml::value_variant::BinXmlValue;
-use crate::model::xml::{BinXmlPI, XmlElement};
+use crate::model::xml::{char_from_reference, BinXmlPI, XmlElement};
 use crate::xml_output::BinXmlOutput;
 use crate::ParserSettings;
 
 use core::borrow::BorrowMut;
+use hashbrown::HashMap;
 use log::trace;
 use serde_json::{json, Map, Value};
 use std::borrow::Cow;
+use std::sync::Arc;
 
 use crate::binxml::name::BinXmlName;
 use crate::err::SerializationError::JsonStructureError;
 use quick_xml::events::BytesText;
 
+/// The shape of the JSON produced for every record.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum JsonFormat {
+    /// Mirrors the structure of the XML, with attributes stored under `#attributes`
+    /// (or under `<ELEMENTNAME>_attributes` when `separate_json_attributes` is set).
+    #[default]
+    Nested,
+    /// A single flat object per record, keyed by the path of every value without the root element
+    /// (`System.EventID`, `EventData.TargetUserName`, ...).
+    ///
+    /// Attributes are folded into the path of their element (`System.Provider.Name`),
+    /// unless the element also has text, in which case they are named `<PATH>_<ATTRIBUTE>`
+    /// (`System.EventID_Qualifiers`). Repeated elements are collected into arrays.
+    /// Empty elements are omitted, except for named `Data` elements, which are `null`.
+    Flat,
+    /// Like `Flat`, with the keys renamed using a `FieldMapping`.
+    FlatMapped(Arc<FieldMapping>),
+}
+
+impl JsonFormat {
+    /// Flat output, using Elastic Common Schema field names (see `FieldMapping::ecs`).
+    pub fn ecs() -> Self {
+        JsonFormat::FlatMapped(Arc::new(FieldMapping::ecs()))
+    }
+}
+
+/// Renames the keys of flat JSON output.
+///
+/// Keys are renamed by an exact match first, and otherwise by the first matching prefix.
+/// Keys which do not match are left unchanged.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct FieldMapping {
+    fields: HashMap<String, String>,
+    prefixes: Vec<(String, String)>,
+}
+
+impl FieldMapping {
+    pub fn new() -> Self {
+        FieldMapping::default()
+    }
+
+    /// A mapping to Elastic Common Schema field names, following the conventions of Winlogbeat.
+    /// `EventData` fields are stored under `winlog.event_data`, `UserData` fields under
+    /// `winlog.user_data` and other `System` fields under `winlog`.
+    pub fn ecs() -> Self {
+        FieldMapping::new()
+            .field("System.TimeCreated.SystemTime", "@timestamp")
+            .field("System.EventID", "event.code")
+            .field("System.EventID_Qualifiers", "winlog.event_id_qualifiers")
+            .field("System.Provider.Name", "event.provider")
+            .field("System.Provider.Guid", "winlog.provider_guid")
+            .field(
+                "System.Provider.EventSourceName",
+                "winlog.event_source_name",
+            )
+            .field("System.EventRecordID", "winlog.record_id")
+            .field("System.Channel", "winlog.channel")
+            .field("System.Computer", "host.name")
+            .field("System.Execution.ProcessID", "winlog.process.pid")
+            .field("System.Execution.ThreadID", "winlog.process.thread.id")
+            .field("System.Security.UserID", "winlog.user.identifier")
+            .field("System.Level", "winlog.level")
+            .field("System.Task", "winlog.task")
+            .field("System.Opcode", "winlog.opcode")
+            .field("System.Keywords", "winlog.keywords")
+            .field("System.Version", "winlog.version")
+            .field("System.Correlation.ActivityID", "winlog.activity_id")
+            .field(
+                "System.Correlation.RelatedActivityID",
+                "winlog.related_activity_id",
+            )
+            .prefix("EventData.", "winlog.event_data.")
+            .prefix("UserData.", "winlog.user_data.")
+            .prefix("System.", "winlog.")
+    }
+
+    /// Renames the key `from` to `to`.
+    pub fn field(mut self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.fields.insert(from.into(), to.into());
+
+        self
+    }
+
+    /// Replaces the prefix `from` of keys with `to`.
+    pub fn prefix(mut self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.prefixes.push((from.into(), to.into()));
+
+        self
+    }
+
+    /// Returns the new name of `key`.
+    pub fn map<'a>(&self, key: &'a str) -> Cow<'a, str> {
+        if let Some(mapped) = self.fields.get(key) {
+            return Cow::Owned(mapped.clone());
+        }
+
+        for (from, to) in self.prefixes.iter() {
+            if let Some(rest) = key.strip_prefix(from.as_str()) {
+                return Cow::Owned(format!("{}{}", to, rest));
+            }
+        }
+
+        Cow::Borrowed(key)
+    }
+}
+
+/// An element which is currently open, when producing flat JSON.
+struct FlatElement {
+    path: String,
+    attributes: Vec<(String, Value)>,
+    has_text: bool,
+    has_children: bool,
+    is_named_data: bool,
+}
+
 pub struct JsonOutput {
     map: Value,
     stack: Vec<String>,
     separate_json_attributes: bool,
+    format: JsonFormat,
+    flat_elements: Vec<FlatElement>,
 }
 
 impl JsonOutput {
@@ -26,9 +145,173 @@ impl JsonOutput {
             map: Value::Object(Map::new()),
             stack: vec![],
             separate_json_attributes: settings.should_separate_json_attributes(),
+            format: settings.get_json_format().clone(),
+            flat_elements: vec![],
         }
     }
 
+    fn is_flat(&self) -> bool {
+        !matches!(self.format, JsonFormat::Nested)
+    }
+
+    /// Returns the (renamed) key of `path` in flat output.
+    fn flat_key<'a>(&self, path: &'a str) -> Cow<'a, str> {
+        match &self.format {
+            JsonFormat::FlatMapped(mapping) => mapping.map(path),
+            _ => Cow::Borrowed(path),
+        }
+    }
+
+    fn flat_object(&mut self) -> &mut Map<String, Value> {
+        if !self.map.is_object() {
+            self.map = Value::Object(Map::new());
+        }
+
+        self.map.as_object_mut().expect("Set to an object above")
+    }
+
+    /// Inserts a value, collecting the values of repeated keys into an array.
+    fn insert_flat_value(&mut self, path: &str, value: Value) {
+        let key = self.flat_key(path).into_owned();
+        let object = self.flat_object();
+
+        match object.get_mut(&key) {
+            None | Some(Value::Null) => {
+                object.insert(key, value);
+            }
+            Some(Value::Array(values)) => values.push(value),
+            Some(existing) => {
+                let first = existing.take();
+                *existing = json!([first, value]);
+            }
+        }
+    }
+
+    /// Appends text to the last value of a key.
+    fn append_flat_text(&mut self, path: &str, text: &str) {
+        let key = self.flat_key(path).into_owned();
+        let object = self.flat_object();
+
+        let current = match object.get_mut(&key) {
+            Some(Value::Array(values)) => values.last_mut(),
+            current => current,
+        };
+
+        match current {
+            Some(Value::String(current)) => current.push_str(text),
+            Some(Value::Null) | None => {
+                object.insert(key, Value::String(text.to_owned()));
+            }
+            Some(current) => {
+                let appended = format!("{}{}", current, text);
+                *current = Value::String(appended);
+            }
+        }
+    }
+
+    fn open_flat_element(&mut self, element: &XmlElement) -> SerializationResult<()> {
+        let mut name = element.name.as_str().to_owned();
+        let mut is_named_data = false;
+        let mut attributes = vec![];
+
+        for attribute in element.attributes.iter() {
+            let attribute_name = attribute.name.as_str();
+
+            if name == "Data" && !is_named_data && attribute_name == "Name" {
+                name = attribute.value.as_cow_str()?.into_owned();
+                is_named_data = true;
+                continue;
+            }
+
+            // Namespace declarations are not data.
+            if attribute_name.starts_with("xmlns") {
+                continue;
+            }
+
+            let value = Value::try_from(attribute.value.as_ref())?;
+            if !value.is_null() {
+                attributes.push((attribute_name.to_owned(), value));
+            }
+        }
+
+        let path = match self.flat_elements.last_mut() {
+            // The root element is not part of the path.
+            None => String::new(),
+            Some(parent) => {
+                parent.has_children = true;
+
+                if parent.path.is_empty() {
+                    name
+                } else {
+                    format!("{}.{}", parent.path, name)
+                }
+            }
+        };
+
+        self.flat_elements.push(FlatElement {
+            path,
+            attributes,
+            has_text: false,
+            has_children: false,
+            is_named_data,
+        });
+
+        Ok(())
+    }
+
+    fn close_flat_element(&mut self) {
+        let element = match self.flat_elements.pop() {
+            Some(element) => element,
+            None => return,
+        };
+
+        if element.is_named_data
+            && !element.has_text
+            && !element.has_children
+            && element.attributes.is_empty()
+        {
+            self.insert_flat_value(&element.path, Value::Null);
+        }
+
+        for (attribute, value) in element.attributes {
+            let path = if element.path.is_empty() {
+                attribute
+            } else if element.has_text {
+                format!("{}_{}", element.path, attribute)
+            } else {
+                format!("{}.{}", element.path, attribute)
+            };
+
+            self.insert_flat_value(&path, value);
+        }
+    }
+
+    fn visit_flat_characters(&mut self, value: Cow<BinXmlValue>) -> SerializationResult<()> {
+        let (path, has_text) = match self.flat_elements.last_mut() {
+            Some(element) => {
+                let has_text = element.has_text;
+                element.has_text = true;
+
+                let path = if element.path.is_empty() {
+                    "#text".to_owned()
+                } else {
+                    element.path.clone()
+                };
+
+                (path, has_text)
+            }
+            None => return Ok(()),
+        };
+
+        if has_text {
+            self.append_flat_text(&path, &value.as_cow_str()?);
+        } else {
+            self.insert_flat_value(&path, value_to_json(value)?);
+        }
+
+        Ok(())
+    }
+
     /// Looks up the current path, will fill with empty objects if needed.
     fn get_or_create_current_path(&mut self) -> &mut Value {
         let mut v_temp = self.map.borrow_mut();
@@ -107,7 +390,7 @@ impl JsonOutput {
             .find(|a| a.name.as_ref().as_str() == "Name")
         {
             Some(name) => {
-                let data_key: Cow<'_, str> = name.value.as_ref().as_cow_str();
+                let data_key: Cow<'_, str> = name.value.as_ref().as_cow_str()?;
 
                 self.insert_node_without_attributes(element, &data_key)
             }
@@ -192,7 +475,7 @@ impl JsonOutput {
 
         for attribute in element.attributes.iter() {
             let value = attribute.value.clone().into_owned();
-            let value: Value = value.into();
+            let value = Value::try_from(value)?;
 
             if !value.is_null() {
                 let name: &str = attribute.name.as_str();
@@ -257,7 +540,7 @@ impl JsonOutput {
     }
 
     pub fn into_value(self) -> SerializationResult<Value> {
-        if !self.stack.is_empty() {
+        if !self.stack.is_empty() || !self.flat_elements.is_empty() {
             return Err(SerializationError::JsonStructureError {
                 message: "Invalid stream, EOF reached before closing all attributes".to_string(),
             });
@@ -267,6 +550,15 @@ impl JsonOutput {
     }
 }
 
+// A small optimization in case we already have an owned string.
+pub(crate) fn value_to_json(value: Cow<BinXmlValue>) -> SerializationResult<Value> {
+    match value {
+        Cow::Owned(BinXmlValue::StringType(value)) => Ok(json!(value)),
+        Cow::Owned(value) => Value::try_from(value),
+        Cow::Borrowed(value) => Value::try_from(value),
+    }
+}
+
 impl BinXmlOutput for JsonOutput {
     fn visit_end_of_stream(&mut self) -> SerializationResult<()> {
         trace!("visit_end_of_stream");
@@ -275,6 +567,10 @@ impl BinXmlOutput for JsonOutput {
 
     fn visit_open_start_element(&mut self, element: &XmlElement) -> SerializationResult<()> {
         trace!("visit_open_start_element: {:?}", element.name);
+        if self.is_flat() {
+            return self.open_flat_element(element);
+        }
+
         let element_name = element.name.as_str();
 
         if element_name == "Data" {
@@ -290,6 +586,11 @@ impl BinXmlOutput for JsonOutput {
     }
 
     fn visit_close_element(&mut self, _element: &XmlElement) -> SerializationResult<()> {
+        if self.is_flat() {
+            self.close_flat_element();
+            return Ok(());
+        }
+
         let p = self.stack.pop();
         trace!("visit_close_element: {:?}", p);
         Ok(())
@@ -297,31 +598,26 @@ impl BinXmlOutput for JsonOutput {
 
     fn visit_characters(&mut self, value: Cow<BinXmlValue>) -> SerializationResult<()> {
         trace!("visit_chars {:?}", &self.stack);
+        if self.is_flat() {
+            return self.visit_flat_characters(value);
+        }
+
         // We need to clone this bool since the next statement will borrow self as mutable.
         let separate_json_attributes = self.separate_json_attributes;
         let current_value = self.get_or_create_current_path();
 
-        // A small optimization in case we already have an owned string.
-        fn value_to_json(value: Cow<BinXmlValue>) -> Value {
-            if let Cow::Owned(BinXmlValue::StringType(value)) = value {
-                json!(value)
-            } else {
-                value.into_owned().into()
-            }
-        }
-
         // If our parent is an element without any attributes,
         // we simply swap the null with the string value.
         // This is also true for the case when the attributes were inserted as our siblings.
         match current_value {
             // Regular, distinct node.
             Value::Null => {
-                *current_value = value_to_json(value);
+                *current_value = value_to_json(value)?;
             }
             Value::Object(object) => {
                 if separate_json_attributes {
                     if object.is_empty() {
-                        *current_value = value_to_json(value);
+                        *current_value = value_to_json(value)?;
                     } else {
                         // TODO: Currently we discard some of the data in this case. What should we do?
                     }
@@ -341,7 +637,7 @@ impl BinXmlOutput for JsonOutput {
                     match object.get_mut(TEXT_KEY) {
                         // Regular, distinct node.
                         None | Some(Value::Null) => {
-                            object.insert(TEXT_KEY.to_owned(), value_to_json(value));
+                            object.insert(TEXT_KEY.to_owned(), value_to_json(value)?);
                         }
                         // The first time we encounter another node with the same name,
                         // we convert the exiting value into an array with both values.
@@ -350,11 +646,11 @@ impl BinXmlOutput for JsonOutput {
                             object.remove(TEXT_KEY);
                             object.insert(
                                 TEXT_KEY.to_owned(),
-                                json!([perv_value, value_to_json(value)]),
+                                json!([perv_value, value_to_json(value)?]),
                             );
                         }
                         // If we already have an array, we can just push into it.
-                        Some(Value::Array(arr)) => arr.push(value_to_json(value)),
+                        Some(Value::Array(arr)) => arr.push(value_to_json(value)?),
                         current_value => {
                             return Err(SerializationError::JsonStructureError {
                             message: format!(
@@ -369,10 +665,10 @@ impl BinXmlOutput for JsonOutput {
             // The first time we encounter another node with the same name,
             // we convert the exiting value into an array with both values.
             Value::String(current_string) => {
-                current_string.push_str(&value.as_cow_str());
+                current_string.push_str(&value.as_cow_str()?);
             }
             // If we already have an array, we can just push into it.
-            Value::Array(arr) => arr.push(value_to_json(value)),
+            Value::Array(arr) => arr.push(value_to_json(value)?),
             current_value => {
                 return Err(SerializationError::JsonStructureError {
                     message: format!(
@@ -386,10 +682,8 @@ impl BinXmlOutput for JsonOutput {
         Ok(())
     }
 
-    fn visit_cdata_section(&mut self) -> SerializationResult<()> {
-        Err(SerializationError::Unimplemented {
-            message: format!("`{}`: visit_cdata_section", file!()),
-        })
+    fn visit_cdata_section(&mut self, data: Cow<'_, str>) -> SerializationResult<()> {
+        self.visit_characters(Cow::Owned(BinXmlValue::StringType(data.into_owned())))
     }
 
     fn visit_entity_reference(&mut self, entity: &BinXmlName) -> Result<(), SerializationError> {
@@ -413,11 +707,18 @@ impl BinXmlOutput for JsonOutput {
 
     fn visit_character_reference(
         &mut self,
-        _char_ref: Cow<'_, str>,
+        char_ref: Cow<'_, str>,
     ) -> Result<(), SerializationError> {
-        Err(SerializationError::Unimplemented {
-            message: format!("`{}`: visit_character_reference", file!()),
-        })
+        let value =
+            char_ref
+                .parse::<u16>()
+                .map_err(|_| SerializationError::JsonStructureError {
+                    message: format!("Invalid XML character reference &#{};", char_ref),
+                })?;
+
+        self.visit_characters(Cow::Owned(BinXmlValue::StringType(
+            char_from_reference(value).to_string(),
+        )))
     }
 
     fn visit_processing_instruction(&mut self, _pi: &BinXmlPI) -> Result<(), SerializationError> {
@@ -436,8 +737,11 @@ impl BinXmlOutput for JsonOutput {
 mod tests {
     use crate::binxml::name::BinXmlName;
     use crate::binxml::value_variant::BinXmlValue;
+    use crate::err::SerializationError;
     use crate::model::xml::{XmlAttribute, XmlElement};
-    use crate::{BinXmlOutput, JsonOutput, ParserSettings};
+    use crate::{
+        BinXmlOutput, FieldMapping, JsonFormat, JsonOutput, JsonStreamOutput, ParserSettings,
+    };
     use pretty_assertions::assert_eq;
     use quick_xml::events::{BytesStart, Event};
     use quick_xml::Reader;
@@ -474,12 +778,11 @@ mod tests {
         }
     }
 
-    /// Converts an XML string to JSON, panics in xml is invalid.
-    fn xml_to_json(xml: &str, settings: &ParserSettings) -> String {
+    /// Feeds an XML string to `output`, panics in xml is invalid.
+    fn visit_xml<T: BinXmlOutput>(xml: &str, output: &mut T) {
         let mut reader = Reader::from_str(xml);
         reader.trim_text(true);
 
-        let mut output = JsonOutput::new(settings);
         output.visit_start_of_stream().expect("Start of stream");
 
         loop {
@@ -508,7 +811,9 @@ mod tests {
                         ))))
                         .expect("Text element"),
                     Event::Comment(_) => {}
-                    Event::CData(_) => unimplemented!(),
+                    Event::CData(cdata) => output
+                        .visit_cdata_section(Cow::Owned(bytes_to_string(cdata.as_ref())))
+                        .expect("CDATA section"),
                     Event::Decl(_) => {}
                     Event::PI(_) => unimplemented!(),
                     Event::DocType(_) => {}
@@ -520,8 +825,26 @@ mod tests {
                 Err(e) => panic!("Error at position {}: {:?}", reader.buffer_position(), e),
             }
         }
+    }
 
-        serde_json::to_string_pretty(&output.into_value().expect("Output")).expect("To serialize")
+    /// Converts an XML string to JSON, panics in xml is invalid.
+    fn xml_to_json(xml: &str, settings: &ParserSettings) -> String {
+        let mut output = JsonOutput::new(settings);
+        visit_xml(xml, &mut output);
+
+        let json = serde_json::to_string_pretty(&output.into_value().expect("Output"))
+            .expect("To serialize");
+
+        // Nested JSON is the same when streamed.
+        if *settings.get_json_format() == JsonFormat::Nested {
+            let mut output =
+                JsonStreamOutput::with_writer(Vec::new(), &settings.clone().indent(true));
+            visit_xml(xml, &mut output);
+
+            assert_eq!(bytes_to_string(&output.into_writer()), json);
+        }
+
+        json
     }
 
     #[test]
@@ -554,4 +877,163 @@ mod tests {
 
         assert_eq!(xml_to_json(s1, &settings), s2)
     }
+
+    #[test]
+    fn test_xml_to_json_irregular_structure_is_streamed_the_same() {
+        let s1 = r##"
+<Event>
+    <HTTPResponseHeadersInfo>
+        <Header>HTTP/1.1 200 OK</Header>
+        <Header>x-ms-version: 2009-09-19</Header>
+        <Empty></Empty>
+        <Empty></Empty>
+        <Header>x-ms-lease-status: unlocked</Header>
+    </HTTPResponseHeadersInfo>
+    <Action name="NoProxy"></Action>
+    <Action name="NoProxy"></Action>
+    <CacheInfo>note<URLCacheResponseInfo></URLCacheResponseInfo></CacheInfo>
+    <EventData>
+        <Data Name="Name">first</Data>
+        <Data>unnamed</Data>
+        <Data Name="Name">second</Data>
+        <Data>other</Data>
+    </EventData>
+</Event>
+"##
+        .trim();
+
+        for separate_json_attributes in [false, true] {
+            let settings = ParserSettings::new()
+                .num_threads(1)
+                .separate_json_attributes(separate_json_attributes);
+
+            // `xml_to_json` checks that `JsonStreamOutput` writes the same JSON.
+            xml_to_json(s1, &settings);
+        }
+    }
+
+    #[test]
+    fn test_xml_to_flat_json() {
+        let s1 = r#"
+<Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event">
+    <System>
+        <Provider Name="Microsoft-Windows-WinINet" Guid="43D1A55C-76D6-4F7E-995C-64C711E5CAFE"></Provider>
+        <EventID Qualifiers="16384">4111</EventID>
+        <Correlation></Correlation>
+    </System>
+    <EventData>
+        <Data Name="TargetUserName">SYSTEM</Data>
+        <Data Name="ProcessName"></Data>
+        <Data>first</Data>
+        <Data>second</Data>
+    </EventData>
+    <UserData>
+        <HTTPResponseHeadersInfo>
+            <Header>HTTP/1.1 200 OK</Header>
+            <Header>x-ms-version: 2009-09-19</Header>
+            <Header>x-ms-lease-status: unlocked</Header>
+        </HTTPResponseHeadersInfo>
+    </UserData>
+</Event>
+"#
+        .trim();
+        let s2 = r#"
+{
+  "System.Provider.Name": "Microsoft-Windows-WinINet",
+  "System.Provider.Guid": "43D1A55C-76D6-4F7E-995C-64C711E5CAFE",
+  "System.EventID": "4111",
+  "System.EventID_Qualifiers": "16384",
+  "EventData.TargetUserName": "SYSTEM",
+  "EventData.ProcessName": null,
+  "EventData.Data": [
+    "first",
+    "second"
+  ],
+  "UserData.HTTPResponseHeadersInfo.Header": [
+    "HTTP/1.1 200 OK",
+    "x-ms-version: 2009-09-19",
+    "x-ms-lease-status: unlocked"
+  ]
+}
+"#
+        .trim();
+
+        let settings = ParserSettings::new().json_format(JsonFormat::Flat);
+
+        assert_eq!(xml_to_json(s1, &settings), s2)
+    }
+
+    #[test]
+    fn test_xml_to_mapped_json() {
+        let s1 = r#"
+<Event>
+    <System>
+        <EventID>4624</EventID>
+        <Computer>host</Computer>
+        <Task>12544</Task>
+    </System>
+    <EventData>
+        <Data Name="TargetUserName">SYSTEM</Data>
+        <Data Name="LogonType">5</Data>
+    </EventData>
+</Event>
+"#
+        .trim();
+        let s2 = r#"
+{
+  "event.code": "4624",
+  "host.name": "host",
+  "System.Task": "12544",
+  "user.name": "SYSTEM",
+  "event_data.LogonType": "5"
+}
+"#
+        .trim();
+
+        let mapping = FieldMapping::new()
+            .field("System.EventID", "event.code")
+            .field("System.Computer", "host.name")
+            .field("EventData.TargetUserName", "user.name")
+            .prefix("EventData.", "event_data.");
+        let settings = ParserSettings::new().json_format(JsonFormat::FlatMapped(mapping.into()));
+
+        assert_eq!(xml_to_json(s1, &settings), s2)
+    }
+
+    #[test]
+    fn test_ecs_mapping() {
+        let ecs = FieldMapping::ecs();
+
+        assert_eq!(ecs.map("System.TimeCreated.SystemTime"), "@timestamp");
+        assert_eq!(ecs.map("System.Computer"), "host.name");
+        assert_eq!(
+            ecs.map("EventData.TargetUserName"),
+            "winlog.event_data.TargetUserName"
+        );
+        assert_eq!(ecs.map("System.Foo"), "winlog.Foo");
+        assert_eq!(ecs.map("Other"), "Other");
+    }
+
+    #[test]
+    fn test_unsupported_values_are_an_error() {
+        fn assert_unsupported<T: BinXmlOutput>(output: &mut T) {
+            output.visit_start_of_stream().expect("Start of stream");
+            output
+                .visit_open_start_element(&dummy_event())
+                .expect("Open start element");
+
+            assert!(matches!(
+                output.visit_characters(Cow::Owned(BinXmlValue::EvtHandle)),
+                Err(SerializationError::UnsupportedValueType { .. })
+            ));
+        }
+
+        for format in [JsonFormat::Nested, JsonFormat::Flat] {
+            let settings = ParserSettings::new().json_format(format);
+            assert_unsupported(&mut JsonOutput::new(&settings));
+        }
+
+        let settings = ParserSettings::new();
+        assert_unsupported(&mut JsonStreamOutput::with_writer(Vec::new(), &settings));
+    }
 }
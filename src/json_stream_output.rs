@@ -0,0 +1,585 @@
+use crate::err::{SerializationError, SerializationResult};
+
+use crate::binxml::name::BinXmlName;
+use crate::binxml::value_variant::BinXmlValue;
+use crate::json_output::value_to_json;
+use crate::model::xml::{char_from_reference, BinXmlPI, XmlElement};
+use crate::xml_output::BinXmlOutput;
+use crate::ParserSettings;
+
+use log::trace;
+use quick_xml::events::BytesText;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use serde_json::{json, Map, Value};
+use std::borrow::Cow;
+use std::io::Write;
+use std::mem;
+
+/// The root object of the record is always the first node.
+const ROOT: usize = 0;
+
+/// A node of the record.
+#[derive(Debug)]
+enum Node {
+    /// An object, its values are other nodes.
+    Object(Vec<(String, usize)>),
+    /// Any other value (the objects of attributes are stored as a single value as well).
+    Value(Value),
+}
+
+/// An element which is currently open.
+#[derive(Debug)]
+struct Frame {
+    /// The key of the element in the object of its parent.
+    key: String,
+    /// The node of the element, `None` until it is looked up in its parent (or after it was removed from it).
+    node: Option<usize>,
+}
+
+/// Writes nested JSON (see `JsonFormat::Nested`) to `W`, without building a `serde_json::Value`.
+///
+/// The output is the same as serializing the value built by `JsonOutput`, but the nodes of
+/// the record are stored in a flat list and the open elements keep the index of their node,
+/// instead of looking up the path of the current element from the root for every token.
+/// The record is written once the end of the stream is reached.
+pub struct JsonStreamOutput<W: Write> {
+    writer: W,
+    indent: bool,
+    separate_json_attributes: bool,
+    nodes: Vec<Node>,
+    stack: Vec<Frame>,
+}
+
+impl<W: Write> JsonStreamOutput<W> {
+    pub fn with_writer(target: W, settings: &ParserSettings) -> Self {
+        JsonStreamOutput {
+            writer: target,
+            indent: settings.should_indent(),
+            separate_json_attributes: settings.should_separate_json_attributes(),
+            nodes: vec![],
+            stack: vec![],
+        }
+    }
+
+    /// Consume the output, returning the writer.
+    pub fn into_writer(self) -> W {
+        self.writer
+    }
+
+    fn push_node(&mut self, node: Node) -> usize {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    /// Returns the entries of an object node, converting an object value (of attributes) to nodes.
+    fn entries_mut(&mut self, id: usize) -> Option<&mut Vec<(String, usize)>> {
+        if let Node::Value(Value::Object(map)) = &mut self.nodes[id] {
+            let map = mem::take(map);
+            let entries = map
+                .into_iter()
+                .map(|(key, value)| (key, self.push_node(Node::Value(value))))
+                .collect();
+            self.nodes[id] = Node::Object(entries);
+        }
+
+        match &mut self.nodes[id] {
+            Node::Object(entries) => Some(entries),
+            Node::Value(_) => None,
+        }
+    }
+
+    fn get(&mut self, id: usize, key: &str) -> Option<usize> {
+        self.entries_mut(id)?
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| *v)
+    }
+
+    /// Whether the node is `null` or an empty object (a placeholder).
+    fn is_placeholder(&self, id: usize) -> bool {
+        match &self.nodes[id] {
+            Node::Object(entries) => entries.is_empty(),
+            Node::Value(Value::Object(map)) => map.is_empty(),
+            Node::Value(value) => value.is_null(),
+        }
+    }
+
+    fn is_empty_object(&self, id: usize) -> bool {
+        match &self.nodes[id] {
+            Node::Object(entries) => entries.is_empty(),
+            Node::Value(Value::Object(map)) => map.is_empty(),
+            Node::Value(_) => false,
+        }
+    }
+
+    /// Inserts `key` into the object `id`, replacing the value of an existing key in place.
+    fn insert(&mut self, id: usize, key: &str, node: Node) -> usize {
+        let new_id = self.push_node(node);
+        let entries = self.entries_mut(id).expect("Expected an object");
+
+        match entries.iter_mut().find(|(k, _)| k == key) {
+            Some(entry) => entry.1 = new_id,
+            None => entries.push((key.to_owned(), new_id)),
+        }
+
+        new_id
+    }
+
+    /// Removes `key` from the object `id`, moving the last key to its place (like `serde_json::Map::remove`).
+    fn remove(&mut self, id: usize, key: &str) {
+        if let Some(entries) = self.entries_mut(id) {
+            if let Some(position) = entries.iter().position(|(k, _)| k == key) {
+                entries.swap_remove(position);
+            }
+        }
+    }
+
+    /// Returns the child `key` of `parent`, creating it if needed.
+    /// Mirrors the way `JsonOutput` creates the current path.
+    fn child_or_create(&mut self, parent: usize, key: &str) -> usize {
+        if let Some(entries) = self.entries_mut(parent) {
+            if let Some((_, id)) = entries.iter().find(|(k, _)| k == key) {
+                return *id;
+            }
+
+            let id = self.push_node(Node::Object(vec![]));
+            self.entries_mut(parent)
+                .expect("Checked above")
+                .push((key.to_owned(), id));
+            return id;
+        }
+
+        // A value (text which came before the child elements) is moved to the child,
+        // and `null` is replaced by an empty object.
+        let child = match mem::replace(&mut self.nodes[parent], Node::Object(vec![])) {
+            Node::Value(Value::Null) => Node::Object(vec![]),
+            value => value,
+        };
+        let id = self.push_node(child);
+        self.nodes[parent] = Node::Object(vec![(key.to_owned(), id)]);
+
+        id
+    }
+
+    /// Returns the node of the current element, creating the missing nodes of the path.
+    fn current_node(&mut self) -> usize {
+        let mut current = ROOT;
+
+        for index in 0..self.stack.len() {
+            current = match self.stack[index].node {
+                Some(node) => node,
+                None => {
+                    let key = mem::take(&mut self.stack[index].key);
+                    let node = self.child_or_create(current, &key);
+                    self.stack[index] = Frame {
+                        key,
+                        node: Some(node),
+                    };
+                    node
+                }
+            };
+        }
+
+        current
+    }
+
+    /// Returns the node of the parent of the current element, creating the missing nodes of the path.
+    fn current_parent(&mut self) -> usize {
+        self.current_node();
+
+        match self.stack.len() {
+            0 | 1 => ROOT,
+            len => self.stack[len - 2].node.expect("Created above"),
+        }
+    }
+
+    fn to_value(&self, id: usize) -> Value {
+        serde_json::to_value(NodeRef {
+            nodes: &self.nodes,
+            id,
+        })
+        .unwrap_or(Value::Null)
+    }
+
+    fn insert_node_without_attributes(&mut self, name: &str) -> SerializationResult<()> {
+        trace!("insert_node_without_attributes");
+        self.stack.push(Frame {
+            key: name.to_owned(),
+            node: None,
+        });
+
+        let parent = self.current_parent();
+        let old = self.get(parent, name).expect("Created above");
+        let new = self.insert(parent, name, Node::Value(Value::Null));
+        self.stack.last_mut().expect("Pushed above").node = Some(new);
+
+        // Like `JsonOutput`, an existing value moves to the next free slot, and the key is
+        // used by the new node.
+        if self.is_placeholder(old) {
+            return Ok(());
+        }
+
+        let mut free_slot = 1;
+        while self
+            .get(parent, &format!("{}_{}", name, free_slot))
+            .is_some()
+        {
+            free_slot += 1
+        }
+
+        let entries = self.entries_mut(parent).expect("Parent is an object");
+        entries.push((format!("{}_{}", name, free_slot), old));
+
+        Ok(())
+    }
+
+    fn insert_node_with_attributes(
+        &mut self,
+        element: &XmlElement,
+        name: &str,
+    ) -> SerializationResult<()> {
+        trace!("insert_node_with_attributes");
+        self.stack.push(Frame {
+            key: name.to_owned(),
+            node: None,
+        });
+
+        let mut attributes = Map::new();
+
+        for attribute in element.attributes.iter() {
+            let value = attribute.value.clone().into_owned();
+            let value = Value::try_from(value)?;
+
+            if !value.is_null() {
+                let name: &str = attribute.name.as_str();
+                attributes.insert(name.to_owned(), value);
+            }
+        }
+
+        if attributes.is_empty() {
+            // If the object does not have attributes, replace it with a null placeholder,
+            // so it will be printed as a key-value pair
+            let parent = self.current_parent();
+            let node = self.insert(parent, name, Node::Value(Value::Null));
+            self.stack.last_mut().expect("Pushed above").node = Some(node);
+        } else if self.separate_json_attributes {
+            let parent = self.current_parent();
+            self.insert(
+                parent,
+                &format!("{}_attributes", name),
+                Node::Value(Value::Object(attributes)),
+            );
+
+            // If the element's main value is empty, it is removed.
+            let node = self.get(parent, name).expect("Created above");
+            if self.is_empty_object(node) {
+                self.remove(parent, name);
+                self.stack.last_mut().expect("Pushed above").node = None;
+            }
+        } else {
+            let node = self.current_node();
+            if self.entries_mut(node).is_none() {
+                return Err(SerializationError::JsonStructureError {
+                    message:
+                        "This is a bug - expected current value to exist, and to be an object type.
+                            Check that the value is not `Value::null`"
+                            .to_string(),
+                });
+            }
+
+            self.insert(node, "#attributes", Node::Value(Value::Object(attributes)));
+        }
+
+        Ok(())
+    }
+}
+
+/// Serializes a node and its children.
+struct NodeRef<'a> {
+    nodes: &'a [Node],
+    id: usize,
+}
+
+impl<'a> Serialize for NodeRef<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match &self.nodes[self.id] {
+            Node::Object(entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, id) in entries.iter() {
+                    map.serialize_entry(
+                        key,
+                        &NodeRef {
+                            nodes: self.nodes,
+                            id: *id,
+                        },
+                    )?;
+                }
+                map.end()
+            }
+            Node::Value(value) => value.serialize(serializer),
+        }
+    }
+}
+
+impl<W: Write> BinXmlOutput for JsonStreamOutput<W> {
+    fn visit_end_of_stream(&mut self) -> SerializationResult<()> {
+        trace!("visit_end_of_stream");
+        if !self.stack.is_empty() {
+            return Err(SerializationError::JsonStructureError {
+                message: "Invalid stream, EOF reached before closing all attributes".to_string(),
+            });
+        }
+
+        let root = NodeRef {
+            nodes: &self.nodes,
+            id: ROOT,
+        };
+
+        if self.indent {
+            serde_json::to_writer_pretty(&mut self.writer, &root)?;
+        } else {
+            serde_json::to_writer(&mut self.writer, &root)?;
+        }
+
+        self.nodes.clear();
+
+        Ok(())
+    }
+
+    fn visit_open_start_element(&mut self, element: &XmlElement) -> SerializationResult<()> {
+        trace!("visit_open_start_element: {:?}", element.name);
+        let element_name = element.name.as_str();
+
+        // Like a regular node, but uses it's "Name" attribute.
+        if element_name == "Data" {
+            return match element
+                .attributes
+                .iter()
+                .find(|a| a.name.as_ref().as_str() == "Name")
+            {
+                Some(name) => self.insert_node_without_attributes(&name.value.as_cow_str()?),
+                // Ignore this node
+                None => {
+                    self.stack.push(Frame {
+                        key: "Data".to_owned(),
+                        node: None,
+                    });
+                    Ok(())
+                }
+            };
+        }
+
+        // <Task>12288</Task> -> {"Task": 12288}
+        if element.attributes.is_empty() {
+            return self.insert_node_without_attributes(element_name);
+        }
+
+        self.insert_node_with_attributes(element, element_name)
+    }
+
+    fn visit_close_element(&mut self, _element: &XmlElement) -> SerializationResult<()> {
+        let p = self.stack.pop();
+        trace!("visit_close_element: {:?}", p);
+        Ok(())
+    }
+
+    fn visit_characters(&mut self, value: Cow<BinXmlValue>) -> SerializationResult<()> {
+        trace!("visit_chars");
+        let node = self.current_node();
+
+        if self.entries_mut(node).is_some() {
+            if self.separate_json_attributes {
+                // Text of an element with children is discarded, like in `JsonOutput`.
+                if self.is_empty_object(node) {
+                    self.nodes[node] = Node::Value(value_to_json(value)?);
+                }
+                return Ok(());
+            }
+
+            // If multiple nodes with the same name exists, the `#text` attribute is converted into an array.
+            const TEXT_KEY: &str = "#text";
+            let text = match self.get(node, TEXT_KEY) {
+                None => {
+                    self.insert(node, TEXT_KEY, Node::Value(value_to_json(value)?));
+                    return Ok(());
+                }
+                Some(text) => text,
+            };
+
+            match &mut self.nodes[text] {
+                Node::Value(Value::Null) => {
+                    self.insert(node, TEXT_KEY, Node::Value(value_to_json(value)?));
+                }
+                Node::Value(Value::String(perv_value)) => {
+                    let perv_value = mem::take(perv_value);
+                    self.remove(node, TEXT_KEY);
+                    self.insert(
+                        node,
+                        TEXT_KEY,
+                        Node::Value(json!([perv_value, value_to_json(value)?])),
+                    );
+                }
+                Node::Value(Value::Array(arr)) => arr.push(value_to_json(value)?),
+                _ => {
+                    return Err(SerializationError::JsonStructureError {
+                        message: format!(
+                            "expected current value to be a String or an Array, found {:?}, new value is {:?}",
+                            self.to_value(text), value
+                        ),
+                    });
+                }
+            }
+
+            return Ok(());
+        }
+
+        match &mut self.nodes[node] {
+            // Regular, distinct node.
+            Node::Value(current_value @ Value::Null) => *current_value = value_to_json(value)?,
+            Node::Value(Value::String(current_string)) => {
+                current_string.push_str(&value.as_cow_str()?);
+            }
+            Node::Value(Value::Array(arr)) => arr.push(value_to_json(value)?),
+            _ => {
+                return Err(SerializationError::JsonStructureError {
+                    message: format!(
+                        "expected current value to be a String or an Array, found {:?}, new value is {:?}",
+                        self.to_value(node), value
+                    ),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn visit_cdata_section(&mut self, data: Cow<'_, str>) -> SerializationResult<()> {
+        self.visit_characters(Cow::Owned(BinXmlValue::StringType(data.into_owned())))
+    }
+
+    fn visit_entity_reference(&mut self, entity: &BinXmlName) -> Result<(), SerializationError> {
+        // We need to create a BytesText event to access quick-xml's unescape functionality (which is private).
+        // We also terminate the entity.
+        let entity_ref = "&".to_string() + entity.as_str() + ";";
+
+        let xml_event = BytesText::from_escaped(&entity_ref);
+        match xml_event.unescape() {
+            Ok(escaped) => {
+                let as_string = escaped.to_string();
+
+                self.visit_characters(Cow::Owned(BinXmlValue::StringType(as_string)))?;
+                Ok(())
+            }
+            Err(_) => Err(SerializationError::JsonStructureError {
+                message: format!("Unterminated XML Entity {}", entity_ref),
+            }),
+        }
+    }
+
+    fn visit_character_reference(
+        &mut self,
+        char_ref: Cow<'_, str>,
+    ) -> Result<(), SerializationError> {
+        let value =
+            char_ref
+                .parse::<u16>()
+                .map_err(|_| SerializationError::JsonStructureError {
+                    message: format!("Invalid XML character reference &#{};", char_ref),
+                })?;
+
+        self.visit_characters(Cow::Owned(BinXmlValue::StringType(
+            char_from_reference(value).to_string(),
+        )))
+    }
+
+    fn visit_processing_instruction(&mut self, _pi: &BinXmlPI) -> Result<(), SerializationError> {
+        Err(SerializationError::Unimplemented {
+            message: format!("`{}`: visit_processing_instruction_data", file!()),
+        })
+    }
+
+    fn visit_start_of_stream(&mut self) -> SerializationResult<()> {
+        trace!("visit_start_of_stream");
+        self.nodes.clear();
+        self.stack.clear();
+        self.push_node(Node::Object(vec![]));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ensure_env_logger_initialized, EvtxParser, JsonOutput};
+    use pretty_assertions::assert_eq;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// Checks that every record is written exactly like the value built by `JsonOutput`.
+    fn assert_same_as_json_output(path: &PathBuf, settings: ParserSettings) {
+        let mut parser = match EvtxParser::from_path(path) {
+            Ok(parser) => parser.with_configuration(settings.clone()),
+            // Some samples are not evtx files.
+            Err(_) => return,
+        };
+
+        let records = parser.serialized_records(|record| {
+            let record = record?;
+            let event_record_id = record.event_record_id;
+
+            let mut output = JsonOutput::new(&settings);
+            let expected = record
+                .clone()
+                .into_output(&mut output)
+                .and_then(|_| Ok(output.into_value()?))
+                .map(|value| {
+                    if settings.should_indent() {
+                        serde_json::to_string_pretty(&value).unwrap()
+                    } else {
+                        serde_json::to_string(&value).unwrap()
+                    }
+                });
+
+            let mut output = JsonStreamOutput::with_writer(Vec::new(), &settings);
+            let streamed = record
+                .into_output(&mut output)
+                .map(|_| String::from_utf8(output.into_writer()).unwrap());
+
+            Ok((event_record_id, streamed.ok(), expected.ok()))
+        });
+
+        for record in records.flatten() {
+            let (id, streamed, expected) = record;
+            assert_eq!(
+                streamed,
+                expected,
+                "{} record {} ({:?})",
+                path.display(),
+                id,
+                settings
+            );
+        }
+    }
+
+    #[test]
+    fn test_same_output_as_json_output_for_all_samples() {
+        ensure_env_logger_initialized();
+        let samples = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("samples");
+
+        for entry in fs::read_dir(samples).unwrap() {
+            let path = entry.unwrap().path();
+
+            for indent in [true, false] {
+                for separate_json_attributes in [true, false] {
+                    let settings = ParserSettings::new()
+                        .num_threads(1)
+                        .indent(indent)
+                        .separate_json_attributes(separate_json_attributes);
+
+                    assert_same_as_json_output(&path, settings);
+                }
+            }
+        }
+    }
+}
@@ -11,25 +11,73 @@ mod macros;
 #[macro_use]
 extern crate bitflags;
 
-pub use evtx_chunk::{EvtxChunk, EvtxChunkData, EvtxChunkHeader, IterChunkRecords};
-pub use evtx_parser::{EvtxParser, IntoIterChunks, IterChunks, ParserSettings};
+pub use csv_output::CsvOutput;
+pub use evtx_analysis::{Finding, FindingKind};
+#[cfg(feature = "arrow")]
+pub use evtx_arrow::RecordBatchBuilder;
+pub use evtx_carver::{CarvedRecord, CarvedRecordSource, EvtxCarver};
+pub use evtx_checkpoint::Checkpoint;
+pub use evtx_chunk::{
+    ChunkBytes, EvtxChunk, EvtxChunkData, EvtxChunkHeader, IterChunkRecords, RecordRecovery,
+};
+pub use evtx_follow::EvtxFollower;
+pub use evtx_index::{ChunkIndex, ChunkIndexEntry};
+#[cfg(feature = "mmap")]
+pub use evtx_mmap::MappedFile;
+pub use evtx_ordering::RecordOrder;
+pub use evtx_parser::{EvtxParser, IntoIterChunks, IterChunks, ParserSettings, RecordFilter};
+pub use evtx_query::EvtxQuery;
 pub use evtx_record::{EvtxRecord, EvtxRecordHeader, SerializedEvtxRecord};
-pub use json_output::JsonOutput;
+#[cfg(feature = "sigma")]
+pub use evtx_sigma::{SigmaFields, SigmaMatch, SigmaRule, SigmaRuleSet};
+#[cfg(feature = "sqlite")]
+pub use evtx_sqlite::SqliteWriter;
+pub use evtx_stream::EvtxStreamParser;
+pub use evtx_system::SystemFields;
+pub use evtx_verify::{
+    ChecksumReport, ChunkReport, FileHeaderReport, RecordIdGap, VerificationReport,
+};
+pub use json_output::{FieldMapping, JsonFormat, JsonOutput};
+pub use json_stream_output::JsonStreamOutput;
 pub use xml_output::{BinXmlOutput, XmlOutput};
 
+#[cfg(feature = "mmap")]
+pub use memmap2::Mmap;
+
 pub mod binxml;
 pub mod err;
 pub mod model;
 
+mod evtx_analysis;
+#[cfg(feature = "arrow")]
+mod evtx_arrow;
+mod evtx_carver;
+mod evtx_checkpoint;
 mod evtx_chunk;
+mod evtx_deserializer;
 mod evtx_file_header;
+mod evtx_follow;
+mod evtx_index;
+#[cfg(feature = "mmap")]
+mod evtx_mmap;
+mod evtx_ordering;
 mod evtx_parser;
+mod evtx_query;
 mod evtx_record;
+#[cfg(feature = "sigma")]
+mod evtx_sigma;
+#[cfg(feature = "sqlite")]
+mod evtx_sqlite;
+mod evtx_stream;
+mod evtx_system;
+mod evtx_verify;
 mod string_cache;
 mod template_cache;
 mod utils;
 
+mod csv_output;
 mod json_output;
+mod json_stream_output;
 mod xml_output;
 
 pub type ChunkOffset = u32;
@@ -72,7 +120,7 @@ mod test_readme {
         ($doc:expr, $id:ident) => {
             #[doc = $doc]
             enum $id {}
-        }
+        };
     }
 
     calculated_doc!(include_str!("../README.md"), _DoctestReadme);
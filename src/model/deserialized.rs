@@ -16,8 +16,9 @@ pub enum BinXMLDeserializedTokens<'a> {
     CloseEmptyElement,
     CloseElement,
     Value(BinXmlValue<'a>),
-    CDATASection,
-    CharRef,
+    CDATASection(String),
+    /// The code point of a character reference (`&#N;`).
+    CharRef(u16),
     EntityRef(BinXmlEntityReference),
     PITarget(BinXMLProcessingInstructionTarget),
     PIData(String),
@@ -11,11 +11,20 @@ pub enum XmlModel<'a> {
     CloseElement,
     PI(BinXmlPI<'a>),
     EntityRef(Cow<'a, BinXmlName>),
+    CDATA(Cow<'a, str>),
+    /// The code point of a character reference (`&#N;`).
+    CharRef(u16),
     Value(Cow<'a, BinXmlValue<'a>>),
     EndOfStream,
     StartOfStream,
 }
 
+/// The character referenced by a character reference,
+/// code points which are not valid characters (such as lone surrogates) are replaced with U+FFFD.
+pub(crate) fn char_from_reference(value: u16) -> char {
+    char::from_u32(u32::from(value)).unwrap_or(char::REPLACEMENT_CHARACTER)
+}
+
 #[derive(Debug)]
 pub(crate) struct XmlElementBuilder<'a> {
     name: Option<Cow<'a, BinXmlName>>,
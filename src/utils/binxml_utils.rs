@@ -22,7 +22,7 @@ pub fn read_len_prefixed_utf16_string<T: ReadSeek>(
     is_null_terminated: bool,
 ) -> Result<Option<String>, FailedToReadString> {
     let expected_number_of_characters = stream.read_u16::<LittleEndian>()?;
-    let needed_bytes = u64::from(expected_number_of_characters * 2);
+    let needed_bytes = u64::from(expected_number_of_characters) * 2;
 
     trace!(
         "Offset `0x{offset:08x} ({offset})` reading a{nul}string of len {len}",
@@ -78,24 +78,53 @@ pub fn read_ansi_encoded_string<T: ReadSeek>(
             // There may be multiple NULs in the string, prune them.
             bytes.retain(|&b| b != 0);
 
-            let s = match decode(&bytes, DecoderTrap::Strict, ansi_codec).0 {
-                Ok(s) => s,
-                Err(message) => {
-                    let as_boxed_err = Box::<dyn StdErr + Send + Sync>::from(message.to_string());
-                    let wrapped_io_err = WrappedIoError::capture_hexdump(as_boxed_err, stream);
-                    return Err(DeserializationError::FailedToReadToken {
-                        t: format!("ansi_string {}", ansi_codec.name()),
-                        token_name: "",
-                        source: wrapped_io_err,
-                    });
-                }
-            };
-
-            Ok(Some(s))
+            decode_ansi_string(&bytes, stream, ansi_codec).map(Some)
         }
     }
 }
 
+/// Reads an array of NUL terminated ansi encoded strings (which takes `size` bytes) from the given stream.
+pub fn read_ansi_encoded_string_array<T: ReadSeek>(
+    stream: &mut T,
+    size: u64,
+    ansi_codec: EncodingRef,
+) -> DeserializationResult<Vec<String>> {
+    let mut bytes = vec![0; size as usize];
+    stream.read_exact(&mut bytes)?;
+
+    // The last string is terminated as well.
+    if let Some(0) = bytes.last() {
+        bytes.pop();
+    }
+
+    if bytes.is_empty() {
+        return Ok(vec![]);
+    }
+
+    bytes
+        .split(|&b| b == 0)
+        .map(|string| decode_ansi_string(string, stream, ansi_codec))
+        .collect()
+}
+
+fn decode_ansi_string<T: ReadSeek>(
+    bytes: &[u8],
+    stream: &mut T,
+    ansi_codec: EncodingRef,
+) -> DeserializationResult<String> {
+    decode(bytes, DecoderTrap::Strict, ansi_codec)
+        .0
+        .map_err(|message| {
+            let as_boxed_err = Box::<dyn StdErr + Send + Sync>::from(message.to_string());
+            let wrapped_io_err = WrappedIoError::capture_hexdump(as_boxed_err, stream);
+            DeserializationError::FailedToReadToken {
+                t: format!("ansi_string {}", ansi_codec.name()),
+                token_name: "",
+                source: wrapped_io_err,
+            }
+        })
+}
+
 pub fn read_null_terminated_utf16_string<T: ReadSeek>(stream: &mut T) -> io::Result<String> {
     read_utf16_string(stream, None)
 }
@@ -6,7 +6,19 @@ use std::error::Error;
 use std::fmt::Write;
 use std::io::SeekFrom;
 
+/// Dumps the data around the current position of `cursor`, leaving the position unchanged.
 pub fn dump_stream<T: ReadSeek>(cursor: &mut T, lookbehind: i32) -> Result<String, Box<dyn Error>> {
+    let position = cursor.stream_position()?;
+    let dump = dump_stream_at_position(cursor, lookbehind);
+    cursor.seek(SeekFrom::Start(position))?;
+
+    dump
+}
+
+fn dump_stream_at_position<T: ReadSeek>(
+    cursor: &mut T,
+    lookbehind: i32,
+) -> Result<String, Box<dyn Error>> {
     let mut s = String::new();
 
     cursor.seek(SeekFrom::Current(lookbehind.into()))?;
@@ -7,7 +7,7 @@ use log::trace;
 use std::io::Write;
 
 use quick_xml::events::attributes::Attribute;
-use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::events::{BytesCData, BytesDecl, BytesEnd, BytesStart, BytesText, Event};
 use quick_xml::Writer;
 
 use crate::binxml::name::BinXmlName;
@@ -31,8 +31,9 @@ pub trait BinXmlOutput {
     ///                                                     ~~~~~~~~~~~~~~~
     fn visit_characters(&mut self, value: Cow<BinXmlValue>) -> SerializationResult<()>;
 
-    /// Unimplemented
-    fn visit_cdata_section(&mut self) -> SerializationResult<()>;
+    /// Called with the text of a CDATA section, (ex. <![CDATA[<text>]]>)
+    ///                                                       ~~~~~~
+    fn visit_cdata_section(&mut self, data: Cow<'_, str>) -> SerializationResult<()>;
 
     /// Emit the character "&" and the text.
     fn visit_entity_reference(&mut self, entity: &BinXmlName) -> SerializationResult<()>;
@@ -82,7 +83,7 @@ impl<W: Write> BinXmlOutput for XmlOutput<W> {
         let mut event_builder = BytesStart::new(element.name.as_ref().as_str());
 
         for attr in element.attributes.iter() {
-            let value_cow: Cow<'_, str> = attr.value.as_ref().as_cow_str();
+            let value_cow: Cow<'_, str> = attr.value.as_ref().as_cow_str()?;
 
             if value_cow.len() > 0 {
                 let name_as_str = attr.name.as_str();
@@ -107,17 +108,28 @@ impl<W: Write> BinXmlOutput for XmlOutput<W> {
 
     fn visit_characters(&mut self, value: Cow<BinXmlValue>) -> SerializationResult<()> {
         trace!("visit_chars");
-        let cow: Cow<str> = value.as_cow_str();
+        let cow: Cow<str> = value.as_cow_str()?;
         let event = BytesText::new(&cow);
         self.writer.write_event(Event::Text(event))?;
 
         Ok(())
     }
 
-    fn visit_cdata_section(&mut self) -> SerializationResult<()> {
-        Err(SerializationError::Unimplemented {
-            message: format!("`{}`: visit_cdata_section", file!()),
-        })
+    fn visit_cdata_section(&mut self, data: Cow<'_, str>) -> SerializationResult<()> {
+        trace!("visit_cdata_section");
+        // `]]>` would end the section, so it is split between two sections.
+        let mut rest = data.as_ref();
+        while let Some(index) = rest.find("]]>") {
+            let (section, next) = rest.split_at(index + 2);
+            self.writer
+                .write_event(Event::CData(BytesCData::new(section)))?;
+            rest = next;
+        }
+
+        self.writer
+            .write_event(Event::CData(BytesCData::new(rest)))?;
+
+        Ok(())
     }
 
     fn visit_entity_reference(&mut self, entity: &BinXmlName) -> Result<(), SerializationError> {
@@ -131,11 +143,14 @@ impl<W: Write> BinXmlOutput for XmlOutput<W> {
 
     fn visit_character_reference(
         &mut self,
-        _char_ref: Cow<'_, str>,
+        char_ref: Cow<'_, str>,
     ) -> Result<(), SerializationError> {
-        Err(SerializationError::Unimplemented {
-            message: format!("`{}`: visit_character_reference", file!()),
-        })
+        let xml_ref = "&#".to_string() + char_ref.as_ref() + ";";
+        // xml_ref is already escaped
+        let event = Event::Text(BytesText::from_escaped(&xml_ref));
+        self.writer.write_event(event)?;
+
+        Ok(())
     }
 
     fn visit_processing_instruction(&mut self, pi: &BinXmlPI) -> SerializationResult<()> {
@@ -157,3 +172,49 @@ impl<W: Write> BinXmlOutput for XmlOutput<W> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::xml::XmlAttribute;
+
+    #[test]
+    fn test_unsupported_values_are_an_error() {
+        let mut output = XmlOutput::with_writer(Vec::new(), &ParserSettings::new());
+        let element = XmlElement {
+            name: Cow::Owned(BinXmlName::from_str("Data")),
+            attributes: vec![XmlAttribute {
+                name: Cow::Owned(BinXmlName::from_str("Handle")),
+                value: Cow::Owned(BinXmlValue::EvtHandle),
+            }],
+        };
+
+        assert!(matches!(
+            output.visit_open_start_element(&element),
+            Err(SerializationError::UnsupportedValueType { .. })
+        ));
+        assert!(matches!(
+            output.visit_characters(Cow::Owned(BinXmlValue::BinXmlType(vec![]))),
+            Err(SerializationError::UnsupportedValueType { .. })
+        ));
+    }
+
+    #[test]
+    fn test_cdata_section_end_is_split() {
+        let mut output = XmlOutput::with_writer(Vec::new(), &ParserSettings::new());
+
+        for data in ["a]]>b", "]]>]]>", "]]", ""] {
+            output.visit_cdata_section(Cow::Borrowed(data)).unwrap();
+        }
+
+        assert_eq!(
+            String::from_utf8(output.into_writer()).unwrap(),
+            concat!(
+                "<![CDATA[a]]]]><![CDATA[>b]]>",
+                "<![CDATA[]]]]><![CDATA[>]]]]><![CDATA[>]]>",
+                "<![CDATA[]]]]>",
+                "<![CDATA[]]>"
+            )
+        );
+    }
+}
@@ -30,6 +30,16 @@ pub fn samples_dir() -> PathBuf {
         .unwrap()
 }
 
+/// Inputs found by the fuzz targets (see `fuzz/`), one directory per target.
+pub fn fuzz_corpus_dir() -> PathBuf {
+    PathBuf::from(file!())
+        .parent()
+        .unwrap()
+        .join("fuzz_corpus")
+        .canonicalize()
+        .unwrap()
+}
+
 pub fn regular_sample() -> PathBuf {
     samples_dir().join("security.evtx")
 }
@@ -91,3 +101,7 @@ pub fn sample_with_no_crc32() -> PathBuf {
 pub fn sample_with_invalid_flags_in_header() -> PathBuf {
     samples_dir().join("post-Security.evtx")
 }
+
+pub fn sample_with_cleared_log() -> PathBuf {
+    samples_dir().join("system.evtx")
+}
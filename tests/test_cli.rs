@@ -3,9 +3,11 @@ mod fixtures;
 use fixtures::*;
 
 use assert_cmd::prelude::*;
-use std::fs::File;
+use std::fs::{self, File, OpenOptions};
 use std::io::{Read, Write};
+use std::path::Path;
 use std::process::Command;
+use std::time::{Duration, Instant};
 use tempfile::tempdir;
 
 #[test]
@@ -70,3 +72,507 @@ fn test_it_overwrites_file_anyways_if_passed_flag() {
         "Expected output to be printed to file"
     )
 }
+
+#[test]
+fn test_it_carves_records_from_raw_image() {
+    let d = tempdir().unwrap();
+    let image_path = d.as_ref().join("image.raw");
+
+    let mut sample = vec![];
+    File::open(regular_sample())
+        .unwrap()
+        .read_to_end(&mut sample)
+        .unwrap();
+
+    // Strip the file header, and bury the first chunk in garbage.
+    let mut image = vec![0xFF; 1234];
+    image.extend_from_slice(&sample[4096..4096 + 65536]);
+    image.extend_from_slice(&[0; 1234]);
+    File::create(&image_path)
+        .unwrap()
+        .write_all(&image)
+        .unwrap();
+
+    let mut cmd = Command::cargo_bin("evtx_dump").expect("failed to find binary");
+    cmd.args(&["--carve", "-o", "jsonl", image_path.to_str().unwrap()]);
+
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 91);
+
+    // The offsets of the records in the image are shown.
+    let mut cmd = Command::cargo_bin("evtx_dump").expect("failed to find binary");
+    cmd.args(&["--carve", image_path.to_str().unwrap()]);
+
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with(&format!("Record 1 (offset {})\n", 1234 + 512)));
+}
+
+#[test]
+fn test_it_dumps_only_selected_events() {
+    let sample = regular_sample();
+
+    let mut cmd = Command::cargo_bin("evtx_dump").expect("failed to find binary");
+    cmd.args(&[
+        "--events",
+        "5-6,90-92",
+        "-o",
+        "jsonl",
+        sample.to_str().unwrap(),
+    ]);
+
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 5);
+}
+
+#[test]
+fn test_it_dumps_only_events_matching_query() {
+    let sample = regular_sample();
+
+    let mut cmd = Command::cargo_bin("evtx_dump").expect("failed to find binary");
+    cmd.args(&[
+        "--query",
+        "*[System[(EventID=1100 or EventID=4608) and Level=4]]",
+        "--events",
+        "1-100",
+        "-o",
+        "jsonl",
+        sample.to_str().unwrap(),
+    ]);
+
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.lines().count() > 0);
+    for line in stdout.lines() {
+        let event: serde_json::Value = serde_json::from_str(line).unwrap();
+        let event_id = &event["Event"]["System"]["EventID"];
+
+        assert!(*event_id == 1100 || *event_id == 4608, "{}", line);
+        assert_eq!(event["Event"]["System"]["Level"], 4);
+        assert!(event["Event"]["System"]["EventRecordID"].as_u64().unwrap() <= 100);
+    }
+}
+
+#[test]
+fn test_it_rejects_invalid_query() {
+    let sample = regular_sample();
+
+    let mut cmd = Command::cargo_bin("evtx_dump").expect("failed to find binary");
+    cmd.args(&["--query", "*[System[EventID=]]", sample.to_str().unwrap()]);
+
+    let output = cmd.output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("position 17"));
+}
+
+#[cfg(feature = "sigma")]
+#[test]
+fn test_it_prints_sigma_matches() {
+    let d = tempdir().unwrap();
+    fs::write(
+        d.as_ref().join("logon.yml"),
+        "title: Logon\nid: logon\nlogsource:\n  service: security\ndetection:\n  sel:\n    EventID: 4624\n  condition: sel\n",
+    )
+    .unwrap();
+    fs::write(
+        d.as_ref().join("count.yml"),
+        "title: Count\ndetection:\n  sel:\n    EventID: 4624\n  condition: sel | count() > 5\n",
+    )
+    .unwrap();
+
+    let sample = regular_sample();
+
+    let mut cmd = Command::cargo_bin("evtx_dump").expect("failed to find binary");
+    cmd.args(&[
+        "sigma",
+        "--rules",
+        &d.as_ref().to_string_lossy(),
+        sample.to_str().unwrap(),
+    ]);
+
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("count.yml"));
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!stdout.is_empty());
+    for line in stdout.lines() {
+        let m: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert_eq!(m["rule_id"], "logon");
+        assert_eq!(m["title"], "Logon");
+    }
+}
+
+#[test]
+fn test_it_dumps_flat_json() {
+    let sample = regular_sample();
+
+    let mut cmd = Command::cargo_bin("evtx_dump").expect("failed to find binary");
+    cmd.args(&[
+        "-o",
+        "jsonl",
+        "--json-format",
+        "ecs",
+        sample.to_str().unwrap(),
+    ]);
+
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    for line in stdout.lines() {
+        let record: serde_json::Value = serde_json::from_str(line).unwrap();
+        let record = record.as_object().unwrap();
+
+        assert!(record.contains_key("@timestamp"));
+        assert!(record.contains_key("event.code"));
+        assert!(record.values().all(|value| !value.is_object()));
+    }
+}
+
+#[test]
+fn test_it_dumps_csv_with_selected_columns() {
+    let sample = regular_sample();
+
+    let mut cmd = Command::cargo_bin("evtx_dump").expect("failed to find binary");
+    cmd.args(&[
+        "-o",
+        "csv",
+        "-c",
+        "record_id",
+        "-c",
+        "System/EventID",
+        "-c",
+        "EventData/Data[@Name='TargetUserName']",
+        "-c",
+        "EventData/Data",
+        sample.to_str().unwrap(),
+    ]);
+
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut lines = stdout.lines();
+
+    assert_eq!(
+        lines.next(),
+        Some("record_id,System/EventID,EventData/Data[@Name='TargetUserName'],EventData/Data")
+    );
+    assert_eq!(lines.next(), Some("1,4608,,"));
+    assert!(lines
+        .next()
+        .unwrap()
+        .starts_with("2,4624,SYSTEM,\"S-1-0-0, -, -, 0x0, S-1-5-18,"));
+}
+
+#[cfg(feature = "arrow")]
+#[test]
+fn test_it_dumps_parquet() {
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    let d = tempdir().unwrap();
+    let output_path = d.as_ref().join("security.parquet");
+    let sample = regular_sample();
+
+    let mut cmd = Command::cargo_bin("evtx_dump").expect("failed to find binary");
+    cmd.args(&[
+        "-o",
+        "parquet",
+        "-f",
+        output_path.to_str().unwrap(),
+        sample.to_str().unwrap(),
+    ]);
+
+    assert!(cmd.output().unwrap().status.success());
+
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&output_path).unwrap())
+        .unwrap()
+        .build()
+        .unwrap();
+    let rows: usize = reader.map(|batch| batch.unwrap().num_rows()).sum();
+
+    let mut parser = evtx::EvtxParser::from_path(&sample).unwrap();
+    assert_eq!(rows, parser.records().filter(|r| r.is_ok()).count());
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_it_adds_records_to_sqlite_database() {
+    let d = tempdir().unwrap();
+    let db_path = d.as_ref().join("records.db");
+    let sample = regular_sample();
+
+    // Dumping the same file twice replaces its records instead of duplicating them.
+    for _ in 0..2 {
+        let mut cmd = Command::cargo_bin("evtx_dump").expect("failed to find binary");
+        cmd.args(&[
+            "-o",
+            "sqlite",
+            "-f",
+            db_path.to_str().unwrap(),
+            sample.to_str().unwrap(),
+        ]);
+
+        assert!(cmd.output().unwrap().status.success());
+    }
+
+    let connection = rusqlite::Connection::open(&db_path).unwrap();
+    let (records, source_files): (usize, usize) = connection
+        .query_row(
+            "SELECT COUNT(*), COUNT(DISTINCT source_file) FROM records",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap();
+    let logons: usize = connection
+        .query_row(
+            "SELECT COUNT(*) FROM records JOIN event_data USING (source_file, record_id) \
+             WHERE event_id = 4624 AND name = 'TargetUserName' AND value = 'SYSTEM'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+
+    let mut parser = evtx::EvtxParser::from_path(&sample).unwrap();
+    assert_eq!(records, parser.records().filter(|r| r.is_ok()).count());
+    assert_eq!(source_files, 1);
+    assert!(logons > 0);
+}
+
+#[test]
+fn test_it_rejects_invalid_columns() {
+    let sample = regular_sample();
+
+    let mut cmd = Command::cargo_bin("evtx_dump").expect("failed to find binary");
+    cmd.args(&[
+        "-o",
+        "tsv",
+        "-c",
+        "System/EventID[",
+        sample.to_str().unwrap(),
+    ]);
+
+    let output = cmd.output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Failed to parse columns"));
+}
+
+#[test]
+fn test_it_sorts_records_regardless_of_threads() {
+    let sample = regular_sample();
+
+    let dump_with_threads = |threads: &str| {
+        let mut cmd = Command::cargo_bin("evtx_dump").expect("failed to find binary");
+        cmd.args(&[
+            "--sort-by",
+            "timestamp",
+            "-t",
+            threads,
+            "-o",
+            "jsonl",
+            sample.to_str().unwrap(),
+        ]);
+
+        let output = cmd.output().unwrap();
+        assert!(output.status.success());
+        output.stdout
+    };
+
+    assert_eq!(dump_with_threads("1"), dump_with_threads("4"));
+}
+
+#[test]
+fn test_it_sorts_selected_events_of_wrapped_file() {
+    let d = tempdir().unwrap();
+    let wrapped_path = d.as_ref().join("wrapped.evtx");
+
+    // Simulate a log which wrapped around, by moving the first chunk to the end of the file.
+    let sample = fs::read(regular_sample()).unwrap();
+    let mut wrapped = sample[..4096].to_vec();
+    wrapped.extend_from_slice(&sample[4096 + 65536..]);
+    wrapped.extend_from_slice(&sample[4096..4096 + 65536]);
+    fs::write(&wrapped_path, wrapped).unwrap();
+
+    let mut cmd = Command::cargo_bin("evtx_dump").expect("failed to find binary");
+    cmd.args(&[
+        "--sort-by",
+        "record-id",
+        "--events",
+        "1-100000",
+        "-o",
+        "jsonl",
+        wrapped_path.to_str().unwrap(),
+    ]);
+
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+
+    let ids: Vec<u64> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| {
+            let record: serde_json::Value = serde_json::from_str(line).unwrap();
+            record["Event"]["System"]["EventRecordID"].as_u64().unwrap()
+        })
+        .collect();
+    let mut sorted = ids.clone();
+    sorted.sort_unstable();
+
+    assert_eq!(ids.first(), Some(&1));
+    assert_eq!(ids, sorted);
+}
+
+#[test]
+fn test_it_verifies_input() {
+    let sample = regular_sample();
+
+    let mut cmd = Command::cargo_bin("evtx_dump").expect("failed to find binary");
+    cmd.args(&["verify", sample.to_str().unwrap()]);
+
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["file_header"]["checksum"]["valid"], true);
+
+    // Tamper with a record in the first chunk.
+    let d = tempdir().unwrap();
+    let tampered_path = d.as_ref().join("tampered.evtx");
+    let mut data = fs::read(&sample).unwrap();
+    data[4096 + 1000] ^= 0xff;
+    fs::write(&tampered_path, data).unwrap();
+
+    let mut cmd = Command::cargo_bin("evtx_dump").expect("failed to find binary");
+    cmd.args(&["verify", tampered_path.to_str().unwrap()]);
+
+    let output = cmd.output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["chunks"][0]["data_checksum"]["valid"], false);
+}
+
+#[test]
+fn test_it_finds_log_clearing() {
+    let sample = sample_with_cleared_log();
+
+    let mut cmd = Command::cargo_bin("evtx_dump").expect("failed to find binary");
+    cmd.args(&["analyze", sample.to_str().unwrap()]);
+
+    let output = cmd.output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+
+    let findings: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let cleared: Vec<_> = findings
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|finding| finding["kind"] == "log_cleared")
+        .map(|finding| finding["record_id"].as_u64().unwrap())
+        .collect();
+
+    assert_eq!(cleared, vec![1847, 1848]);
+}
+
+#[test]
+fn test_it_follows_input() {
+    let d = tempdir().unwrap();
+    let f = d.as_ref().join("growing.evtx");
+    let out = d.as_ref().join("out.jsonl");
+    fs::copy(samples_dir().join("new-user-security.evtx"), &f).unwrap();
+
+    let mut child = Command::cargo_bin("evtx_dump")
+        .expect("failed to find binary")
+        .args(&[
+            "--follow",
+            "-o",
+            "jsonl",
+            "-f",
+            out.to_str().unwrap(),
+            f.to_str().unwrap(),
+        ])
+        .spawn()
+        .unwrap();
+
+    assert_eq!(wait_for_lines(&out, 4), 4);
+
+    // Append a chunk to the file while it is being followed.
+    let mut sample = vec![];
+    File::open(regular_sample())
+        .unwrap()
+        .read_to_end(&mut sample)
+        .unwrap();
+    OpenOptions::new()
+        .append(true)
+        .open(&f)
+        .unwrap()
+        .write_all(&sample[4096..4096 + 65536])
+        .unwrap();
+
+    let lines = wait_for_lines(&out, 4 + 91);
+    assert!(child.try_wait().unwrap().is_none());
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    assert_eq!(lines, 4 + 91);
+}
+
+/// Waits until the file at `path` has at least `count` lines (or a timeout), returning its number of lines.
+fn wait_for_lines(path: &Path, count: usize) -> usize {
+    let deadline = Instant::now() + Duration::from_secs(30);
+
+    loop {
+        let lines = fs::read_to_string(path)
+            .map(|output| output.lines().count())
+            .unwrap_or(0);
+
+        if lines >= count || Instant::now() > deadline {
+            return lines;
+        }
+
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+#[test]
+fn test_it_dumps_records_from_stdin() {
+    let sample = regular_sample();
+
+    let mut cmd = Command::cargo_bin("evtx_dump").expect("failed to find binary");
+    cmd.args(&["-o", "jsonl", sample.to_str().unwrap()]);
+    let expected = cmd.output().unwrap();
+    assert!(expected.status.success());
+
+    let output = assert_cmd::Command::cargo_bin("evtx_dump")
+        .expect("failed to find binary")
+        .args(&["-o", "jsonl", "-"])
+        .pipe_stdin(&sample)
+        .unwrap()
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(expected.stdout).unwrap()
+    );
+}
+
+#[test]
+fn test_it_refuses_to_carve_from_stdin() {
+    let mut cmd = Command::cargo_bin("evtx_dump").expect("failed to find binary");
+    cmd.args(&["--carve", "-"]);
+
+    cmd.assert().failure().code(1);
+}
@@ -0,0 +1,41 @@
+mod fixtures;
+
+#[path = "../fuzz/src/lib.rs"]
+mod harness;
+
+use fixtures::*;
+use std::fs;
+use std::panic;
+
+/// Runs `target` on every input of the regression corpus of `target_name`.
+fn test_corpus(target_name: &str, target: fn(&[u8])) {
+    ensure_env_logger_initialized();
+    let dir = fuzz_corpus_dir().join(target_name);
+
+    let mut count = 0;
+    for entry in fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        let data = fs::read(&path).unwrap();
+
+        let result = panic::catch_unwind(|| target(&data));
+        assert!(result.is_ok(), "{} panicked", path.display());
+        count += 1;
+    }
+
+    assert!(count > 0, "{} has no inputs", dir.display());
+}
+
+#[test]
+fn test_fuzz_regressions_file() {
+    test_corpus("file", harness::parse_file);
+}
+
+#[test]
+fn test_fuzz_regressions_chunk() {
+    test_corpus("chunk", harness::parse_chunk);
+}
+
+#[test]
+fn test_fuzz_regressions_binxml() {
+    test_corpus("binxml", harness::parse_binxml);
+}
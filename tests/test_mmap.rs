@@ -0,0 +1,92 @@
+#![cfg(feature = "mmap")]
+mod fixtures;
+
+use evtx::{EvtxParser, Mmap, ParserSettings};
+use fixtures::*;
+use std::fs::{self, File};
+use std::path::Path;
+use tempfile::tempdir;
+
+fn mmap(path: impl AsRef<Path>) -> Mmap {
+    let file = File::open(path).unwrap();
+    // Samples are never modified while the tests run.
+    unsafe { Mmap::map(&file).unwrap() }
+}
+
+#[test]
+fn test_mmap_records_match_buffered_records() {
+    ensure_env_logger_initialized();
+    let sample = regular_sample();
+
+    let mut buffered = EvtxParser::from_path(&sample).unwrap();
+    let mut mapped = EvtxParser::from_mmap(mmap(&sample)).unwrap();
+
+    let expected: Vec<_> = buffered.records_json().map(|r| r.unwrap()).collect();
+    let actual: Vec<_> = mapped.records_json().map(|r| r.unwrap()).collect();
+
+    assert_eq!(actual.len(), expected.len());
+    for (actual, expected) in actual.iter().zip(&expected) {
+        assert_eq!(actual.event_record_id, expected.event_record_id);
+        assert_eq!(actual.data, expected.data);
+    }
+}
+
+#[test]
+fn test_mmap_chunks_are_borrowed() {
+    ensure_env_logger_initialized();
+    let mut parser = EvtxParser::from_mmap(mmap(regular_sample())).unwrap();
+
+    let mut chunk_count = 0;
+    for chunk in parser.chunks() {
+        let chunk = chunk.unwrap();
+        assert!(chunk.data.is_borrowed());
+        chunk_count += 1;
+    }
+
+    assert!(chunk_count > 0);
+}
+
+#[test]
+fn test_mmap_with_multiple_threads() {
+    ensure_env_logger_initialized();
+    let mut parser = EvtxParser::from_mmap(mmap(regular_sample()))
+        .unwrap()
+        .with_configuration(ParserSettings::new().num_threads(8));
+
+    assert_eq!(parser.records().filter(|r| r.is_ok()).count(), 2261);
+}
+
+#[test]
+fn test_mmap_skips_empty_chunks() {
+    ensure_env_logger_initialized();
+    let sample = sample_with_a_chunk_past_zeroes();
+
+    let mut buffered = EvtxParser::from_path(&sample).unwrap();
+    let mut mapped = EvtxParser::from_mmap(mmap(&sample)).unwrap();
+
+    assert_eq!(
+        mapped.records().filter(|r| r.is_ok()).count(),
+        buffered.records().filter(|r| r.is_ok()).count()
+    );
+}
+
+#[test]
+fn test_mmap_truncated_file() {
+    ensure_env_logger_initialized();
+    let mut data = fs::read(regular_sample()).unwrap();
+    // Keep the file header, the first chunk and half of the second chunk.
+    data.truncate(0x1000 + 0x10000 + 0x8000);
+
+    let d = tempdir().unwrap();
+    let path = d.path().join("truncated.evtx");
+    fs::write(&path, &data).unwrap();
+
+    let mut buffered = EvtxParser::from_buffer(data).unwrap();
+    let mut mapped = EvtxParser::from_mmap(mmap(&path)).unwrap();
+
+    let expected: Vec<_> = buffered.records().map(|r| r.is_ok()).collect();
+    let actual: Vec<_> = mapped.records().map(|r| r.is_ok()).collect();
+
+    assert_eq!(actual, expected);
+    assert!(!actual.is_empty() && actual.len() < 2261);
+}